@@ -0,0 +1,668 @@
+//! Message Batches API emulation for local models.
+//!
+//! Anthropic's `/v1/messages/batches` has no local equivalent, so the proxy
+//! keeps batches for local models itself. Each batch lives in
+//! `<batches.dir>/<id>/`:
+//! - `batch.json`: the `message_batch` object plus the headers to send
+//! - `requests.jsonl`: the submitted requests, one per line
+//! - `results.jsonl`: one result line per finished request, appended as each
+//!   completes
+//!
+//! Request counts are rebuilt from `results.jsonl` on load, so a restarted
+//! proxy resumes unfinished batches where they stopped. Execution lives in
+//! `server` (it reuses the `/v1/messages` forwarding path); this module owns
+//! storage, batch state and the per-model concurrency limits. File I/O here
+//! is blocking; `server` calls the methods that touch disk from
+//! `spawn_blocking`.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Semaphore;
+
+/// Client headers stored with a batch and sent with each of its requests.
+/// Credentials are deliberately not persisted.
+pub const FORWARDED_HEADERS: &[&str] = &["anthropic-version", "anthropic-beta"];
+
+/// Most requests accepted in one batch, as on Anthropic.
+pub const MAX_REQUESTS: usize = 100_000;
+
+/// Largest batch creation body accepted, as on Anthropic.
+pub const MAX_BATCH_BYTES: usize = 256 * 1024 * 1024;
+
+/// Largest response body kept as one request's result.
+pub const MAX_RESULT_BYTES: usize = 10 * 1024 * 1024;
+
+/// `expires_at` is reported as on Anthropic; local batches run to completion.
+const EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessingStatus {
+    InProgress,
+    Canceling,
+    Ended,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestCounts {
+    pub processing: u64,
+    pub succeeded: u64,
+    pub errored: u64,
+    pub canceled: u64,
+    pub expired: u64,
+}
+
+/// A `message_batch` object in Anthropic's format.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageBatch {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub processing_status: ProcessingStatus,
+    pub request_counts: RequestCounts,
+    pub ended_at: Option<String>,
+    pub created_at: String,
+    pub expires_at: String,
+    pub archived_at: Option<String>,
+    pub cancel_initiated_at: Option<String>,
+    pub results_url: Option<String>,
+}
+
+/// One entry of a batch creation request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchRequest {
+    pub custom_id: String,
+    pub params: Value,
+}
+
+/// Outcome of one batch request: the `result` of a results line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BatchResult {
+    Succeeded {
+        message: Value,
+    },
+    /// `error` is the full Anthropic error body (`{"type": "error", ...}`).
+    Errored {
+        error: Value,
+    },
+    Canceled,
+    Expired,
+}
+
+impl BatchResult {
+    /// An `errored` result that did not come from an upstream error body.
+    pub fn error(kind: &str, message: impl Into<String>) -> Self {
+        BatchResult::Errored {
+            error: error_body(kind, message),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ResultLine {
+    custom_id: String,
+    result: BatchResult,
+}
+
+/// `batch.json`: the API object plus what execution needs.
+#[derive(Serialize, Deserialize)]
+struct StoredBatch {
+    #[serde(flatten)]
+    batch: MessageBatch,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+}
+
+/// All local batches, on disk and in memory.
+pub struct BatchStore {
+    dir: PathBuf,
+    max_concurrent_per_target: usize,
+    batches: Mutex<HashMap<String, StoredBatch>>,
+    /// Per (local model, endpoint count); a changed count gets a fresh one.
+    limits: Mutex<HashMap<(String, usize), Arc<Semaphore>>>,
+}
+
+impl BatchStore {
+    /// Load every batch under `dir` (which need not exist yet). Batches that
+    /// fail to load are logged and skipped.
+    pub fn open(dir: &str, max_concurrent_per_target: usize) -> std::io::Result<Self> {
+        let store = Self {
+            dir: PathBuf::from(dir),
+            max_concurrent_per_target,
+            batches: Mutex::new(HashMap::new()),
+            limits: Mutex::new(HashMap::new()),
+        };
+        let entries = match std::fs::read_dir(&store.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(store),
+            Err(e) => return Err(e),
+        };
+        let mut batches = store.batches.lock().unwrap();
+        for entry in entries {
+            let id = entry?.file_name().to_string_lossy().into_owned();
+            match store.load(&id) {
+                Ok(stored) => {
+                    batches.insert(id, stored);
+                }
+                Err(e) => tracing::warn!(batch_id = %id, error = %e, "Skipping unreadable batch"),
+            }
+        }
+        drop(batches);
+        Ok(store)
+    }
+
+    fn load(&self, id: &str) -> anyhow::Result<StoredBatch> {
+        let mut stored: StoredBatch =
+            serde_json::from_slice(&std::fs::read(self.path(id, "batch.json"))?)?;
+        let total = read_lines(&self.path(id, "requests.jsonl"))?.len() as u64;
+        let results = self.path(id, "results.jsonl");
+        if drop_torn_line(&results)? {
+            tracing::warn!(batch_id = %id, "Dropped a partially written batch result");
+        }
+        let mut counts = RequestCounts::default();
+        for line in read_results(&results)? {
+            counts.add(&line.result);
+        }
+        counts.processing = total.saturating_sub(counts.finished());
+        stored.batch.request_counts = counts;
+        Ok(stored)
+    }
+
+    /// Persist a new batch and return it, `in_progress`.
+    pub fn create(
+        &self,
+        requests: &[BatchRequest],
+        headers: &HeaderMap,
+    ) -> std::io::Result<MessageBatch> {
+        let id = format!("msgbatch_{}", uuid::Uuid::new_v4().simple());
+        let now = SystemTime::now();
+        let batch = MessageBatch {
+            id: id.clone(),
+            kind: "message_batch".to_string(),
+            processing_status: ProcessingStatus::InProgress,
+            request_counts: RequestCounts {
+                processing: requests.len() as u64,
+                ..Default::default()
+            },
+            ended_at: None,
+            created_at: rfc3339(now),
+            expires_at: rfc3339(now + EXPIRY),
+            archived_at: None,
+            cancel_initiated_at: None,
+            results_url: None,
+        };
+        let headers = FORWARDED_HEADERS
+            .iter()
+            .filter_map(|name| {
+                let value = headers.get(*name)?.to_str().ok()?;
+                Some((name.to_string(), value.to_string()))
+            })
+            .collect();
+        let stored = StoredBatch { batch, headers };
+
+        std::fs::create_dir_all(self.dir.join(&id))?;
+        let mut lines = Vec::new();
+        for request in requests {
+            serde_json::to_writer(&mut lines, request)?;
+            lines.push(b'\n');
+        }
+        std::fs::write(self.path(&id, "requests.jsonl"), lines)?;
+        self.save(&stored)?;
+
+        let batch = stored.batch.clone();
+        self.batches.lock().unwrap().insert(id, stored);
+        Ok(batch)
+    }
+
+    pub fn get(&self, id: &str) -> Option<MessageBatch> {
+        let batches = self.batches.lock().unwrap();
+        batches.get(id).map(|s| s.batch.clone())
+    }
+
+    /// Batches newest first, paginated like Anthropic's list endpoint:
+    /// `after_id` pages towards older batches, `before_id` towards newer ones.
+    /// Returns the page and whether more batches lie beyond it.
+    pub fn list(
+        &self,
+        limit: usize,
+        before_id: Option<&str>,
+        after_id: Option<&str>,
+    ) -> (Vec<MessageBatch>, bool) {
+        let mut all: Vec<MessageBatch> = {
+            let batches = self.batches.lock().unwrap();
+            batches.values().map(|s| s.batch.clone()).collect()
+        };
+        all.sort_by(|a, b| (&b.created_at, &b.id).cmp(&(&a.created_at, &a.id)));
+        let position = |id: &str| all.iter().position(|b| b.id == id);
+
+        if let Some(before) = before_id {
+            let end = position(before).unwrap_or(0);
+            let start = end.saturating_sub(limit);
+            return (all[start..end].to_vec(), start > 0);
+        }
+        let start = after_id.and_then(position).map_or(0, |i| i + 1);
+        let end = (start + limit).min(all.len());
+        (all[start..end].to_vec(), end < all.len())
+    }
+
+    /// Start canceling an `in_progress` batch; other batches are returned
+    /// unchanged. `None` for unknown IDs.
+    pub fn cancel(&self, id: &str) -> std::io::Result<Option<MessageBatch>> {
+        let mut batches = self.batches.lock().unwrap();
+        let Some(stored) = batches.get_mut(id) else {
+            return Ok(None);
+        };
+        if stored.batch.processing_status == ProcessingStatus::InProgress {
+            stored.batch.processing_status = ProcessingStatus::Canceling;
+            stored.batch.cancel_initiated_at = Some(rfc3339(SystemTime::now()));
+            self.save(stored)?;
+        }
+        Ok(Some(stored.batch.clone()))
+    }
+
+    pub fn is_canceling(&self, id: &str) -> bool {
+        let batches = self.batches.lock().unwrap();
+        batches
+            .get(id)
+            .is_some_and(|s| s.batch.processing_status == ProcessingStatus::Canceling)
+    }
+
+    /// Remove an ended batch and its files. Returns `Err` with the batch's
+    /// status when it is still processing, `Ok(false)` for unknown IDs.
+    pub fn delete(&self, id: &str) -> Result<bool, ProcessingStatus> {
+        let mut batches = self.batches.lock().unwrap();
+        match batches.get(id) {
+            None => return Ok(false),
+            Some(s) if s.batch.processing_status != ProcessingStatus::Ended => {
+                return Err(s.batch.processing_status)
+            }
+            Some(_) => {}
+        }
+        batches.remove(id);
+        if let Err(e) = std::fs::remove_dir_all(self.dir.join(id)) {
+            tracing::warn!(batch_id = %id, error = %e, "Failed to remove batch files");
+        }
+        Ok(true)
+    }
+
+    /// IDs of batches that still have requests to run (or to cancel).
+    pub fn unfinished(&self) -> Vec<String> {
+        let batches = self.batches.lock().unwrap();
+        batches
+            .values()
+            .filter(|s| s.batch.processing_status != ProcessingStatus::Ended)
+            .map(|s| s.batch.id.clone())
+            .collect()
+    }
+
+    /// Requests of a batch that have no result yet.
+    pub fn pending(&self, id: &str) -> anyhow::Result<Vec<BatchRequest>> {
+        let _guard = self.batches.lock().unwrap();
+        let done: HashSet<String> = read_results(&self.path(id, "results.jsonl"))?
+            .into_iter()
+            .map(|line| line.custom_id)
+            .collect();
+        let mut pending = Vec::new();
+        for line in read_lines(&self.path(id, "requests.jsonl"))? {
+            let request: BatchRequest = serde_json::from_str(&line)?;
+            if !done.contains(&request.custom_id) {
+                pending.push(request);
+            }
+        }
+        Ok(pending)
+    }
+
+    /// Headers stored with the batch, to send with each request.
+    pub fn headers(&self, id: &str) -> HeaderMap {
+        let batches = self.batches.lock().unwrap();
+        let mut out = HeaderMap::new();
+        for (name, value) in batches.get(id).into_iter().flat_map(|s| &s.headers) {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                out.insert(name, value);
+            }
+        }
+        out
+    }
+
+    /// Append a request's result and update the batch counts.
+    pub fn record(&self, id: &str, custom_id: &str, result: BatchResult) -> std::io::Result<()> {
+        let mut batches = self.batches.lock().unwrap();
+        let line = ResultLine {
+            custom_id: custom_id.to_string(),
+            result,
+        };
+        let mut bytes = serde_json::to_vec(&line)?;
+        bytes.push(b'\n');
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path(id, "results.jsonl"))?
+            .write_all(&bytes)?;
+
+        if let Some(stored) = batches.get_mut(id) {
+            let counts = &mut stored.batch.request_counts;
+            counts.processing = counts.processing.saturating_sub(1);
+            counts.add(&line.result);
+        }
+        Ok(())
+    }
+
+    /// Mark a batch `ended` once every request has a result.
+    pub fn finish(&self, id: &str) -> std::io::Result<()> {
+        let mut batches = self.batches.lock().unwrap();
+        let Some(stored) = batches.get_mut(id) else {
+            return Ok(());
+        };
+        stored.batch.processing_status = ProcessingStatus::Ended;
+        stored.batch.ended_at = Some(rfc3339(SystemTime::now()));
+        stored.batch.results_url = Some(format!("/v1/messages/batches/{id}/results"));
+        self.save(stored)
+    }
+
+    /// Path of a batch's `results.jsonl`, which is missing until the first
+    /// result is recorded.
+    pub fn results_path(&self, id: &str) -> PathBuf {
+        self.path(id, "results.jsonl")
+    }
+
+    /// Batch requests run at once across all batches, per endpoint.
+    pub fn max_concurrent_per_target(&self) -> usize {
+        self.max_concurrent_per_target
+    }
+
+    /// Concurrency limit for batch requests to local model `model_id`:
+    /// `max_concurrent_per_target` for each of its `endpoints`.
+    pub fn limit(&self, model_id: &str, endpoints: usize) -> Arc<Semaphore> {
+        let mut limits = self.limits.lock().unwrap();
+        limits
+            .entry((model_id.to_string(), endpoints))
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max_concurrent_per_target * endpoints)))
+            .clone()
+    }
+
+    fn path(&self, id: &str, file: &str) -> PathBuf {
+        self.dir.join(id).join(file)
+    }
+
+    /// Write via a temp file + rename so a crash never leaves a torn file.
+    fn save(&self, stored: &StoredBatch) -> std::io::Result<()> {
+        let path = self.path(&stored.batch.id, "batch.json");
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(stored)?)?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+impl RequestCounts {
+    fn add(&mut self, result: &BatchResult) {
+        match result {
+            BatchResult::Succeeded { .. } => self.succeeded += 1,
+            BatchResult::Errored { .. } => self.errored += 1,
+            BatchResult::Canceled => self.canceled += 1,
+            BatchResult::Expired => self.expired += 1,
+        }
+    }
+
+    fn finished(&self) -> u64 {
+        self.succeeded + self.errored + self.canceled + self.expired
+    }
+}
+
+/// Non-empty lines of a file; a missing file has none.
+fn read_lines(path: &std::path::Path) -> std::io::Result<Vec<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => Ok(text
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(str::to_string)
+            .collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// The results in `results.jsonl`, skipping lines that don't parse; their
+/// requests count as pending and run again.
+fn read_results(path: &std::path::Path) -> std::io::Result<Vec<ResultLine>> {
+    Ok(read_lines(path)?
+        .iter()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Truncate `path` after its last newline, dropping a line cut short by a
+/// crash so the next append starts on a line of its own. Returns whether
+/// anything was dropped.
+fn drop_torn_line(path: &std::path::Path) -> std::io::Result<bool> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    if bytes.last().is_none_or(|&b| b == b'\n') {
+        return Ok(false);
+    }
+    let keep = bytes.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .set_len(keep as u64)?;
+    Ok(true)
+}
+
+/// Anthropic error body: `{"type": "error", "error": {"type", "message"}}`.
+fn error_body(kind: &str, message: impl Into<String>) -> Value {
+    serde_json::json!({
+        "type": "error",
+        "error": {"type": kind, "message": message.into()},
+    })
+}
+
+/// An Anthropic-style error response for the batches endpoints.
+pub fn error_response(status: StatusCode, kind: &str, message: impl Into<String>) -> Response {
+    (status, axum::Json(error_body(kind, message))).into_response()
+}
+
+/// Format a time as RFC 3339 UTC with second precision.
+pub fn rfc3339(t: SystemTime) -> String {
+    let secs = t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_dir() -> String {
+        std::env::temp_dir()
+            .join(format!("cc-proxy-batches-{}", uuid::Uuid::new_v4()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn requests(n: usize) -> Vec<BatchRequest> {
+        (0..n)
+            .map(|i| BatchRequest {
+                custom_id: format!("req-{i}"),
+                params: json!({"model": "m", "max_tokens": 8, "messages": []}),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn formats_rfc3339() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let t = UNIX_EPOCH + Duration::from_secs(1_709_251_199);
+        assert_eq!(rfc3339(t), "2024-02-29T23:59:59Z");
+    }
+
+    #[test]
+    fn reopened_store_resumes_pending_requests() {
+        let dir = temp_dir();
+        let mut headers = HeaderMap::new();
+        headers.insert("anthropic-version", "2023-06-01".parse().unwrap());
+        headers.insert("x-api-key", "sk-secret".parse().unwrap());
+
+        let store = BatchStore::open(&dir, 2).unwrap();
+        let batch = store.create(&requests(3), &headers).unwrap();
+        let message = json!({"type": "message", "content": []});
+        store
+            .record(
+                &batch.id,
+                "req-1",
+                BatchResult::Succeeded {
+                    message: message.clone(),
+                },
+            )
+            .unwrap();
+        assert_eq!(store.get(&batch.id).unwrap().request_counts.succeeded, 1);
+        drop(store);
+
+        let store = BatchStore::open(&dir, 2).unwrap();
+        assert_eq!(store.unfinished(), vec![batch.id.clone()]);
+        let reloaded = store.get(&batch.id).unwrap();
+        assert_eq!(reloaded.request_counts.processing, 2);
+        assert_eq!(reloaded.request_counts.succeeded, 1);
+        let pending: Vec<String> = store
+            .pending(&batch.id)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.custom_id)
+            .collect();
+        assert_eq!(pending, ["req-0", "req-2"]);
+        // Only the non-credential headers were kept
+        let headers = store.headers(&batch.id);
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers["anthropic-version"], "2023-06-01");
+
+        store
+            .record(&batch.id, "req-0", BatchResult::error("api_error", "boom"))
+            .unwrap();
+        store.cancel(&batch.id).unwrap();
+        assert!(store.is_canceling(&batch.id));
+        store
+            .record(&batch.id, "req-2", BatchResult::Canceled)
+            .unwrap();
+        store.finish(&batch.id).unwrap();
+
+        let ended = store.get(&batch.id).unwrap();
+        assert_eq!(ended.processing_status, ProcessingStatus::Ended);
+        assert!(ended.cancel_initiated_at.is_some());
+        let counts = &ended.request_counts;
+        assert_eq!(
+            (
+                counts.processing,
+                counts.succeeded,
+                counts.errored,
+                counts.canceled
+            ),
+            (0, 1, 1, 1)
+        );
+        let results = std::fs::read_to_string(store.results_path(&batch.id)).unwrap();
+        let first: Value = serde_json::from_str(results.lines().next().unwrap()).unwrap();
+        assert_eq!(
+            first,
+            json!({"custom_id": "req-1", "result": {"type": "succeeded", "message": message}})
+        );
+        assert_eq!(store.delete(&batch.id), Ok(true));
+        assert!(BatchStore::open(&dir, 2).unwrap().get(&batch.id).is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn truncated_result_line_is_dropped_on_reopen() {
+        let dir = temp_dir();
+        let store = BatchStore::open(&dir, 2).unwrap();
+        let batch = store.create(&requests(2), &HeaderMap::new()).unwrap();
+        store
+            .record(&batch.id, "req-0", BatchResult::Canceled)
+            .unwrap();
+        drop(store);
+        // A crash midway through appending req-1's result
+        let results = std::path::Path::new(&dir)
+            .join(&batch.id)
+            .join("results.jsonl");
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&results)
+            .unwrap();
+        file.write_all(br#"{"custom_id":"req-1","result":{"ty"#)
+            .unwrap();
+        drop(file);
+
+        let store = BatchStore::open(&dir, 2).unwrap();
+        let counts = store.get(&batch.id).unwrap().request_counts;
+        assert_eq!((counts.processing, counts.canceled), (1, 1));
+        let pending: Vec<String> = store
+            .pending(&batch.id)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.custom_id)
+            .collect();
+        assert_eq!(pending, ["req-1"]);
+
+        // The rerun's result lands on a line of its own
+        store
+            .record(&batch.id, "req-1", BatchResult::Canceled)
+            .unwrap();
+        let results = std::fs::read_to_string(store.results_path(&batch.id)).unwrap();
+        assert_eq!(results.lines().count(), 2);
+        assert!(store.pending(&batch.id).unwrap().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn list_pages_newest_first() {
+        let dir = temp_dir();
+        let store = BatchStore::open(&dir, 1).unwrap();
+        let ids: Vec<String> = (0..3)
+            .map(|_| store.create(&requests(1), &HeaderMap::new()).unwrap().id)
+            .collect();
+        // Same-second creations order by ID; sort the expectation the same way.
+        let mut newest_first: Vec<MessageBatch> =
+            ids.iter().map(|id| store.get(id).unwrap()).collect();
+        newest_first.sort_by(|a, b| (&b.created_at, &b.id).cmp(&(&a.created_at, &a.id)));
+        let order: Vec<&str> = newest_first.iter().map(|b| b.id.as_str()).collect();
+
+        let page_ids = |page: Vec<MessageBatch>| page.into_iter().map(|b| b.id).collect::<Vec<_>>();
+        let (page, more) = store.list(2, None, None);
+        assert_eq!(page_ids(page), order[..2]);
+        assert!(more);
+        let (page, more) = store.list(2, None, Some(order[1]));
+        assert_eq!(page_ids(page), order[2..]);
+        assert!(!more);
+        let (page, more) = store.list(1, Some(order[2]), None);
+        assert_eq!(page_ids(page), order[1..2]);
+        assert!(more);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -5,7 +5,13 @@ use figment::providers::{Env, Format, Toml};
 use figment::Figment;
 use serde::Deserialize;
 
-use crate::models::ModelDef;
+use crate::models::{
+    AliasRule, FallbackHop, LoadBalanceStrategy, ModelAuth, ModelDef, ALIAS_ANTHROPIC,
+    RESERVED_PARAMS,
+};
+use crate::proxy::compare_policy::ModelGlobs;
+use crate::routes::{RouteRule, ROUTE_ANTHROPIC};
+use crate::targets::HealthProbe;
 
 /// Top-level proxy configuration.
 #[derive(Debug, Clone, Deserialize)]
@@ -30,6 +36,35 @@ pub struct ProxyConfig {
     /// Locally-served model definitions from `[[models]]` in TOML.
     #[serde(default)]
     pub models: Vec<ModelDef>,
+
+    /// Model aliasing rules from `[[aliases]]` in TOML, checked in order.
+    #[serde(default)]
+    pub aliases: Vec<AliasRule>,
+
+    /// Content-aware routing rules from `[[routes]]`, checked in order
+    /// before model-based routing.
+    #[serde(default)]
+    pub routes: Vec<RouteRule>,
+
+    /// Background target health checks.
+    #[serde(default)]
+    pub health_check: HealthCheckConfig,
+
+    /// Per-target circuit breakers.
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+
+    /// Retries of 429/529 upstream responses.
+    #[serde(default)]
+    pub retry: RetryConfig,
+
+    /// Message Batches emulation for local models.
+    #[serde(default)]
+    pub batches: BatchesConfig,
+
+    /// Compare mode: where compare results are stored.
+    #[serde(default)]
+    pub compare: CompareConfig,
 }
 
 /// Server listen configuration.
@@ -37,6 +72,274 @@ pub struct ProxyConfig {
 pub struct ServerConfig {
     #[serde(default = "default_listen_address")]
     pub listen_address: String,
+
+    /// Poll the config file every N seconds and hot-reload it when its
+    /// modification time changes. Disabled when unset; SIGHUP and
+    /// `POST /api/config/reload` always work.
+    #[serde(default)]
+    pub config_watch_secs: Option<u64>,
+
+    /// JSON file that persists models added, changed or removed through
+    /// `/api/models`. Loaded at startup and layered over `[[models]]` on every
+    /// reload. Runtime changes are in-memory only when unset.
+    #[serde(default)]
+    pub models_state_file: Option<String>,
+}
+
+/// Active health checking of target URLs (see `targets`).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct HealthCheckConfig {
+    /// Probe every target every N seconds. Disabled when unset.
+    #[serde(default)]
+    pub interval_secs: Option<u64>,
+
+    /// Per-probe timeout.
+    #[serde(default = "default_health_timeout")]
+    pub timeout_secs: u64,
+
+    /// `health` (GET /health) or `messages` (one-token /v1/messages call).
+    #[serde(default)]
+    pub probe: HealthProbe,
+
+    /// Consecutive failed probes before a target is marked down.
+    #[serde(default = "default_unhealthy_threshold")]
+    pub unhealthy_threshold: u32,
+
+    /// Successful probes slower than this mark the target degraded.
+    #[serde(default = "default_degraded_latency_ms")]
+    pub degraded_latency_ms: u64,
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: None,
+            timeout_secs: default_health_timeout(),
+            probe: HealthProbe::default(),
+            unhealthy_threshold: default_unhealthy_threshold(),
+            degraded_latency_ms: default_degraded_latency_ms(),
+        }
+    }
+}
+
+/// Circuit breaker settings, shared by every target URL (see `targets`).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CircuitBreakerConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Consecutive failures (connect error, timeout, 5xx) that open the circuit.
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+
+    /// Also open when the failure rate over the last `window_size` requests
+    /// reaches this fraction (0.0–1.0), once `min_requests` have been seen.
+    #[serde(default)]
+    pub error_rate_threshold: Option<f64>,
+
+    #[serde(default = "default_breaker_window")]
+    pub window_size: usize,
+
+    #[serde(default = "default_breaker_window")]
+    pub min_requests: usize,
+
+    /// How long the circuit stays open before a trial request is let through.
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            failure_threshold: default_failure_threshold(),
+            error_rate_threshold: None,
+            window_size: default_breaker_window(),
+            min_requests: default_breaker_window(),
+            cooldown_secs: default_cooldown_secs(),
+        }
+    }
+}
+
+/// Retry policy for rate-limited (429) and overloaded (529) upstream
+/// responses, on both the target and passthrough paths.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RetryConfig {
+    /// Retries after the first attempt. 0 disables retrying.
+    #[serde(default)]
+    pub max_retries: u32,
+
+    /// First backoff delay; doubles on each retry.
+    #[serde(default = "default_retry_base_delay_ms")]
+    pub base_delay_ms: u64,
+
+    /// Upper bound for a computed backoff delay (`retry-after` is honored as sent).
+    #[serde(default = "default_retry_max_delay_ms")]
+    pub max_delay_ms: u64,
+
+    /// Total time a request may spend across attempts and backoff before the
+    /// last upstream response is returned as-is.
+    #[serde(default = "default_retry_budget_secs")]
+    pub budget_secs: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            base_delay_ms: default_retry_base_delay_ms(),
+            max_delay_ms: default_retry_max_delay_ms(),
+            budget_secs: default_retry_budget_secs(),
+        }
+    }
+}
+
+/// Message Batches emulation: where batches are stored and how hard they
+/// may drive each target.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchesConfig {
+    /// Directory holding one subdirectory per batch. Created on first use.
+    #[serde(default = "default_batches_dir")]
+    pub dir: String,
+
+    /// Batch requests in flight per endpoint of a local model, across all batches.
+    #[serde(default = "default_batches_max_concurrent")]
+    pub max_concurrent_per_target: usize,
+}
+
+impl Default for BatchesConfig {
+    fn default() -> Self {
+        Self {
+            dir: default_batches_dir(),
+            max_concurrent_per_target: default_batches_max_concurrent(),
+        }
+    }
+}
+
+/// Compare mode settings. The policy fields choose which Anthropic-routed
+/// requests are mirrored (see `proxy::compare_policy`) and take effect on
+/// reload.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompareConfig {
+    /// JSONL file that every completed compare pair is appended to, served
+    /// by `GET /api/compare`.
+    #[serde(default = "default_compare_store_file")]
+    pub store_file: String,
+
+    /// Most recent pairs per compare model behind `GET /api/compare/stats`.
+    #[serde(default = "default_compare_stats_window")]
+    pub stats_window: usize,
+
+    /// Percentage (0–100) of eligible requests to mirror.
+    #[serde(default = "default_sample_percent")]
+    pub sample_percent: f64,
+
+    /// Globs of requested model IDs to mirror; all models when empty.
+    #[serde(default)]
+    pub include_models: ModelGlobs,
+
+    /// Globs of requested model IDs never to mirror.
+    #[serde(default)]
+    pub exclude_models: ModelGlobs,
+
+    /// Skip Claude Code quota checks (one user message, no tools,
+    /// `max_tokens` <= 32).
+    #[serde(default = "default_true")]
+    pub skip_quota_checks: bool,
+
+    /// Skip requests whose body is larger than this many bytes.
+    #[serde(default)]
+    pub max_request_bytes: Option<usize>,
+
+    /// Local models every mirrored request is sent to. When empty, requests
+    /// go to the default target (`--target-url`) as the `--model` model.
+    #[serde(default)]
+    pub candidates: Vec<CompareCandidate>,
+
+    /// Compare requests per model that may wait, in arrival order, for a
+    /// concurrency slot; more are dropped. 0 drops whenever at capacity.
+    #[serde(default = "default_compare_queue_depth")]
+    pub queue_depth: usize,
+
+    /// Longest a queued compare request waits for a slot before it is dropped.
+    #[serde(default = "default_compare_queue_max_wait_secs")]
+    pub queue_max_wait_secs: u64,
+
+    /// Optional LLM-as-judge scoring of compare pairs.
+    #[serde(default)]
+    pub judge: Option<JudgeConfig>,
+}
+
+/// LLM-as-judge settings: the judge model scores each sampled pair's
+/// target response against the primary response under `rubric`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct JudgeConfig {
+    /// A `[[models]]` id, judged by that local model, or any other model id,
+    /// sent to Anthropic (`passthrough.url`) with `auth`.
+    pub model: String,
+
+    /// Instructions for grading the target response against the primary
+    /// response. The judge is always asked to answer with a JSON score and
+    /// rationale.
+    #[serde(default = "default_judge_rubric")]
+    pub rubric: String,
+
+    /// Percentage (0–100) of compare pairs to judge.
+    #[serde(default = "default_sample_percent")]
+    pub sample_percent: f64,
+
+    /// Judge requests in flight at once; more wait in the compare queue.
+    #[serde(default = "default_judge_max_concurrent")]
+    pub max_concurrent: usize,
+
+    #[serde(default = "default_judge_timeout_secs")]
+    pub timeout_secs: u64,
+
+    #[serde(default = "default_judge_max_tokens")]
+    pub max_tokens: u64,
+
+    /// Most characters of the client's conversation shown to the judge;
+    /// longer conversations keep their end.
+    #[serde(default = "default_judge_max_context_chars")]
+    pub max_context_chars: usize,
+
+    /// Credentials for an Anthropic judge. Local judges use their model's.
+    #[serde(default)]
+    pub auth: ModelAuth,
+}
+
+/// One compare candidate: a `[[models]]` entry, whose endpoints, protocol,
+/// credentials, params and capabilities apply.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CompareCandidate {
+    pub model: String,
+
+    /// Per-request timeout. Defaults to `target.timeout_secs`.
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+
+    /// Compare requests in flight to this candidate; more wait in the
+    /// compare queue. Defaults to `target.max_concurrent`.
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
+}
+
+impl Default for CompareConfig {
+    fn default() -> Self {
+        Self {
+            store_file: default_compare_store_file(),
+            stats_window: default_compare_stats_window(),
+            sample_percent: default_sample_percent(),
+            include_models: ModelGlobs::default(),
+            exclude_models: ModelGlobs::default(),
+            skip_quota_checks: true,
+            max_request_bytes: None,
+            candidates: Vec::new(),
+            queue_depth: default_compare_queue_depth(),
+            queue_max_wait_secs: default_compare_queue_max_wait_secs(),
+            judge: None,
+        }
+    }
 }
 
 /// Passthrough upstream configuration (used only in `compare` and `anthropic-only` modes).
@@ -100,6 +403,94 @@ fn default_max_concurrent() -> usize {
     50
 }
 
+fn default_health_timeout() -> u64 {
+    5
+}
+
+fn default_unhealthy_threshold() -> u32 {
+    3
+}
+
+fn default_degraded_latency_ms() -> u64 {
+    2000
+}
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+fn default_breaker_window() -> usize {
+    20
+}
+
+fn default_cooldown_secs() -> u64 {
+    30
+}
+
+fn default_retry_base_delay_ms() -> u64 {
+    500
+}
+
+fn default_retry_max_delay_ms() -> u64 {
+    8000
+}
+
+fn default_retry_budget_secs() -> u64 {
+    30
+}
+
+fn default_batches_dir() -> String {
+    "cc-proxy-batches".to_string()
+}
+
+fn default_batches_max_concurrent() -> usize {
+    4
+}
+
+fn default_compare_store_file() -> String {
+    "cc-proxy-compare.jsonl".to_string()
+}
+
+fn default_compare_stats_window() -> usize {
+    1000
+}
+
+fn default_sample_percent() -> f64 {
+    100.0
+}
+
+fn default_compare_queue_depth() -> usize {
+    100
+}
+
+fn default_compare_queue_max_wait_secs() -> u64 {
+    30
+}
+
+fn default_judge_rubric() -> String {
+    "Score the candidate response from 0 to 10 against the reference response to \
+     the same conversation: 10 if it is at least as correct, complete and useful \
+     (including its tool calls), 0 if it is wrong or unusable. Differences in \
+     wording alone do not lower the score."
+        .to_string()
+}
+
+fn default_judge_max_concurrent() -> usize {
+    4
+}
+
+fn default_judge_timeout_secs() -> u64 {
+    120
+}
+
+fn default_judge_max_tokens() -> u64 {
+    1024
+}
+
+fn default_judge_max_context_chars() -> usize {
+    20_000
+}
+
 impl ProxyConfig {
     /// Load configuration from TOML file and environment variables.
     ///
@@ -115,4 +506,215 @@ impl ProxyConfig {
 
         Ok(config)
     }
+
+    /// Check the configuration for errors that would only surface at request
+    /// time. Returns every problem found, not just the first.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if !matches!(
+            self.default_mode.as_str(),
+            "target" | "compare" | "anthropic-only"
+        ) {
+            errors.push(format!(
+                "default_mode: invalid mode '{}', expected: target, compare, or anthropic-only",
+                self.default_mode
+            ));
+        }
+        if let Err(e) = validate_url(&self.passthrough.url) {
+            errors.push(format!("passthrough.url: {e}"));
+        }
+        if let Some(ref url) = self.target.url {
+            if let Err(e) = validate_url(url) {
+                errors.push(format!("target.url: {e}"));
+            }
+        }
+        if let Some(rate) = self.circuit_breaker.error_rate_threshold {
+            if !(rate > 0.0 && rate <= 1.0) {
+                errors.push(format!(
+                    "circuit_breaker.error_rate_threshold: {rate} is not in (0, 1]"
+                ));
+            }
+        }
+        if self.batches.max_concurrent_per_target == 0 {
+            errors.push("batches.max_concurrent_per_target: must be at least 1".to_string());
+        }
+        if self.compare.stats_window == 0 {
+            errors.push("compare.stats_window: must be at least 1".to_string());
+        }
+        if !(0.0..=100.0).contains(&self.compare.sample_percent) {
+            errors.push("compare.sample_percent: must be between 0 and 100".to_string());
+        }
+        if self.compare.queue_max_wait_secs == 0 {
+            errors.push("compare.queue_max_wait_secs: must be at least 1".to_string());
+        }
+        if let Some(judge) = &self.compare.judge {
+            if !(0.0..=100.0).contains(&judge.sample_percent) {
+                errors.push("compare.judge.sample_percent: must be between 0 and 100".to_string());
+            }
+            if judge.max_concurrent == 0 {
+                errors.push("compare.judge.max_concurrent: must be at least 1".to_string());
+            }
+            if judge.rubric.trim().is_empty() {
+                errors.push("compare.judge.rubric: must not be empty".to_string());
+            }
+            if let Err(e) = judge.auth.resolve() {
+                errors.push(format!("compare.judge.auth: {e}"));
+            }
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        for m in &self.models {
+            if m.id.is_empty() {
+                errors.push("models: entry with empty id".to_string());
+                continue;
+            }
+            if !seen.insert(m.id.as_str()) {
+                errors.push(format!("models.{}: duplicate model id", m.id));
+            }
+            errors.extend(
+                validate_model(m)
+                    .into_iter()
+                    .map(|e| format!("models.{}: {e}", m.id)),
+            );
+        }
+
+        // `--model` without `[[models]]` synthesizes a registry entry.
+        if self.models.is_empty() {
+            if let Some(ref id) = self.model_override {
+                seen.insert(id.as_str());
+            }
+        }
+
+        for m in &self.models {
+            for hop in m.fallback_hops() {
+                if let FallbackHop::Local(ref id) = hop {
+                    if id == &m.id {
+                        errors.push(format!("models.{}: fallback to itself", m.id));
+                    } else if !seen.contains(id.as_str()) {
+                        errors.push(format!("models.{}: fallback to unknown model '{id}'", m.id));
+                    }
+                }
+            }
+            match m.overflow_hop() {
+                Some(FallbackHop::Local(to)) if to == m.id => {
+                    errors.push(format!("models.{}: overflow_to itself", m.id));
+                }
+                Some(FallbackHop::Local(to)) if !seen.contains(to.as_str()) => {
+                    errors.push(format!(
+                        "models.{}: overflow_to '{to}' is not a registered model or \"anthropic:<model>\"",
+                        m.id
+                    ));
+                }
+                Some(FallbackHop::Anthropic { model: None }) => {
+                    errors.push(format!(
+                        "models.{}: overflow_to \"anthropic\" must name the model, e.g. \"anthropic:claude-sonnet-4-5\"",
+                        m.id
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        for (i, alias) in self.aliases.iter().enumerate() {
+            if let Err(e) = alias.compile() {
+                errors.push(format!("aliases[{i}]: {e}"));
+            }
+            if alias.to != ALIAS_ANTHROPIC && !seen.contains(alias.to.as_str()) {
+                errors.push(format!(
+                    "aliases[{i}]: target '{}' is not a registered model or \"{ALIAS_ANTHROPIC}\"",
+                    alias.to
+                ));
+            }
+        }
+
+        for (i, route) in self.routes.iter().enumerate() {
+            if route.to != ROUTE_ANTHROPIC && !seen.contains(route.to.as_str()) {
+                errors.push(format!(
+                    "{}: target '{}' is not a registered model or \"{ROUTE_ANTHROPIC}\"",
+                    route.label(i),
+                    route.to
+                ));
+            }
+        }
+
+        let mut candidates = std::collections::HashSet::new();
+        for (i, candidate) in self.compare.candidates.iter().enumerate() {
+            if !seen.contains(candidate.model.as_str()) {
+                errors.push(format!(
+                    "compare.candidates[{i}]: '{}' is not a registered model",
+                    candidate.model
+                ));
+            }
+            if !candidates.insert(candidate.model.as_str()) {
+                errors.push(format!(
+                    "compare.candidates[{i}]: duplicate model '{}'",
+                    candidate.model
+                ));
+            }
+            if candidate.max_concurrent == Some(0) {
+                errors.push(format!(
+                    "compare.candidates[{i}].max_concurrent: must be at least 1"
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Validate a single model definition, independent of the rest of the config.
+pub fn validate_model(m: &ModelDef) -> Vec<String> {
+    let mut errors = Vec::new();
+    if let Some(ref url) = m.target_url {
+        if let Err(e) = validate_url(url) {
+            errors.push(format!("target_url: {e}"));
+        }
+    }
+    for ep in &m.endpoints {
+        if let Err(e) = validate_url(&ep.url) {
+            errors.push(format!("endpoints: {e}"));
+        }
+    }
+    if m.strategy == LoadBalanceStrategy::Weighted
+        && !m.endpoints.is_empty()
+        && m.endpoints.iter().all(|e| e.weight == 0)
+    {
+        errors.push("endpoints: weighted strategy needs at least one non-zero weight".to_string());
+    }
+    if let Err(e) = m.auth.resolve() {
+        errors.push(format!("auth: {e}"));
+    }
+    if let Some(ref path) = m.tokenizer {
+        if !std::path::Path::new(path).is_file() {
+            errors.push(format!("tokenizer: '{path}' is not a file"));
+        }
+    }
+    for (table, params) in [
+        ("defaults", &m.params.defaults),
+        ("overrides", &m.params.overrides),
+    ] {
+        for key in params.keys() {
+            if RESERVED_PARAMS.contains(&key.as_str()) {
+                errors.push(format!("params.{table}: `{key}` cannot be set"));
+            }
+        }
+    }
+    errors
+}
+
+/// Require an absolute http(s) URL with a host.
+pub fn validate_url(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("invalid URL '{url}': {e}"))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("URL '{url}' must use http or https"));
+    }
+    if parsed.host_str().is_none() {
+        return Err(format!("URL '{url}' has no host"));
+    }
+    Ok(())
 }
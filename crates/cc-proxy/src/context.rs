@@ -0,0 +1,163 @@
+//! Context-window enforcement for local models.
+//!
+//! Uses the `tokens` estimate of the request input plus its (clamped)
+//! `max_tokens` against the model's `context_window`. Overflowing requests
+//! get the same `invalid_request_error` Anthropic returns, so Claude Code
+//! compacts and retries instead of the target failing mid-request.
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde_json::Value;
+
+use crate::models::ModelDef;
+
+/// A request that would not fit in a model's context window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextOverflow {
+    pub input_tokens: u64,
+    pub max_tokens: u64,
+    pub context_window: u64,
+}
+
+impl ContextOverflow {
+    /// Anthropic-style 400 `invalid_request_error`. The message starts with
+    /// "prompt is too long", which Claude Code recognizes.
+    pub fn into_response(self) -> Response {
+        let body = serde_json::json!({
+            "type": "error",
+            "error": {
+                "type": "invalid_request_error",
+                "message": format!(
+                    "prompt is too long: {} tokens + {} max_tokens > {} maximum",
+                    self.input_tokens, self.max_tokens, self.context_window
+                ),
+            },
+        });
+        (StatusCode::BAD_REQUEST, axum::Json(body)).into_response()
+    }
+}
+
+/// `max_tokens` the target will see: a `[models.params]` override, else the
+/// request's value, else the model's then the global `default`, capped at
+/// the model's `max_output_tokens`.
+pub fn effective_max_tokens(req: &Value, default: Option<u64>, model: &ModelDef) -> u64 {
+    let param =
+        |params: &serde_json::Map<String, Value>| params.get("max_tokens").and_then(|m| m.as_u64());
+    let requested = param(&model.params.overrides)
+        .or_else(|| req.get("max_tokens").and_then(|m| m.as_u64()))
+        .or_else(|| param(&model.params.defaults))
+        .or(default)
+        .unwrap_or(0);
+    model
+        .max_output_tokens
+        .map_or(requested, |cap| requested.min(cap))
+}
+
+/// Check `req` against `model.context_window`. Models without a window
+/// always fit.
+pub fn check(
+    req: &Value,
+    default_max_tokens: Option<u64>,
+    model: &ModelDef,
+) -> Result<(), ContextOverflow> {
+    let Some(context_window) = model.context_window else {
+        return Ok(());
+    };
+    let input_tokens = crate::tokens::estimate_input_tokens(req);
+    let max_tokens = effective_max_tokens(req, default_max_tokens, model);
+    if input_tokens + max_tokens > context_window {
+        return Err(ContextOverflow {
+            input_tokens,
+            max_tokens,
+            context_window,
+        });
+    }
+    Ok(())
+}
+
+/// Cap `max_tokens` in a request body at `model.max_output_tokens`.
+/// Returns the original value when it was lowered.
+pub fn clamp_max_tokens(req: &mut Value, model: &ModelDef) -> Option<u64> {
+    let cap = model.max_output_tokens?;
+    let obj = req.as_object_mut()?;
+    let requested = obj.get("max_tokens").and_then(|m| m.as_u64())?;
+    if requested <= cap {
+        return None;
+    }
+    obj.insert("max_tokens".to_string(), Value::Number(cap.into()));
+    Some(requested)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn model(context_window: Option<u64>, max_output_tokens: Option<u64>) -> ModelDef {
+        ModelDef {
+            id: "m".into(),
+            context_window,
+            max_output_tokens,
+            ..Default::default()
+        }
+    }
+
+    fn request(input_chars: usize, max_tokens: u64) -> Value {
+        json!({
+            "max_tokens": max_tokens,
+            "messages": [{"role": "", "content": "x".repeat(input_chars)}]
+        })
+    }
+
+    #[test]
+    fn output_is_clamped_before_the_window_check() {
+        let m = model(Some(10_000), Some(2_000));
+        // ~8000 input tokens + 32000 requested, clamped to 2000 → fits.
+        let mut req = request(32_000, 32_000);
+        assert_eq!(check(&req, None, &m), Ok(()));
+
+        assert_eq!(clamp_max_tokens(&mut req, &m), Some(32_000));
+        assert_eq!(req["max_tokens"], 2_000);
+        assert_eq!(clamp_max_tokens(&mut req, &m), None);
+    }
+
+    #[test]
+    fn overflow_reports_the_estimate() {
+        let m = model(Some(10_000), Some(4_000));
+        let req = request(32_000, 32_000);
+        assert_eq!(
+            check(&req, None, &m),
+            Err(ContextOverflow {
+                input_tokens: 8_000,
+                max_tokens: 4_000,
+                context_window: 10_000,
+            })
+        );
+    }
+
+    #[test]
+    fn models_without_a_window_always_fit() {
+        let req = request(4_000_000, 32_000);
+        assert_eq!(check(&req, None, &model(None, None)), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn overflow_response_is_an_invalid_request_error() {
+        let resp = ContextOverflow {
+            input_tokens: 9,
+            max_tokens: 2,
+            context_window: 10,
+        }
+        .into_response();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"]["type"], "invalid_request_error");
+        assert!(json["error"]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("prompt is too long"));
+    }
+}
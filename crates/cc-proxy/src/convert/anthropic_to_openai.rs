@@ -1,7 +1,13 @@
 //! Anthropic -> OpenAI chat completions format conversion.
 //!
 //! Converts an Anthropic Messages API request (`serde_json::Value`) to an
-//! OpenAI-compatible chat completions request, suitable for sending to LiteLLM.
+//! OpenAI-compatible chat completions request, suitable for LiteLLM or for
+//! `protocol = "openai"` targets (vLLM, SGLang, llama.cpp). Responses from
+//! those targets are converted back by `openai_to_anthropic`.
+//!
+//! The response half of this module serves the `/v1/chat/completions`
+//! ingress: Anthropic responses (JSON, SSE and errors) are converted to
+//! OpenAI format for OpenAI-SDK clients.
 //!
 //! All parsing uses `serde_json::Value` — no typed Anthropic structs. This
 //! makes the conversion resilient to unknown content block types (thinking,
@@ -14,6 +20,28 @@ use serde_json::{json, Value};
 /// Known content block types we can convert to OpenAI format.
 const KNOWN_BLOCK_TYPES: &[&str] = &["text", "image", "tool_use", "tool_result"];
 
+/// Top-level Anthropic request keys with no OpenAI equivalent or handled
+/// explicitly. Any other key (e.g. `chat_template_kwargs` from
+/// `[models.params]`) is copied through unchanged.
+const ANTHROPIC_REQUEST_KEYS: &[&str] = &[
+    "model",
+    "messages",
+    "system",
+    "max_tokens",
+    "stream",
+    "temperature",
+    "top_p",
+    "stop_sequences",
+    "tools",
+    "tool_choice",
+    "metadata",
+    "thinking",
+    "context_management",
+    "service_tier",
+    "container",
+    "mcp_servers",
+];
+
 /// Convert an Anthropic Messages API request to OpenAI chat completions JSON.
 ///
 /// Accepts a `serde_json::Value` (the raw parsed JSON body) so deserialization
@@ -74,12 +102,18 @@ pub fn anthropic_to_openai(req: &Value) -> Result<Value> {
         .and_then(|v| v.as_u64())
         .unwrap_or(4096);
 
+    let stream = req.get("stream").and_then(|v| v.as_bool()).unwrap_or(false);
+
     let mut request = json!({
         "model": model,
         "messages": messages,
         "max_completion_tokens": max_tokens,
-        "stream": false,
+        "stream": stream,
     });
+    if stream {
+        // Usage arrives in a final chunk only when asked for.
+        request["stream_options"] = json!({ "include_usage": true });
+    }
 
     // Optional fields
     if let Some(temp) = req.get("temperature") {
@@ -97,6 +131,13 @@ pub fn anthropic_to_openai(req: &Value) -> Result<Value> {
     if let Some(tc) = req.get("tool_choice") {
         request["tool_choice"] = convert_tool_choice(tc);
     }
+    if let Some(obj) = req.as_object() {
+        for (key, value) in obj {
+            if !ANTHROPIC_REQUEST_KEYS.contains(&key.as_str()) {
+                request[key] = value.clone();
+            }
+        }
+    }
 
     Ok(request)
 }
@@ -270,6 +311,308 @@ fn convert_tool_choice(tc: &Value) -> Value {
     }
 }
 
+/// Map an Anthropic `stop_reason` to an OpenAI `finish_reason`.
+fn finish_reason(stop_reason: &str) -> &'static str {
+    match stop_reason {
+        "max_tokens" | "model_context_window_exceeded" => "length",
+        "tool_use" => "tool_calls",
+        "refusal" => "content_filter",
+        _ => "stop",
+    }
+}
+
+fn completion_id(message_id: Option<&str>) -> String {
+    let id = message_id.unwrap_or("unknown");
+    format!("chatcmpl-{}", id.strip_prefix("msg_").unwrap_or(id))
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn openai_usage(usage: Option<&Value>) -> Value {
+    let tokens = |key: &str| {
+        usage
+            .and_then(|u| u.get(key))
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0)
+    };
+    // Cached input is reported separately by Anthropic but is part of the prompt.
+    let prompt = tokens("input_tokens")
+        + tokens("cache_read_input_tokens")
+        + tokens("cache_creation_input_tokens");
+    let completion = tokens("output_tokens");
+    json!({
+        "prompt_tokens": prompt,
+        "completion_tokens": completion,
+        "total_tokens": prompt + completion,
+    })
+}
+
+/// Convert a non-streaming Anthropic Messages response to an OpenAI
+/// `chat.completion`.
+pub fn anthropic_response_to_openai(resp: &Value) -> Value {
+    let mut text = String::new();
+    let mut reasoning = String::new();
+    let mut tool_calls = Vec::new();
+
+    for block in resp
+        .get("content")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+    {
+        match block.get("type").and_then(|t| t.as_str()).unwrap_or("") {
+            "text" => text.push_str(block.get("text").and_then(|t| t.as_str()).unwrap_or("")),
+            "thinking" => {
+                reasoning.push_str(block.get("thinking").and_then(|t| t.as_str()).unwrap_or(""))
+            }
+            "tool_use" => tool_calls.push(json!({
+                "id": block.get("id").cloned().unwrap_or(Value::Null),
+                "type": "function",
+                "function": {
+                    "name": block.get("name").cloned().unwrap_or(Value::Null),
+                    "arguments": serde_json::to_string(block.get("input").unwrap_or(&json!({})))
+                        .unwrap_or_default(),
+                },
+            })),
+            _ => {}
+        }
+    }
+
+    let mut message = json!({
+        "role": "assistant",
+        "content": if text.is_empty() && !tool_calls.is_empty() { Value::Null } else { json!(text) },
+    });
+    if !reasoning.is_empty() {
+        message["reasoning_content"] = json!(reasoning);
+    }
+    if !tool_calls.is_empty() {
+        message["tool_calls"] = json!(tool_calls);
+    }
+
+    let stop_reason = resp
+        .get("stop_reason")
+        .and_then(|s| s.as_str())
+        .unwrap_or("end_turn");
+
+    json!({
+        "id": completion_id(resp.get("id").and_then(|v| v.as_str())),
+        "object": "chat.completion",
+        "created": unix_now(),
+        "model": resp.get("model").cloned().unwrap_or(Value::Null),
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": finish_reason(stop_reason),
+        }],
+        "usage": openai_usage(resp.get("usage")),
+    })
+}
+
+/// Convert an Anthropic `error` body (or any other non-2xx body) to an
+/// OpenAI-style error object.
+pub fn anthropic_error_to_openai(body: &[u8]) -> Value {
+    let parsed: Option<Value> = serde_json::from_slice(body).ok();
+    let error = parsed.as_ref().and_then(|v| v.get("error"));
+    let message = error
+        .and_then(|e| e.get("message"))
+        .and_then(|m| m.as_str())
+        .map(String::from)
+        .unwrap_or_else(|| String::from_utf8_lossy(body).trim().to_string());
+    let error_type = error
+        .and_then(|e| e.get("type"))
+        .and_then(|t| t.as_str())
+        .unwrap_or("api_error");
+    json!({
+        "error": {"message": message, "type": error_type, "param": null, "code": null}
+    })
+}
+
+/// Incremental Anthropic Messages SSE -> `chat.completion.chunk` SSE
+/// translator, the inverse of `openai_to_anthropic::SseTranslator`.
+#[derive(Debug, Default)]
+pub struct ChunkTranslator {
+    /// Bytes of an incomplete trailing event.
+    pending: Vec<u8>,
+    /// Send a final usage chunk (`stream_options.include_usage`).
+    include_usage: bool,
+    id: String,
+    model: Value,
+    created: u64,
+    /// Anthropic content block index -> OpenAI tool call index.
+    tool_calls: std::collections::HashMap<u64, u64>,
+    usage: Option<Value>,
+    finished: bool,
+}
+
+impl ChunkTranslator {
+    pub fn new(include_usage: bool) -> Self {
+        Self {
+            include_usage,
+            created: unix_now(),
+            ..Default::default()
+        }
+    }
+
+    /// Translate the complete events in `chunk`.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(chunk);
+        let mut out = Vec::new();
+        while let Some(pos) = self.pending.windows(2).position(|w| w == b"\n\n") {
+            let event: Vec<u8> = self.pending.drain(..pos + 2).collect();
+            self.event(&event, &mut out);
+        }
+        out
+    }
+
+    /// Flush a trailing event and terminate the stream with `[DONE]`.
+    pub fn finish(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        if !self.pending.is_empty() {
+            let event = std::mem::take(&mut self.pending);
+            self.event(&event, &mut out);
+        }
+        self.done(&mut out);
+        out
+    }
+
+    fn event(&mut self, event: &[u8], out: &mut Vec<u8>) {
+        if self.finished {
+            return;
+        }
+        let Ok(event) = std::str::from_utf8(event) else {
+            return;
+        };
+        let Some(data) = event
+            .lines()
+            .find_map(|l| l.strip_prefix("data:"))
+            .map(str::trim)
+        else {
+            return;
+        };
+        let Ok(data) = serde_json::from_str::<Value>(data) else {
+            return;
+        };
+
+        match data.get("type").and_then(|t| t.as_str()).unwrap_or("") {
+            "message_start" => {
+                let message = data.get("message");
+                self.id = completion_id(message.and_then(|m| m.get("id")).and_then(|v| v.as_str()));
+                self.model = message
+                    .and_then(|m| m.get("model"))
+                    .cloned()
+                    .unwrap_or(Value::Null);
+                self.usage = message.and_then(|m| m.get("usage")).cloned();
+                self.chunk(out, json!({"role": "assistant", "content": ""}), None);
+            }
+            "content_block_start" => {
+                let block = data.get("content_block");
+                if block.and_then(|b| b.get("type")).and_then(|t| t.as_str()) == Some("tool_use") {
+                    let index = data.get("index").and_then(|i| i.as_u64()).unwrap_or(0);
+                    let tool_index = self.tool_calls.len() as u64;
+                    self.tool_calls.insert(index, tool_index);
+                    self.chunk(
+                        out,
+                        json!({"tool_calls": [{
+                            "index": tool_index,
+                            "id": block.and_then(|b| b.get("id")).cloned().unwrap_or(Value::Null),
+                            "type": "function",
+                            "function": {
+                                "name": block.and_then(|b| b.get("name")).cloned().unwrap_or(Value::Null),
+                                "arguments": "",
+                            },
+                        }]}),
+                        None,
+                    );
+                }
+            }
+            "content_block_delta" => {
+                let Some(delta) = data.get("delta") else {
+                    return;
+                };
+                let text = |key: &str| delta.get(key).and_then(|t| t.as_str()).unwrap_or("");
+                match delta.get("type").and_then(|t| t.as_str()).unwrap_or("") {
+                    "text_delta" => self.chunk(out, json!({"content": text("text")}), None),
+                    "thinking_delta" => {
+                        self.chunk(out, json!({"reasoning_content": text("thinking")}), None)
+                    }
+                    "input_json_delta" => {
+                        let index = data.get("index").and_then(|i| i.as_u64()).unwrap_or(0);
+                        let tool_index = self.tool_calls.get(&index).copied().unwrap_or(0);
+                        self.chunk(
+                            out,
+                            json!({"tool_calls": [{
+                                "index": tool_index,
+                                "function": {"arguments": text("partial_json")},
+                            }]}),
+                            None,
+                        );
+                    }
+                    _ => {}
+                }
+            }
+            "message_delta" => {
+                if let Some(usage) = data.get("usage") {
+                    // message_delta carries cumulative output (and sometimes input) counts.
+                    let merged = self.usage.get_or_insert_with(|| json!({}));
+                    if let (Some(merged), Some(usage)) = (merged.as_object_mut(), usage.as_object())
+                    {
+                        for (k, v) in usage {
+                            merged.insert(k.clone(), v.clone());
+                        }
+                    }
+                }
+                let stop_reason = data
+                    .get("delta")
+                    .and_then(|d| d.get("stop_reason"))
+                    .and_then(|s| s.as_str())
+                    .unwrap_or("end_turn");
+                self.chunk(out, json!({}), Some(finish_reason(stop_reason)));
+            }
+            "message_stop" => self.done(out),
+            "error" => {
+                let error = anthropic_error_to_openai(data.to_string().as_bytes());
+                out.extend_from_slice(format!("data: {error}\n\n").as_bytes());
+                self.done(out);
+            }
+            _ => {}
+        }
+    }
+
+    fn chunk(&self, out: &mut Vec<u8>, delta: Value, finish_reason: Option<&str>) {
+        let chunk = json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}],
+        });
+        out.extend_from_slice(format!("data: {chunk}\n\n").as_bytes());
+    }
+
+    fn done(&mut self, out: &mut Vec<u8>) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        if self.include_usage {
+            let chunk = json!({
+                "id": self.id,
+                "object": "chat.completion.chunk",
+                "created": self.created,
+                "model": self.model,
+                "choices": [],
+                "usage": openai_usage(self.usage.as_ref()),
+            });
+            out.extend_from_slice(format!("data: {chunk}\n\n").as_bytes());
+        }
+        out.extend_from_slice(b"data: [DONE]\n\n");
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -290,7 +633,8 @@ mod tests {
 
         assert_eq!(oai["model"], "claude-sonnet-4");
         assert_eq!(oai["max_completion_tokens"], 1024);
-        assert_eq!(oai["stream"], false); // Always non-streaming for shadows
+        assert_eq!(oai["stream"], true);
+        assert_eq!(oai["stream_options"]["include_usage"], true);
         assert_eq!(oai["messages"].as_array().unwrap().len(), 1);
         assert_eq!(oai["messages"][0]["role"], "user");
         assert_eq!(oai["messages"][0]["content"], "Hello!");
@@ -397,7 +741,14 @@ mod tests {
         req["temperature"] = json!(0.7);
         req["top_p"] = json!(0.9);
 
+        req["top_k"] = json!(20);
+        req["chat_template_kwargs"] = json!({"enable_thinking": false});
+        req["thinking"] = json!({"type": "enabled", "budget_tokens": 1024});
+
         let oai = anthropic_to_openai(&req).unwrap();
+        assert_eq!(oai["top_k"], 20);
+        assert_eq!(oai["chat_template_kwargs"]["enable_thinking"], false);
+        assert!(oai.get("thinking").is_none());
         let temp = oai["temperature"].as_f64().unwrap();
         assert!((temp - 0.7).abs() < 0.001, "temperature: {temp}");
         let top_p = oai["top_p"].as_f64().unwrap();
@@ -476,4 +827,92 @@ mod tests {
         assert_eq!(msgs[0]["role"], "tool");
         assert_eq!(msgs[0]["content"], "Result line 1Result line 2");
     }
+
+    #[test]
+    fn test_response_to_openai() {
+        let resp = json!({
+            "id": "msg_01",
+            "type": "message",
+            "model": "claude-sonnet-4",
+            "content": [
+                {"type": "text", "text": "Checking."},
+                {"type": "tool_use", "id": "toolu_1", "name": "Bash", "input": {"command": "ls"}}
+            ],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 10, "cache_read_input_tokens": 5, "output_tokens": 3}
+        });
+        let oai = anthropic_response_to_openai(&resp);
+        assert_eq!(oai["id"], "chatcmpl-01");
+        assert_eq!(oai["object"], "chat.completion");
+        let choice = &oai["choices"][0];
+        assert_eq!(choice["finish_reason"], "tool_calls");
+        assert_eq!(choice["message"]["content"], "Checking.");
+        assert_eq!(choice["message"]["tool_calls"][0]["id"], "toolu_1");
+        assert_eq!(
+            choice["message"]["tool_calls"][0]["function"]["arguments"],
+            r#"{"command":"ls"}"#
+        );
+        assert_eq!(
+            oai["usage"],
+            json!({"prompt_tokens": 15, "completion_tokens": 3, "total_tokens": 18})
+        );
+    }
+
+    #[test]
+    fn test_error_to_openai() {
+        let err = anthropic_error_to_openai(
+            br#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long"}}"#,
+        );
+        assert_eq!(err["error"]["type"], "invalid_request_error");
+        assert_eq!(err["error"]["message"], "prompt is too long");
+
+        let err = anthropic_error_to_openai(b"upstream timeout");
+        assert_eq!(err["error"]["message"], "upstream timeout");
+    }
+
+    #[test]
+    fn test_stream_to_chunks() {
+        let upstream = concat!(
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",\"model\":\"m\",\"usage\":{\"input_tokens\":7,\"output_tokens\":1}}}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":1,\"content_block\":{\"type\":\"tool_use\",\"id\":\"toolu_1\",\"name\":\"Bash\",\"input\":{}}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{}\"}}\n\n",
+            "event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"tool_use\"},\"usage\":{\"output_tokens\":4}}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        );
+        let mut t = ChunkTranslator::new(true);
+        let (a, b) = upstream.as_bytes().split_at(100);
+        let mut out = t.push(a);
+        out.extend(t.push(b));
+        out.extend(t.finish());
+
+        let out = String::from_utf8(out).unwrap();
+        let chunks: Vec<&str> = out
+            .split("\n\n")
+            .filter(|c| !c.is_empty())
+            .map(|c| c.strip_prefix("data: ").unwrap())
+            .collect();
+        assert_eq!(chunks.last(), Some(&"[DONE]"));
+        let parsed: Vec<Value> = chunks[..chunks.len() - 1]
+            .iter()
+            .map(|c| serde_json::from_str(c).unwrap())
+            .collect();
+        assert_eq!(parsed[0]["id"], "chatcmpl-1");
+        assert_eq!(parsed[0]["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(parsed[1]["choices"][0]["delta"]["content"], "Hi");
+        assert_eq!(
+            parsed[2]["choices"][0]["delta"]["tool_calls"][0]["function"]["name"],
+            "Bash"
+        );
+        assert_eq!(
+            parsed[3]["choices"][0]["delta"]["tool_calls"][0]["function"]["arguments"],
+            "{}"
+        );
+        assert_eq!(parsed[4]["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(
+            parsed[5]["usage"],
+            json!({"prompt_tokens": 7, "completion_tokens": 4, "total_tokens": 11})
+        );
+    }
 }
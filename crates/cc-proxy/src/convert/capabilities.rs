@@ -0,0 +1,420 @@
+//! Adapt Anthropic requests to a local target's capability profile.
+//!
+//! Works on the untyped `serde_json::Value` like the rest of the local path,
+//! so unknown fields pass through untouched. Every change is returned as a
+//! low-severity `ValidationFinding` and emitted on the root span under
+//! `local.capabilities.*`.
+
+use axum::http::HeaderMap;
+use opentelemetry::{Key, Value as OtelValue};
+use serde_json::Value;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use super::validation::{ValidationFinding, ValidationSeverity};
+use crate::models::ModelCapabilities;
+
+/// Remove or downgrade everything in `req` that `caps` marks unsupported.
+pub fn strip_unsupported(req: &mut Value, caps: &ModelCapabilities) -> Vec<ValidationFinding> {
+    let mut findings = Vec::new();
+    let Some(obj) = req.as_object_mut() else {
+        return findings;
+    };
+
+    for (field, supported) in [
+        ("thinking", caps.thinking),
+        ("context_management", caps.context_management),
+    ] {
+        if !supported && obj.remove(field).is_some() {
+            findings.push(finding(
+                "removed_field",
+                format!("Removed unsupported `{field}` parameter"),
+                None,
+                None,
+                None,
+            ));
+        }
+    }
+
+    let mut cache_control_removed = 0;
+
+    if let Some(Value::Array(system)) = obj.get_mut("system") {
+        if !caps.cache_control {
+            for block in system.iter_mut() {
+                cache_control_removed += remove_key(block, "cache_control");
+            }
+        }
+    }
+
+    if let Some(Value::Array(tools)) = obj.get_mut("tools") {
+        if !caps.server_tools {
+            let before = tools.len();
+            tools.retain(|t| t.get("input_schema").is_some() || t.get("type").is_none());
+            let removed = before - tools.len();
+            if removed > 0 {
+                findings.push(finding(
+                    "removed_tool",
+                    format!("Removed {removed} Anthropic-defined tool(s)"),
+                    None,
+                    None,
+                    None,
+                ));
+            }
+        }
+        if !caps.cache_control {
+            for tool in tools.iter_mut() {
+                cache_control_removed += remove_key(tool, "cache_control");
+            }
+        }
+    }
+    if obj
+        .get("tools")
+        .and_then(|t| t.as_array())
+        .is_some_and(|t| t.is_empty())
+    {
+        obj.remove("tools");
+        obj.remove("tool_choice");
+    }
+
+    if let Some(Value::Array(messages)) = obj.get_mut("messages") {
+        for (index, message) in messages.iter_mut().enumerate() {
+            let role = message
+                .get("role")
+                .and_then(|r| r.as_str())
+                .map(String::from);
+            if let Some(Value::Array(blocks)) = message.get_mut("content") {
+                cache_control_removed +=
+                    adapt_blocks(blocks, caps, index, role.as_deref(), &mut findings);
+            }
+        }
+    }
+
+    if cache_control_removed > 0 {
+        findings.push(finding(
+            "removed_field",
+            format!("Removed {cache_control_removed} `cache_control` marker(s)"),
+            None,
+            None,
+            None,
+        ));
+    }
+
+    findings
+}
+
+/// Adapt one content block list in place. Recurses into `tool_result`
+/// content. Returns the number of `cache_control` markers removed.
+fn adapt_blocks(
+    blocks: &mut Vec<Value>,
+    caps: &ModelCapabilities,
+    index: usize,
+    role: Option<&str>,
+    findings: &mut Vec<ValidationFinding>,
+) -> usize {
+    let mut cache_control_removed = 0;
+
+    blocks.retain(|block| {
+        let block_type = block.get("type").and_then(|t| t.as_str()).unwrap_or("");
+        let drop = match block_type {
+            "thinking" | "redacted_thinking" => !caps.thinking,
+            "server_tool_use" => !caps.server_tools,
+            t if t.ends_with("_tool_result") && t != "tool_result" => !caps.server_tools,
+            _ => false,
+        };
+        if drop {
+            findings.push(finding(
+                "removed_block",
+                format!("Removed unsupported \"{block_type}\" block"),
+                Some(block_type),
+                Some(index),
+                role,
+            ));
+        }
+        !drop
+    });
+
+    for block in blocks.iter_mut() {
+        let block_type = block
+            .get("type")
+            .and_then(|t| t.as_str())
+            .unwrap_or("")
+            .to_string();
+
+        match block_type.as_str() {
+            "document" if !caps.documents => {
+                *block = document_to_text(block);
+                findings.push(finding(
+                    "converted_block",
+                    "Converted \"document\" block to text".to_string(),
+                    Some("document"),
+                    Some(index),
+                    role,
+                ));
+            }
+            "image" if !caps.images => {
+                *block = serde_json::json!({"type": "text", "text": "[image omitted]"});
+                findings.push(finding(
+                    "converted_block",
+                    "Replaced \"image\" block with a text placeholder".to_string(),
+                    Some("image"),
+                    Some(index),
+                    role,
+                ));
+            }
+            "tool_result" => {
+                if let Some(Value::Array(inner)) = block.get_mut("content") {
+                    cache_control_removed += adapt_blocks(inner, caps, index, role, findings);
+                }
+            }
+            _ => {}
+        }
+
+        if !caps.citations && remove_key(block, "citations") > 0 {
+            findings.push(finding(
+                "removed_field",
+                format!("Removed `citations` from \"{block_type}\" block"),
+                Some(&block_type),
+                Some(index),
+                role,
+            ));
+        }
+        if !caps.cache_control {
+            cache_control_removed += remove_key(block, "cache_control");
+        }
+    }
+
+    cache_control_removed
+}
+
+/// Flatten a `document` block into a text block, keeping what text it has.
+fn document_to_text(block: &Value) -> Value {
+    let source = block.get("source");
+    let body = match source.and_then(|s| s.get("type")).and_then(|t| t.as_str()) {
+        Some("text") => source
+            .and_then(|s| s.get("data"))
+            .and_then(|d| d.as_str())
+            .map(String::from),
+        Some("content") => source
+            .and_then(|s| s.get("content"))
+            .and_then(|c| c.as_array())
+            .map(|blocks| {
+                blocks
+                    .iter()
+                    .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }),
+        _ => None,
+    };
+
+    let title = block.get("title").and_then(|t| t.as_str());
+    let text = match (title, body) {
+        (Some(title), Some(body)) => format!("# {title}\n\n{body}"),
+        (None, Some(body)) => body,
+        (Some(title), None) => format!("[document omitted: {title}]"),
+        (None, None) => "[document omitted]".to_string(),
+    };
+    serde_json::json!({"type": "text", "text": text})
+}
+
+/// Return `headers` without the ones `caps` marks unsupported, recording a
+/// finding for each removed header.
+pub fn strip_unsupported_headers(
+    headers: &HeaderMap,
+    caps: &ModelCapabilities,
+    findings: &mut Vec<ValidationFinding>,
+) -> HeaderMap {
+    let mut headers = headers.clone();
+    if !caps.anthropic_beta && headers.remove("anthropic-beta").is_some() {
+        findings.push(finding(
+            "removed_header",
+            "Removed unsupported `anthropic-beta` header".to_string(),
+            None,
+            None,
+            None,
+        ));
+    }
+    headers
+}
+
+/// Record capability findings on `span` and as debug events.
+pub fn emit(span: &Span, model: &str, findings: &[ValidationFinding]) {
+    if findings.is_empty() {
+        return;
+    }
+    span.set_attribute(
+        "local.capabilities.transform_count",
+        OtelValue::I64(findings.len() as i64),
+    );
+    let json: Vec<Value> = findings.iter().map(ValidationFinding::to_json).collect();
+    if let Ok(json_str) = serde_json::to_string(&json) {
+        span.set_attribute(
+            Key::from_static_str("local.capabilities.findings_json"),
+            OtelValue::String(json_str.into()),
+        );
+    }
+    for f in findings {
+        tracing::debug!(
+            model = model,
+            category = %f.category,
+            message = %f.message,
+            block_type = f.block_type.as_deref().unwrap_or(""),
+            message_index = f.message_index,
+            "Adapted request for model capabilities"
+        );
+    }
+}
+
+fn remove_key(value: &mut Value, key: &str) -> usize {
+    value
+        .as_object_mut()
+        .and_then(|o| o.remove(key))
+        .map_or(0, |_| 1)
+}
+
+fn finding(
+    category: &str,
+    message: String,
+    block_type: Option<&str>,
+    message_index: Option<usize>,
+    role: Option<&str>,
+) -> ValidationFinding {
+    ValidationFinding {
+        severity: ValidationSeverity::Low,
+        category: category.to_string(),
+        message,
+        block_type: block_type.map(String::from),
+        message_index,
+        role: role.map(String::from),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn none_supported() -> ModelCapabilities {
+        ModelCapabilities {
+            thinking: false,
+            cache_control: false,
+            context_management: false,
+            server_tools: false,
+            citations: false,
+            documents: false,
+            images: false,
+            anthropic_beta: false,
+            count_tokens: false,
+        }
+    }
+
+    #[test]
+    fn default_profile_changes_nothing() {
+        let mut req = json!({
+            "thinking": {"type": "enabled", "budget_tokens": 1024},
+            "system": [{"type": "text", "text": "s", "cache_control": {"type": "ephemeral"}}],
+            "messages": [{"role": "assistant", "content": [{"type": "thinking", "thinking": "..."}]}]
+        });
+        let before = req.clone();
+        assert!(strip_unsupported(&mut req, &ModelCapabilities::default()).is_empty());
+        assert_eq!(req, before);
+    }
+
+    #[test]
+    fn strips_fields_and_blocks() {
+        let mut req = json!({
+            "thinking": {"type": "enabled", "budget_tokens": 1024},
+            "context_management": {"edits": []},
+            "system": [{"type": "text", "text": "s", "cache_control": {"type": "ephemeral"}}],
+            "tools": [
+                {"name": "Bash", "input_schema": {}, "cache_control": {"type": "ephemeral"}},
+                {"type": "web_search_20250305", "name": "web_search"}
+            ],
+            "messages": [
+                {"role": "assistant", "content": [
+                    {"type": "thinking", "thinking": "...", "signature": "x"},
+                    {"type": "server_tool_use", "id": "s1", "name": "web_search", "input": {}},
+                    {"type": "web_search_tool_result", "tool_use_id": "s1", "content": []},
+                    {"type": "text", "text": "answer", "citations": []}
+                ]}
+            ]
+        });
+        let findings = strip_unsupported(&mut req, &none_supported());
+
+        assert_eq!(
+            req,
+            json!({
+                "system": [{"type": "text", "text": "s"}],
+                "tools": [{"name": "Bash", "input_schema": {}}],
+                "messages": [{"role": "assistant", "content": [{"type": "text", "text": "answer"}]}]
+            })
+        );
+        let categories: Vec<&str> = findings.iter().map(|f| f.category.as_str()).collect();
+        assert_eq!(
+            categories,
+            vec![
+                "removed_field", // thinking
+                "removed_field", // context_management
+                "removed_tool",  // web_search
+                "removed_block", // thinking block
+                "removed_block", // server_tool_use
+                "removed_block", // web_search_tool_result
+                "removed_field", // citations
+                "removed_field", // 2x cache_control
+            ]
+        );
+        assert!(findings
+            .iter()
+            .all(|f| f.severity == ValidationSeverity::Low));
+        assert_eq!(findings[3].message_index, Some(0));
+        assert_eq!(findings[3].role.as_deref(), Some("assistant"));
+    }
+
+    #[test]
+    fn documents_and_images_become_text() {
+        let mut req = json!({
+            "messages": [{"role": "user", "content": [
+                {"type": "document", "title": "Notes", "source": {"type": "text", "media_type": "text/plain", "data": "hello"}},
+                {"type": "document", "source": {"type": "base64", "media_type": "application/pdf", "data": "JVBER"}},
+                {"type": "tool_result", "tool_use_id": "t1", "content": [
+                    {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBO"}}
+                ]}
+            ]}]
+        });
+        let findings = strip_unsupported(&mut req, &none_supported());
+
+        let content = &req["messages"][0]["content"];
+        assert_eq!(
+            content[0],
+            json!({"type": "text", "text": "# Notes\n\nhello"})
+        );
+        assert_eq!(
+            content[1],
+            json!({"type": "text", "text": "[document omitted]"})
+        );
+        assert_eq!(
+            content[2]["content"][0],
+            json!({"type": "text", "text": "[image omitted]"})
+        );
+        assert_eq!(findings.len(), 3);
+        assert!(findings.iter().all(|f| f.category == "converted_block"));
+    }
+
+    #[test]
+    fn strips_beta_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert("anthropic-beta", "context-1m-2025-08-07".parse().unwrap());
+        headers.insert("x-app", "cli".parse().unwrap());
+        let mut findings = Vec::new();
+
+        let kept =
+            strip_unsupported_headers(&headers, &ModelCapabilities::default(), &mut findings);
+        assert!(kept.contains_key("anthropic-beta"));
+        assert!(findings.is_empty());
+
+        let stripped = strip_unsupported_headers(&headers, &none_supported(), &mut findings);
+        assert!(!stripped.contains_key("anthropic-beta"));
+        assert!(stripped.contains_key("x-app"));
+        assert_eq!(findings[0].category, "removed_header");
+    }
+}
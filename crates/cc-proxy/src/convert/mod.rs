@@ -1,6 +1,7 @@
-//! Anthropic protocol types and conversion to OpenAI format.
+//! Anthropic protocol types and conversion to and from OpenAI format.
 
-#[allow(dead_code)]
 pub mod anthropic_to_openai;
+pub mod capabilities;
+pub mod openai_to_anthropic;
 pub mod types;
 pub mod validation;
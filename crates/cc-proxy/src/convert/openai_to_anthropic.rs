@@ -0,0 +1,837 @@
+//! OpenAI chat completions -> Anthropic Messages response conversion.
+//!
+//! The inverse of `anthropic_to_openai` for responses: non-streaming
+//! `chat.completion` JSON becomes a Messages `message` object, streaming
+//! `chat.completion.chunk` SSE becomes the Anthropic event sequence
+//! (`message_start`, `content_block_*`, `message_delta`, `message_stop`),
+//! and OpenAI-style error bodies become Anthropic `error` objects.
+//!
+//! `openai_request_to_anthropic` handles the request direction for the
+//! `/v1/chat/completions` ingress.
+//!
+//! Like the request direction, everything works on `serde_json::Value` so
+//! server-specific extras (e.g. `reasoning_content`) don't break parsing.
+
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+/// Map an OpenAI `finish_reason` to an Anthropic `stop_reason`.
+fn stop_reason(finish_reason: &str) -> &'static str {
+    match finish_reason {
+        "length" => "max_tokens",
+        "tool_calls" | "function_call" => "tool_use",
+        "stop_sequence" => "stop_sequence",
+        _ => "end_turn",
+    }
+}
+
+/// Reasoning text emitted by vLLM / SGLang / llama.cpp reasoning parsers.
+fn reasoning_text(message: &Value) -> Option<&str> {
+    message
+        .get("reasoning_content")
+        .or_else(|| message.get("reasoning"))
+        .and_then(|r| r.as_str())
+        .filter(|r| !r.is_empty())
+}
+
+fn message_id(resp: &Value) -> String {
+    match resp.get("id").and_then(|v| v.as_str()) {
+        Some(id) => format!("msg_{id}"),
+        None => format!("msg_{}", uuid::Uuid::new_v4().simple()),
+    }
+}
+
+/// Parse tool call arguments, keeping unparseable strings visible rather
+/// than dropping them.
+fn tool_input(arguments: Option<&Value>) -> Value {
+    match arguments {
+        Some(Value::String(s)) if s.trim().is_empty() => json!({}),
+        Some(Value::String(s)) => serde_json::from_str(s).unwrap_or_else(|_| json!({ "_raw": s })),
+        Some(v @ Value::Object(_)) => v.clone(),
+        _ => json!({}),
+    }
+}
+
+/// Convert a non-streaming `chat.completion` response to an Anthropic
+/// Messages response.
+pub fn openai_to_anthropic(resp: &Value) -> Value {
+    let choice = resp
+        .get("choices")
+        .and_then(|c| c.as_array())
+        .and_then(|c| c.first());
+    let message = choice.and_then(|c| c.get("message"));
+
+    let mut content = Vec::new();
+    if let Some(message) = message {
+        if let Some(reasoning) = reasoning_text(message) {
+            content.push(json!({"type": "thinking", "thinking": reasoning, "signature": ""}));
+        }
+        if let Some(text) = message
+            .get("content")
+            .and_then(|c| c.as_str())
+            .filter(|t| !t.is_empty())
+        {
+            content.push(json!({"type": "text", "text": text}));
+        }
+        for call in message
+            .get("tool_calls")
+            .and_then(|t| t.as_array())
+            .into_iter()
+            .flatten()
+        {
+            let function = call.get("function");
+            content.push(json!({
+                "type": "tool_use",
+                "id": call.get("id").and_then(|v| v.as_str()).unwrap_or_default(),
+                "name": function.and_then(|f| f.get("name")).and_then(|v| v.as_str()).unwrap_or_default(),
+                "input": tool_input(function.and_then(|f| f.get("arguments"))),
+            }));
+        }
+    }
+
+    let finish_reason = choice
+        .and_then(|c| c.get("finish_reason"))
+        .and_then(|f| f.as_str())
+        .unwrap_or("stop");
+    let usage = resp.get("usage");
+    let tokens = |key: &str| {
+        usage
+            .and_then(|u| u.get(key))
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0)
+    };
+
+    json!({
+        "id": message_id(resp),
+        "type": "message",
+        "role": "assistant",
+        "model": resp.get("model").and_then(|m| m.as_str()).unwrap_or("unknown"),
+        "content": content,
+        "stop_reason": stop_reason(finish_reason),
+        "stop_sequence": null,
+        "usage": {
+            "input_tokens": tokens("prompt_tokens"),
+            "output_tokens": tokens("completion_tokens"),
+        },
+    })
+}
+
+/// Convert an OpenAI-style error body to an Anthropic `error` object.
+///
+/// Context-length errors become "prompt is too long" so Claude Code compacts.
+pub fn error_to_anthropic(status: u16, body: &[u8]) -> Value {
+    let parsed: Option<Value> = serde_json::from_slice(body).ok();
+    let message = parsed
+        .as_ref()
+        .and_then(|v| {
+            v.get("error")
+                .and_then(|e| e.get("message").or(Some(e)))
+                .or_else(|| v.get("message"))
+                .or_else(|| v.get("detail"))
+        })
+        .and_then(|m| m.as_str())
+        .map(String::from)
+        .unwrap_or_else(|| String::from_utf8_lossy(body).trim().to_string());
+
+    let error_type = match status {
+        400 | 422 => "invalid_request_error",
+        401 => "authentication_error",
+        403 => "permission_error",
+        404 => "not_found_error",
+        413 => "request_too_large",
+        429 => "rate_limit_error",
+        503 | 529 => "overloaded_error",
+        _ => "api_error",
+    };
+
+    let lower = message.to_lowercase();
+    let message = if error_type == "invalid_request_error"
+        && (lower.contains("context length") || lower.contains("context window"))
+        && !lower.starts_with("prompt is too long")
+    {
+        format!("prompt is too long: {message}")
+    } else {
+        message
+    };
+
+    json!({
+        "type": "error",
+        "error": {"type": error_type, "message": message},
+    })
+}
+
+/// `max_tokens` for OpenAI requests that set neither `max_completion_tokens`
+/// nor `max_tokens` (required by the Messages API).
+pub const DEFAULT_MAX_TOKENS: u64 = 4096;
+
+/// Convert an OpenAI chat completions request to an Anthropic Messages
+/// request. System and developer messages become `system`, tool calls and
+/// `tool` messages become `tool_use` / `tool_result` blocks, and consecutive
+/// messages of the same role are merged as the Messages API requires.
+pub fn openai_request_to_anthropic(req: &Value) -> Value {
+    let mut system = Vec::new();
+    let mut messages: Vec<Value> = Vec::new();
+
+    for msg in req
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .into_iter()
+        .flatten()
+    {
+        let role = msg.get("role").and_then(|r| r.as_str()).unwrap_or("user");
+        let (role, blocks) = match role {
+            "system" | "developer" => {
+                system.push(content_text(msg.get("content")));
+                continue;
+            }
+            "tool" => (
+                "user",
+                vec![json!({
+                    "type": "tool_result",
+                    "tool_use_id": msg.get("tool_call_id").cloned().unwrap_or(Value::Null),
+                    "content": content_text(msg.get("content")),
+                })],
+            ),
+            "assistant" => {
+                let mut blocks = content_blocks(msg.get("content"));
+                for call in msg
+                    .get("tool_calls")
+                    .and_then(|t| t.as_array())
+                    .into_iter()
+                    .flatten()
+                {
+                    let function = call.get("function");
+                    blocks.push(json!({
+                        "type": "tool_use",
+                        "id": call.get("id").cloned().unwrap_or(Value::Null),
+                        "name": function.and_then(|f| f.get("name")).cloned().unwrap_or(Value::Null),
+                        "input": tool_input(function.and_then(|f| f.get("arguments"))),
+                    }));
+                }
+                ("assistant", blocks)
+            }
+            _ => ("user", content_blocks(msg.get("content"))),
+        };
+        if blocks.is_empty() {
+            continue;
+        }
+
+        match messages.last_mut() {
+            Some(last) if last["role"] == role => {
+                if let Some(content) = last["content"].as_array_mut() {
+                    content.extend(blocks);
+                }
+            }
+            _ => messages.push(json!({"role": role, "content": blocks})),
+        }
+    }
+
+    let max_tokens = req
+        .get("max_completion_tokens")
+        .or_else(|| req.get("max_tokens"))
+        .and_then(|m| m.as_u64())
+        .unwrap_or(DEFAULT_MAX_TOKENS);
+
+    let mut out = json!({
+        "model": req.get("model").cloned().unwrap_or(Value::Null),
+        "max_tokens": max_tokens,
+        "messages": messages,
+    });
+    let system: Vec<String> = system.into_iter().filter(|s| !s.is_empty()).collect();
+    if !system.is_empty() {
+        out["system"] = json!(system.join("\n"));
+    }
+    for key in ["stream", "temperature", "top_p"] {
+        if let Some(v) = req.get(key).filter(|v| !v.is_null()) {
+            out[key] = v.clone();
+        }
+    }
+    match req.get("stop") {
+        Some(Value::String(s)) => out["stop_sequences"] = json!([s]),
+        Some(Value::Array(a)) => out["stop_sequences"] = json!(a),
+        _ => {}
+    }
+    if let Some(tools) = req.get("tools").and_then(|t| t.as_array()) {
+        let tools: Vec<Value> = tools
+            .iter()
+            .filter_map(|t| t.get("function"))
+            .map(|f| {
+                let mut tool = json!({
+                    "name": f.get("name").cloned().unwrap_or(Value::Null),
+                    "input_schema": f
+                        .get("parameters")
+                        .cloned()
+                        .unwrap_or_else(|| json!({"type": "object", "properties": {}})),
+                });
+                if let Some(description) = f.get("description").filter(|d| !d.is_null()) {
+                    tool["description"] = description.clone();
+                }
+                tool
+            })
+            .collect();
+        if !tools.is_empty() {
+            out["tools"] = json!(tools);
+        }
+    }
+    if let Some(choice) = req.get("tool_choice") {
+        let converted = match choice {
+            Value::String(s) if s == "required" => Some(json!({"type": "any"})),
+            Value::String(s) if s == "none" => Some(json!({"type": "none"})),
+            Value::String(_) => Some(json!({"type": "auto"})),
+            Value::Object(_) => choice
+                .get("function")
+                .and_then(|f| f.get("name"))
+                .map(|name| json!({"type": "tool", "name": name})),
+            _ => None,
+        };
+        if let (Some(converted), Some(_)) = (converted, out.get("tools")) {
+            out["tool_choice"] = converted;
+        }
+    }
+    if let Some(user) = req.get("user").and_then(|u| u.as_str()) {
+        out["metadata"] = json!({"user_id": user});
+    }
+    out
+}
+
+/// Flatten OpenAI message content (string or parts) to text.
+fn content_text(content: Option<&Value>) -> String {
+    match content {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join(""),
+        _ => String::new(),
+    }
+}
+
+/// Convert OpenAI message content (string or parts) to Anthropic blocks.
+fn content_blocks(content: Option<&Value>) -> Vec<Value> {
+    match content {
+        Some(Value::String(s)) if s.is_empty() => Vec::new(),
+        Some(Value::String(s)) => vec![json!({"type": "text", "text": s})],
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|part| match part.get("type").and_then(|t| t.as_str()) {
+                Some("text") => part
+                    .get("text")
+                    .and_then(|t| t.as_str())
+                    .map(|text| json!({"type": "text", "text": text})),
+                Some("image_url") => part
+                    .get("image_url")
+                    .and_then(|i| i.get("url"))
+                    .and_then(|u| u.as_str())
+                    .map(image_block),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// An Anthropic image block from an OpenAI image URL (data URL or remote).
+fn image_block(url: &str) -> Value {
+    let data_url = url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"));
+    match data_url {
+        Some((media_type, data)) => json!({
+            "type": "image",
+            "source": {"type": "base64", "media_type": media_type, "data": data},
+        }),
+        None => json!({"type": "image", "source": {"type": "url", "url": url}}),
+    }
+}
+
+/// Which content block is currently open in the Anthropic stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpenBlock {
+    Thinking,
+    Text,
+    /// A tool call, by its OpenAI `tool_calls[].index`.
+    Tool(u64),
+}
+
+/// Incremental `chat.completion.chunk` SSE -> Anthropic SSE translator.
+///
+/// Feed upstream bytes to `push` as they arrive (chunk boundaries may split
+/// lines) and call `finish` at end of stream. Each returns the Anthropic
+/// SSE bytes to send downstream, possibly empty.
+#[derive(Debug, Default)]
+pub struct SseTranslator {
+    /// Bytes of an incomplete trailing line.
+    pending: Vec<u8>,
+    started: bool,
+    finished: bool,
+    next_index: usize,
+    open: Option<(OpenBlock, usize)>,
+    /// OpenAI tool call index -> Anthropic content block index.
+    tool_blocks: HashMap<u64, usize>,
+    finish_reason: Option<String>,
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+impl SseTranslator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Translate the complete lines in `chunk`.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(chunk);
+        let mut out = Vec::new();
+        while let Some(pos) = self.pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=pos).collect();
+            self.line(&line, &mut out);
+        }
+        out
+    }
+
+    /// Close the stream, emitting the closing events if `[DONE]` never came.
+    pub fn finish(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        if !self.pending.is_empty() {
+            let line = std::mem::take(&mut self.pending);
+            self.line(&line, &mut out);
+        }
+        self.close(&mut out);
+        out
+    }
+
+    fn line(&mut self, line: &[u8], out: &mut Vec<u8>) {
+        if self.finished {
+            return;
+        }
+        let Ok(line) = std::str::from_utf8(line) else {
+            return;
+        };
+        let Some(data) = line.trim().strip_prefix("data:").map(str::trim) else {
+            return;
+        };
+        if data == "[DONE]" {
+            self.close(out);
+            return;
+        }
+        let Ok(chunk) = serde_json::from_str::<Value>(data) else {
+            return;
+        };
+
+        if let Some(error) = chunk.get("error") {
+            let message = error
+                .get("message")
+                .and_then(|m| m.as_str())
+                .unwrap_or("upstream error");
+            emit(
+                out,
+                "error",
+                json!({"type": "error", "error": {"type": "api_error", "message": message}}),
+            );
+            self.finished = true;
+            return;
+        }
+
+        if !self.started {
+            self.started = true;
+            emit(
+                out,
+                "message_start",
+                json!({
+                    "type": "message_start",
+                    "message": {
+                        "id": message_id(&chunk),
+                        "type": "message",
+                        "role": "assistant",
+                        "model": chunk.get("model").and_then(|m| m.as_str()).unwrap_or("unknown"),
+                        "content": [],
+                        "stop_reason": null,
+                        "stop_sequence": null,
+                        "usage": {"input_tokens": 0, "output_tokens": 0},
+                    },
+                }),
+            );
+        }
+
+        if let Some(usage) = chunk.get("usage").filter(|u| u.is_object()) {
+            if let Some(n) = usage.get("prompt_tokens").and_then(|v| v.as_u64()) {
+                self.input_tokens = n;
+            }
+            if let Some(n) = usage.get("completion_tokens").and_then(|v| v.as_u64()) {
+                self.output_tokens = n;
+            }
+        }
+
+        let Some(choice) = chunk
+            .get("choices")
+            .and_then(|c| c.as_array())
+            .and_then(|c| c.first())
+        else {
+            return;
+        };
+        if let Some(delta) = choice.get("delta") {
+            if let Some(reasoning) = reasoning_text(delta) {
+                let index = self.ensure_open(
+                    OpenBlock::Thinking,
+                    out,
+                    || json!({"type": "thinking", "thinking": "", "signature": ""}),
+                );
+                emit_delta(
+                    out,
+                    index,
+                    json!({"type": "thinking_delta", "thinking": reasoning}),
+                );
+            }
+            if let Some(text) = delta
+                .get("content")
+                .and_then(|c| c.as_str())
+                .filter(|t| !t.is_empty())
+            {
+                let index =
+                    self.ensure_open(OpenBlock::Text, out, || json!({"type": "text", "text": ""}));
+                emit_delta(out, index, json!({"type": "text_delta", "text": text}));
+            }
+            for call in delta
+                .get("tool_calls")
+                .and_then(|t| t.as_array())
+                .into_iter()
+                .flatten()
+            {
+                self.tool_call(call, out);
+            }
+        }
+        if let Some(reason) = choice.get("finish_reason").and_then(|f| f.as_str()) {
+            self.finish_reason = Some(reason.to_string());
+        }
+    }
+
+    fn tool_call(&mut self, call: &Value, out: &mut Vec<u8>) {
+        let tool_index = call.get("index").and_then(|i| i.as_u64()).unwrap_or(0);
+        let function = call.get("function");
+
+        let index = match self.tool_blocks.get(&tool_index) {
+            Some(index) => *index,
+            None => {
+                let id = call
+                    .get("id")
+                    .and_then(|v| v.as_str())
+                    .map(String::from)
+                    .unwrap_or_else(|| format!("toolu_{}", uuid::Uuid::new_v4().simple()));
+                let name = function
+                    .and_then(|f| f.get("name"))
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string();
+                let index = self.ensure_open(
+                    OpenBlock::Tool(tool_index),
+                    out,
+                    || json!({"type": "tool_use", "id": id, "name": name, "input": {}}),
+                );
+                self.tool_blocks.insert(tool_index, index);
+                index
+            }
+        };
+
+        if let Some(arguments) = function
+            .and_then(|f| f.get("arguments"))
+            .and_then(|a| a.as_str())
+            .filter(|a| !a.is_empty())
+        {
+            emit_delta(
+                out,
+                index,
+                json!({"type": "input_json_delta", "partial_json": arguments}),
+            );
+        }
+    }
+
+    /// Index of the open block of `kind`, closing any other open block and
+    /// starting a new one built by `start` if needed.
+    fn ensure_open(
+        &mut self,
+        kind: OpenBlock,
+        out: &mut Vec<u8>,
+        start: impl FnOnce() -> Value,
+    ) -> usize {
+        if let Some((open, index)) = self.open {
+            if open == kind {
+                return index;
+            }
+            emit(
+                out,
+                "content_block_stop",
+                json!({"type": "content_block_stop", "index": index}),
+            );
+        }
+        let index = self.next_index;
+        self.next_index += 1;
+        self.open = Some((kind, index));
+        emit(
+            out,
+            "content_block_start",
+            json!({"type": "content_block_start", "index": index, "content_block": start()}),
+        );
+        index
+    }
+
+    fn close(&mut self, out: &mut Vec<u8>) {
+        if self.finished {
+            return;
+        }
+        if !self.started {
+            // Nothing arrived; still hand the client a well-formed message.
+            self.line(b"data: {}", out);
+        }
+        self.finished = true;
+        if let Some((_, index)) = self.open.take() {
+            emit(
+                out,
+                "content_block_stop",
+                json!({"type": "content_block_stop", "index": index}),
+            );
+        }
+        let reason = stop_reason(self.finish_reason.as_deref().unwrap_or("stop"));
+        emit(
+            out,
+            "message_delta",
+            json!({
+                "type": "message_delta",
+                "delta": {"stop_reason": reason, "stop_sequence": null},
+                "usage": {"input_tokens": self.input_tokens, "output_tokens": self.output_tokens},
+            }),
+        );
+        emit(out, "message_stop", json!({"type": "message_stop"}));
+    }
+}
+
+fn emit(out: &mut Vec<u8>, event: &str, data: Value) {
+    out.extend_from_slice(format!("event: {event}\ndata: {data}\n\n").as_bytes());
+}
+
+fn emit_delta(out: &mut Vec<u8>, index: usize, delta: Value) {
+    emit(
+        out,
+        "content_block_delta",
+        json!({"type": "content_block_delta", "index": index, "delta": delta}),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse translator output back into `(event, data)` pairs.
+    fn events(bytes: &[u8]) -> Vec<(String, Value)> {
+        std::str::from_utf8(bytes)
+            .unwrap()
+            .split("\n\n")
+            .filter(|e| !e.is_empty())
+            .map(|e| {
+                let mut lines = e.lines();
+                let event = lines.next().unwrap().strip_prefix("event: ").unwrap();
+                let data = lines.next().unwrap().strip_prefix("data: ").unwrap();
+                (event.to_string(), serde_json::from_str(data).unwrap())
+            })
+            .collect()
+    }
+
+    fn chunk(delta: Value, finish_reason: Value) -> String {
+        format!(
+            "data: {}\n\n",
+            json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "model": "local",
+                "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}]
+            })
+        )
+    }
+
+    #[test]
+    fn non_streaming_text_and_tool_calls() {
+        let resp = json!({
+            "id": "chatcmpl-1",
+            "model": "local",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "Listing files.",
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": {"name": "Bash", "arguments": "{\"command\":\"ls\"}"}
+                    }]
+                },
+                "finish_reason": "tool_calls"
+            }],
+            "usage": {"prompt_tokens": 12, "completion_tokens": 7}
+        });
+        let msg = openai_to_anthropic(&resp);
+        assert_eq!(msg["id"], "msg_chatcmpl-1");
+        assert_eq!(msg["stop_reason"], "tool_use");
+        assert_eq!(
+            msg["content"],
+            json!([
+                {"type": "text", "text": "Listing files."},
+                {"type": "tool_use", "id": "call_1", "name": "Bash", "input": {"command": "ls"}}
+            ])
+        );
+        assert_eq!(
+            msg["usage"],
+            json!({"input_tokens": 12, "output_tokens": 7})
+        );
+    }
+
+    #[test]
+    fn streaming_text_then_tool_call() {
+        let mut t = SseTranslator::new();
+        let mut out = Vec::new();
+        let upstream = [
+            chunk(json!({"role": "assistant", "content": ""}), Value::Null),
+            chunk(json!({"content": "Hi"}), Value::Null),
+            chunk(
+                json!({"tool_calls": [{"index": 0, "id": "call_1", "type": "function", "function": {"name": "Bash", "arguments": ""}}]}),
+                Value::Null,
+            ),
+            chunk(
+                json!({"tool_calls": [{"index": 0, "function": {"arguments": "{\"command\":"}}]}),
+                Value::Null,
+            ),
+            chunk(
+                json!({"tool_calls": [{"index": 0, "function": {"arguments": "\"ls\"}"}}]}),
+                json!("tool_calls"),
+            ),
+            "data: {\"id\":\"chatcmpl-1\",\"choices\":[],\"usage\":{\"prompt_tokens\":30,\"completion_tokens\":9}}\n\n".to_string(),
+            "data: [DONE]\n\n".to_string(),
+        ]
+        .concat();
+        // Split mid-line to exercise buffering.
+        let (a, b) = upstream.as_bytes().split_at(upstream.len() / 2);
+        out.extend(t.push(a));
+        out.extend(t.push(b));
+        out.extend(t.finish());
+
+        let events = events(&out);
+        let names: Vec<&str> = events.iter().map(|(e, _)| e.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        assert_eq!(
+            events[2].1["delta"],
+            json!({"type": "text_delta", "text": "Hi"})
+        );
+        assert_eq!(
+            events[4].1["content_block"],
+            json!({"type": "tool_use", "id": "call_1", "name": "Bash", "input": {}})
+        );
+        assert_eq!(events[4].1["index"], 1);
+        assert_eq!(events[5].1["delta"]["type"], "input_json_delta");
+        assert_eq!(events[8].1["delta"]["stop_reason"], "tool_use");
+        assert_eq!(
+            events[8].1["usage"],
+            json!({"input_tokens": 30, "output_tokens": 9})
+        );
+    }
+
+    #[test]
+    fn stream_without_done_is_closed_by_finish() {
+        let mut t = SseTranslator::new();
+        let mut out = t.push(chunk(json!({"content": "partial"}), json!("length")).as_bytes());
+        out.extend(t.finish());
+        let events = events(&out);
+        assert_eq!(events.last().unwrap().0, "message_stop");
+        assert_eq!(
+            events[events.len() - 2].1["delta"]["stop_reason"],
+            "max_tokens"
+        );
+        // finish is idempotent
+        assert!(t.finish().is_empty());
+    }
+
+    #[test]
+    fn context_length_errors_become_prompt_too_long() {
+        let body = br#"{"object":"error","message":"This model's maximum context length is 32768 tokens.","type":"BadRequestError","code":400}"#;
+        let err = error_to_anthropic(400, body);
+        assert_eq!(err["error"]["type"], "invalid_request_error");
+        assert!(err["error"]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("prompt is too long: This model's maximum context length"));
+
+        let err = error_to_anthropic(401, br#"{"error":{"message":"bad key"}}"#);
+        assert_eq!(
+            err,
+            json!({"type": "error", "error": {"type": "authentication_error", "message": "bad key"}})
+        );
+    }
+
+    #[test]
+    fn openai_request_round_trips_tools_and_roles() {
+        let req = json!({
+            "model": "my-model",
+            "max_tokens": 256,
+            "stream": true,
+            "stop": "END",
+            "user": "alice",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "What's here?"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBO"}}
+                ]},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_1", "type": "function", "function": {"name": "ls", "arguments": "{}"}},
+                    {"id": "call_2", "type": "function", "function": {"name": "pwd", "arguments": ""}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "a.txt"},
+                {"role": "tool", "tool_call_id": "call_2", "content": "/tmp"}
+            ],
+            "tools": [{"type": "function", "function": {"name": "ls", "parameters": {"type": "object"}}}],
+            "tool_choice": "required"
+        });
+        let out = openai_request_to_anthropic(&req);
+
+        assert_eq!(out["system"], "Be brief.");
+        assert_eq!(out["max_tokens"], 256);
+        assert_eq!(out["stream"], true);
+        assert_eq!(out["stop_sequences"], json!(["END"]));
+        assert_eq!(out["metadata"]["user_id"], "alice");
+        assert_eq!(out["tool_choice"], json!({"type": "any"}));
+        assert_eq!(
+            out["tools"],
+            json!([{"name": "ls", "input_schema": {"type": "object"}}])
+        );
+
+        let messages = out["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[0]["content"][1]["source"],
+            json!({"type": "base64", "media_type": "image/png", "data": "iVBO"})
+        );
+        assert_eq!(messages[1]["content"][1]["input"], json!({}));
+        // Consecutive tool results merge into one user turn.
+        assert_eq!(messages[2]["role"], "user");
+        assert_eq!(
+            messages[2]["content"],
+            json!([
+                {"type": "tool_result", "tool_use_id": "call_1", "content": "a.txt"},
+                {"type": "tool_result", "tool_use_id": "call_2", "content": "/tmp"}
+            ])
+        );
+    }
+}
@@ -21,6 +21,9 @@ pub enum ValidationSeverity {
     High,
     /// Typed parse succeeded but contains unknown content block types.
     Medium,
+    /// The request was adapted for a less capable target (see
+    /// `convert::capabilities`).
+    Low,
 }
 
 impl ValidationSeverity {
@@ -28,6 +31,7 @@ impl ValidationSeverity {
         match self {
             ValidationSeverity::High => "high",
             ValidationSeverity::Medium => "medium",
+            ValidationSeverity::Low => "low",
         }
     }
 }
@@ -48,6 +52,41 @@ pub struct ValidationFinding {
     pub role: Option<String>,
 }
 
+impl ValidationFinding {
+    /// Structured form used in `*.findings_json` span attributes.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut obj = serde_json::Map::new();
+        obj.insert(
+            "severity".to_string(),
+            serde_json::Value::String(self.severity.as_str().to_string()),
+        );
+        obj.insert(
+            "category".to_string(),
+            serde_json::Value::String(self.category.clone()),
+        );
+        obj.insert(
+            "message".to_string(),
+            serde_json::Value::String(self.message.clone()),
+        );
+        if let Some(ref bt) = self.block_type {
+            obj.insert(
+                "block_type".to_string(),
+                serde_json::Value::String(bt.clone()),
+            );
+        }
+        if let Some(idx) = self.message_index {
+            obj.insert(
+                "message_index".to_string(),
+                serde_json::Value::Number(serde_json::Number::from(idx)),
+            );
+        }
+        if let Some(ref role) = self.role {
+            obj.insert("role".to_string(), serde_json::Value::String(role.clone()));
+        }
+        serde_json::Value::Object(obj)
+    }
+}
+
 /// Aggregated validation results for a single request.
 #[derive(Debug)]
 pub struct ValidationReport {
@@ -159,11 +198,16 @@ impl ValidationReport {
             .map(|f| match f.severity {
                 ValidationSeverity::High => 2,
                 ValidationSeverity::Medium => 1,
+                ValidationSeverity::Low => 0,
             })
             .max();
 
         if let Some(sev) = max_severity {
-            let sev_str = if sev >= 2 { "high" } else { "medium" };
+            let sev_str = match sev {
+                2 => "high",
+                1 => "medium",
+                _ => "low",
+            };
             span.set_attribute(
                 Key::from_static_str("shadow.validation.max_severity"),
                 Value::String(sev_str.into()),
@@ -175,37 +219,7 @@ impl ValidationReport {
             let findings_json: Vec<serde_json::Value> = self
                 .findings
                 .iter()
-                .map(|f| {
-                    let mut obj = serde_json::Map::new();
-                    obj.insert(
-                        "severity".to_string(),
-                        serde_json::Value::String(f.severity.as_str().to_string()),
-                    );
-                    obj.insert(
-                        "category".to_string(),
-                        serde_json::Value::String(f.category.clone()),
-                    );
-                    obj.insert(
-                        "message".to_string(),
-                        serde_json::Value::String(f.message.clone()),
-                    );
-                    if let Some(ref bt) = f.block_type {
-                        obj.insert(
-                            "block_type".to_string(),
-                            serde_json::Value::String(bt.clone()),
-                        );
-                    }
-                    if let Some(idx) = f.message_index {
-                        obj.insert(
-                            "message_index".to_string(),
-                            serde_json::Value::Number(serde_json::Number::from(idx)),
-                        );
-                    }
-                    if let Some(ref role) = f.role {
-                        obj.insert("role".to_string(), serde_json::Value::String(role.clone()));
-                    }
-                    serde_json::Value::Object(obj)
-                })
+                .map(ValidationFinding::to_json)
                 .collect();
 
             if let Ok(json_str) = serde_json::to_string(&findings_json) {
@@ -239,6 +253,16 @@ impl ValidationReport {
                         "Validation finding (medium severity)"
                     );
                 }
+                ValidationSeverity::Low => {
+                    tracing::debug!(
+                        category = %finding.category,
+                        message = %finding.message,
+                        block_type = finding.block_type.as_deref().unwrap_or(""),
+                        message_index = finding.message_index,
+                        role = finding.role.as_deref().unwrap_or(""),
+                        "Validation finding (low severity)"
+                    );
+                }
             }
         }
     }
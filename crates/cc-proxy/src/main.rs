@@ -1,24 +1,34 @@
 //! cc-proxy: model gateway for routing Claude Code to self-hosted Anthropic-format deployments.
 
+mod batches;
 mod config;
+mod context;
 mod convert;
 mod mode;
 mod models;
 mod openinference;
 mod proxy;
+mod reload;
+mod routes;
 mod server;
 mod stats;
+mod targets;
+mod tokens;
 
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::time::Duration;
 
-use config::ProxyConfig;
+use batches::BatchStore;
 use mode::{ProxyMode, RuntimeMode};
-use models::{ModelDef, ModelRegistry};
 use proxy::compare::CompareDispatcher;
+use proxy::compare_stats::CompareStats;
+use proxy::compare_store::CompareStore;
+use reload::{CliOverrides, LiveConfig};
 use server::AppState;
 use stats::ProxyStats;
+use targets::{CircuitBreakers, TargetHealth};
+use tokens::TokenizerCache;
 
 fn main() -> anyhow::Result<()> {
     // Parse CLI args
@@ -43,34 +53,17 @@ fn main() -> anyhow::Result<()> {
 
     let allow_anthropic_only = args.iter().any(|a| a == "--allow-anthropic-only");
 
-    // Load configuration
-    let mut config = ProxyConfig::load(&config_path)?;
-
-    // Apply CLI overrides (take precedence over TOML and env vars)
-    if let Some(ref url) = target_url_override {
-        config.target.url = Some(url.clone());
-    }
-    if model_override.is_some() {
-        config.model_override = model_override.clone();
-    }
-    config.anthropic_only_allowed = allow_anthropic_only;
-
-    // Build model registry from TOML [[models]] + backward-compat synthesis from CLI args.
-    // When no [[models]] are configured but --model and --target-url are both set,
-    // synthesize a single model entry so the old CLI-only workflow keeps working.
-    let mut model_defs = config.models.clone();
-    if model_defs.is_empty() {
-        if let Some(ref model_id) = model_override {
-            model_defs.push(ModelDef {
-                id: model_id.clone(),
-                display_name: None,
-                target_url: None, // will use default_target_url
-                context_window: None,
-                max_output_tokens: None,
-            });
-        }
-    }
-    let model_registry = ModelRegistry::new(model_defs, config.target.url.clone());
+    // Load configuration, apply CLI overrides (take precedence over TOML and
+    // env vars), validate, and build the model registry. The same steps run
+    // again on every hot reload.
+    let live = LiveConfig::load(
+        &config_path,
+        CliOverrides {
+            target_url: target_url_override,
+            model_override,
+            allow_anthropic_only,
+        },
+    )?;
 
     // Build the tokio runtime first — tonic gRPC exporter needs a reactor context
     let runtime = tokio::runtime::Builder::new_multi_thread()
@@ -78,6 +71,9 @@ fn main() -> anyhow::Result<()> {
         .build()?;
 
     runtime.block_on(async {
+        let snapshot = live.current();
+        let config = &snapshot.config;
+
         // Initialize tracing (OTLP export is optional — falls back to fmt-only)
         let _tracing_guard = cc_tracing::init_tracing(&config.tracing);
 
@@ -86,15 +82,18 @@ fn main() -> anyhow::Result<()> {
             listen_address = %config.server.listen_address,
             passthrough_url = %config.passthrough.url,
             target_url = ?config.target.url,
-            local_models = model_registry.len(),
+            local_models = snapshot.model_registry.len(),
             "Starting cc-proxy"
         );
 
-        run(config, model_registry).await
+        run(live.clone()).await
     })
 }
 
-async fn run(config: ProxyConfig, model_registry: ModelRegistry) -> anyhow::Result<()> {
+async fn run(live: LiveConfig) -> anyhow::Result<()> {
+    let snapshot = live.current();
+    let config = &snapshot.config;
+
     // Build Anthropic HTTP client
     let primary_client = reqwest::Client::builder()
         .timeout(Duration::from_secs(config.passthrough.timeout_secs))
@@ -105,12 +104,24 @@ async fn run(config: ProxyConfig, model_registry: ModelRegistry) -> anyhow::Resu
         .timeout(Duration::from_secs(config.target.timeout_secs))
         .build()?;
 
-    // Build compare dispatcher
+    // Circuit breakers are shared by the primary path and compare dispatch
+    let breakers = Arc::new(CircuitBreakers::new(config.circuit_breaker.clone()));
+
+    // Build compare dispatcher; completed pairs go to the compare store
+    let compares = Arc::new(CompareStore::open(&config.compare.store_file).map_err(|e| {
+        anyhow::anyhow!(
+            "failed to open compare store {}: {e}",
+            config.compare.store_file
+        )
+    })?);
+    let compare_stats = Arc::new(CompareStats::new(config.compare.stats_window));
     let compare_dispatcher = CompareDispatcher::new(
-        config.target.url.clone().unwrap_or_default(),
         config.target.timeout_secs,
         config.target.max_concurrent,
         target_client,
+        breakers.clone(),
+        Some(compares.clone()),
+        compare_stats.clone(),
     );
 
     // Build stats and mode
@@ -123,15 +134,44 @@ async fn run(config: ProxyConfig, model_registry: ModelRegistry) -> anyhow::Resu
     };
     let mode = RuntimeMode::new(initial_mode);
 
+    // Hot-reload triggers: SIGHUP always, mtime polling when configured
+    #[cfg(unix)]
+    tokio::spawn(reload::watch_sighup(live.clone()));
+    if let Some(secs) = config.server.config_watch_secs.filter(|s| *s > 0) {
+        tokio::spawn(reload::watch_file(live.clone(), Duration::from_secs(secs)));
+    }
+
+    // Active health checks; the task stops if a reload disables them
+    let targets = Arc::new(TargetHealth::new());
+    if config.health_check.interval_secs.is_some_and(|s| s > 0) {
+        tokio::spawn(targets::run_health_checks(
+            live.clone(),
+            targets.clone(),
+            reqwest::Client::new(),
+        ));
+    }
+
+    // Local Message Batches; unfinished ones resume once the server starts
+    let batches = BatchStore::open(
+        &config.batches.dir,
+        config.batches.max_concurrent_per_target,
+    )
+    .map_err(|e| anyhow::anyhow!("failed to open batches dir {}: {e}", config.batches.dir))?;
+
     // Build app state
     let state = AppState {
-        config,
+        live,
         primary_client,
         compare_dispatcher,
         stats,
         mode,
-        model_registry,
         tracing_enabled: Arc::new(AtomicBool::new(true)),
+        targets,
+        breakers,
+        tokenizers: Arc::new(TokenizerCache::default()),
+        batches: Arc::new(batches),
+        compares,
+        compare_stats,
     };
 
     // Run the server
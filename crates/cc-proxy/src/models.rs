@@ -1,15 +1,22 @@
 //! Model registry for routing requests to local targets or Anthropic.
 //!
-//! Built once at startup from config + CLI args. Immutable after construction
-//! so the hot path is a lock-free HashMap lookup.
+//! Built from config + CLI args. The model map is immutable after construction
+//! so the hot path is a lock-free HashMap lookup; the only mutable state is
+//! per-endpoint atomics used for load balancing. Config reloads and the
+//! `/api/models` endpoints build a fresh registry and swap it in (see `reload`).
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
 use serde::{Deserialize, Serialize};
 
+use crate::config::ProxyConfig;
+use crate::targets::TargetHealth;
+
 /// A locally-served model definition from config.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ModelDef {
     /// Model identifier (must match the `model` field in API requests).
     pub id: String,
@@ -19,16 +26,396 @@ pub struct ModelDef {
     pub display_name: Option<String>,
 
     /// Target URL for this model. If None, uses the global `--target-url`.
+    /// Ignored when `endpoints` is non-empty.
     #[serde(default)]
     pub target_url: Option<String>,
 
+    /// Replica endpoints serving this model. When set, one endpoint is picked
+    /// per request according to `strategy`.
+    #[serde(default)]
+    pub endpoints: Vec<EndpointDef>,
+
+    /// How to pick among `endpoints` (default: round-robin).
+    #[serde(default)]
+    pub strategy: LoadBalanceStrategy,
+
     /// Maximum context window size in tokens (reported in /v1/models).
+    /// Requests whose estimated input plus `max_tokens` exceed it are
+    /// rejected as "prompt is too long", or sent to `overflow_to`.
     #[serde(default)]
     pub context_window: Option<u64>,
 
     /// Maximum output tokens the model can generate (reported in /v1/models).
+    /// Larger `max_tokens` requests are clamped to it.
     #[serde(default)]
     pub max_output_tokens: Option<u64>,
+
+    /// Where to send requests that overflow `context_window`: another local
+    /// model ID or `"anthropic:<model>"` (passthrough as that model). Unset
+    /// rejects them.
+    #[serde(default)]
+    pub overflow_to: Option<String>,
+
+    /// Ordered fallback chain tried when this model's target fails before any
+    /// response bytes are streamed. Each entry is another local model ID,
+    /// `"anthropic"` (passthrough, model unchanged) or `"anthropic:<model>"`
+    /// (passthrough with the model rewritten). Only this model's chain is
+    /// followed — fallbacks of fallback models are not.
+    #[serde(default)]
+    pub fallback: Vec<String>,
+
+    /// Anthropic features this model's target understands. Unsupported
+    /// fields, blocks and headers are removed or downgraded before forwarding.
+    #[serde(default)]
+    pub capabilities: ModelCapabilities,
+
+    /// Request body parameters for this model, applied before the global
+    /// `[target]` defaults.
+    #[serde(default)]
+    pub params: ModelParams,
+
+    /// Credentials and extra headers sent to this model's target.
+    #[serde(default)]
+    pub auth: ModelAuth,
+
+    /// API the target speaks. `openai` targets get requests translated to
+    /// `/v1/chat/completions` and responses translated back.
+    #[serde(default)]
+    pub protocol: TargetProtocol,
+
+    /// Path to a HuggingFace `tokenizer.json` used to answer
+    /// `/v1/messages/count_tokens` for this model.
+    #[serde(default)]
+    pub tokenizer: Option<String>,
+}
+
+/// Wire protocol of a local target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetProtocol {
+    /// Anthropic Messages API, forwarded as-is.
+    #[default]
+    Anthropic,
+    /// OpenAI chat completions.
+    Openai,
+}
+
+impl TargetProtocol {
+    /// Path requests are sent to, relative to the target base URL.
+    pub fn messages_path(&self) -> &'static str {
+        match self {
+            TargetProtocol::Anthropic => "/v1/messages",
+            TargetProtocol::Openai => "/v1/chat/completions",
+        }
+    }
+}
+
+/// Upstream credentials for a local target. Secrets are referenced by env
+/// var or file path (e.g. a mounted k8s secret), never stored in config, and
+/// are read when the config is loaded or reloaded, not per request.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelAuth {
+    /// Env var holding the key sent as `x-api-key`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+    /// File holding the key sent as `x-api-key`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key_file: Option<String>,
+    /// Env var holding the token sent as `authorization: Bearer <token>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bearer_token_env: Option<String>,
+    /// File holding the token sent as `authorization: Bearer <token>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bearer_token_file: Option<String>,
+    /// Static headers, sent as-is.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Header name → env var holding its value.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers_env: BTreeMap<String, String>,
+    /// Header name → file holding its value.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers_file: BTreeMap<String, String>,
+}
+
+impl ModelAuth {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Read every configured secret and build the headers to inject,
+    /// replacing whatever the client sent under the same names. Fails on
+    /// the first missing env var, unreadable file or invalid header.
+    pub fn resolve(&self) -> Result<HeaderMap, String> {
+        let mut out = HeaderMap::new();
+        if self.is_empty() {
+            return Ok(out);
+        }
+        let mut insert = |name: &str, value: String| -> Result<(), String> {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("invalid header name '{name}'"))?;
+            let mut value = HeaderValue::from_str(value.trim())
+                .map_err(|_| format!("invalid value for header '{name}'"))?;
+            value.set_sensitive(true);
+            out.insert(name, value);
+            Ok(())
+        };
+
+        for (name, value) in &self.headers {
+            insert(name, value.clone())?;
+        }
+        for (name, var) in &self.headers_env {
+            insert(name, read_env(var)?)?;
+        }
+        for (name, path) in &self.headers_file {
+            insert(name, read_file(path)?)?;
+        }
+        if let Some(key) = secret(&self.api_key_env, &self.api_key_file, "api_key")? {
+            insert("x-api-key", key)?;
+        }
+        if let Some(token) = secret(
+            &self.bearer_token_env,
+            &self.bearer_token_file,
+            "bearer_token",
+        )? {
+            insert("authorization", format!("Bearer {}", token.trim()))?;
+        }
+        Ok(out)
+    }
+}
+
+/// A secret set through exactly one of `<name>_env` / `<name>_file`.
+fn secret(
+    env: &Option<String>,
+    file: &Option<String>,
+    name: &str,
+) -> Result<Option<String>, String> {
+    match (env, file) {
+        (Some(_), Some(_)) => Err(format!("set only one of {name}_env and {name}_file")),
+        (Some(var), None) => read_env(var).map(Some),
+        (None, Some(path)) => read_file(path).map(Some),
+        (None, None) => Ok(None),
+    }
+}
+
+fn read_env(var: &str) -> Result<String, String> {
+    std::env::var(var).map_err(|_| format!("env var {var} is not set"))
+}
+
+fn read_file(path: &str) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("cannot read {path}: {e}"))
+}
+
+/// Per-model request body parameters. Values are arbitrary JSON, so extra
+/// keys such as `top_k` or `chat_template_kwargs` pass through as-is.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelParams {
+    /// Set when the request doesn't carry the key (or carries `null`).
+    pub defaults: serde_json::Map<String, serde_json::Value>,
+    /// Always set, replacing whatever the request carried.
+    pub overrides: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Body keys `[models.params]` may not set: they carry the request itself.
+pub const RESERVED_PARAMS: &[&str] = &["model", "messages", "system", "stream"];
+
+impl ModelParams {
+    /// Insert `defaults` missing from `obj`.
+    pub fn apply_defaults(&self, obj: &mut serde_json::Map<String, serde_json::Value>) {
+        for (key, value) in &self.defaults {
+            if obj.get(key).is_none_or(|v| v.is_null()) {
+                obj.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    /// Write every `overrides` entry into `obj`.
+    pub fn apply_overrides(&self, obj: &mut serde_json::Map<String, serde_json::Value>) {
+        for (key, value) in &self.overrides {
+            obj.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+/// Which Anthropic request features a local target supports. Everything is
+/// supported by default; set a flag to `false` to strip that feature.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelCapabilities {
+    /// `thinking` parameter and `thinking` / `redacted_thinking` blocks.
+    pub thinking: bool,
+    /// `cache_control` markers on system, tool and content blocks.
+    pub cache_control: bool,
+    /// Top-level `context_management`.
+    pub context_management: bool,
+    /// `server_tool_use` / `*_tool_result` blocks and Anthropic-defined tools
+    /// (tool definitions without an `input_schema`).
+    pub server_tools: bool,
+    /// `citations` on text and document blocks.
+    pub citations: bool,
+    /// `document` blocks. Downgraded to text when unsupported.
+    pub documents: bool,
+    /// `image` blocks. Replaced by a text placeholder when unsupported.
+    pub images: bool,
+    /// The `anthropic-beta` request header.
+    pub anthropic_beta: bool,
+    /// `/v1/messages/count_tokens`. Asked only for `anthropic` protocol
+    /// targets when the model's `tokenizer` is unset or fails to load.
+    pub count_tokens: bool,
+}
+
+impl Default for ModelCapabilities {
+    fn default() -> Self {
+        Self {
+            thinking: true,
+            cache_control: true,
+            context_management: true,
+            server_tools: true,
+            citations: true,
+            documents: true,
+            images: true,
+            anthropic_beta: true,
+            count_tokens: true,
+        }
+    }
+}
+
+impl ModelDef {
+    /// Parse the `fallback` chain into typed hops.
+    pub fn fallback_hops(&self) -> Vec<FallbackHop> {
+        self.fallback
+            .iter()
+            .map(|s| FallbackHop::parse(s))
+            .collect()
+    }
+
+    /// Parse `overflow_to` the same way as a fallback entry.
+    pub fn overflow_hop(&self) -> Option<FallbackHop> {
+        self.overflow_to.as_deref().map(FallbackHop::parse)
+    }
+}
+
+/// A single entry in a model's fallback chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FallbackHop {
+    /// Retry on another locally-registered model.
+    Local(String),
+    /// Retry on the Anthropic passthrough, optionally rewriting the model.
+    Anthropic { model: Option<String> },
+}
+
+impl FallbackHop {
+    pub fn parse(s: &str) -> Self {
+        match s.strip_prefix("anthropic") {
+            Some("") => FallbackHop::Anthropic { model: None },
+            Some(rest) => match rest.strip_prefix(':') {
+                Some(model) if !model.is_empty() => FallbackHop::Anthropic {
+                    model: Some(model.to_string()),
+                },
+                _ => FallbackHop::Local(s.to_string()),
+            },
+            None => FallbackHop::Local(s.to_string()),
+        }
+    }
+}
+
+/// A single replica endpoint from `[[models.endpoints]]`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointDef {
+    /// Base URL of the replica (e.g. `http://replica-0:8000`).
+    pub url: String,
+
+    /// Relative weight for the `weighted` strategy (ignored otherwise).
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// Strategy for spreading requests across a model's replica endpoints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadBalanceStrategy {
+    /// Cycle through endpoints in order.
+    #[default]
+    RoundRobin,
+    /// Pick the endpoint with the fewest requests currently in flight.
+    LeastInFlight,
+    /// Distribute requests proportionally to each endpoint's `weight`.
+    Weighted,
+}
+
+impl LoadBalanceStrategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoadBalanceStrategy::RoundRobin => "round_robin",
+            LoadBalanceStrategy::LeastInFlight => "least_in_flight",
+            LoadBalanceStrategy::Weighted => "weighted",
+        }
+    }
+}
+
+/// A model aliasing rule from `[[aliases]]` in TOML.
+///
+/// Rules are checked in order for any model ID that isn't registered exactly;
+/// the first match wins. Exactly one of `pattern` or `regex` must be set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AliasRule {
+    /// Glob matched against the whole model ID (`*` = any run, `?` = one char),
+    /// e.g. `claude-*-haiku-*`.
+    #[serde(default)]
+    pub pattern: Option<String>,
+
+    /// Regular expression matched against the model ID (unanchored unless the
+    /// expression anchors itself).
+    #[serde(default)]
+    pub regex: Option<String>,
+
+    /// A registered local model ID, or `"anthropic"` to keep the request on
+    /// the passthrough.
+    pub to: String,
+}
+
+/// Alias `to` value that keeps a request on the Anthropic passthrough.
+pub const ALIAS_ANTHROPIC: &str = "anthropic";
+
+impl AliasRule {
+    /// Compile the rule's matcher. Globs are translated to an anchored regex.
+    pub fn compile(&self) -> Result<regex::Regex, String> {
+        match (&self.pattern, &self.regex) {
+            (Some(glob), None) => {
+                regex::Regex::new(&glob_to_regex(glob)).map_err(|e| e.to_string())
+            }
+            (None, Some(re)) => regex::Regex::new(re).map_err(|e| e.to_string()),
+            _ => Err("exactly one of `pattern` or `regex` must be set".to_string()),
+        }
+    }
+
+    /// The pattern text, for logs and spans.
+    pub fn describe(&self) -> &str {
+        self.pattern
+            .as_deref()
+            .or(self.regex.as_deref())
+            .unwrap_or("")
+    }
+}
+
+/// Translate a `*`/`?` glob into an anchored regex.
+pub fn glob_to_regex(glob: &str) -> String {
+    let mut out = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => out.push_str(".*"),
+            '?' => out.push('.'),
+            c => out.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    out.push('$');
+    out
 }
 
 /// Routing decision for a single request.
@@ -36,78 +423,312 @@ pub struct ModelDef {
 pub enum RouteTarget {
     /// Route to a local model at this base URL.
     Local {
-        model_def: ModelDef,
+        model_def: Arc<ModelDef>,
         target_url: String,
+        /// In-flight reservation on the chosen endpoint. Hold it until the
+        /// response has been fully streamed.
+        lease: EndpointLease,
     },
     /// Route to Anthropic passthrough.
     Anthropic,
 }
 
+/// An in-flight reservation on a single endpoint.
+///
+/// Increments the endpoint's in-flight counter on creation and decrements it
+/// on drop, so `least_in_flight` sees requests until their body completes.
+pub struct EndpointLease {
+    in_flight: Arc<AtomicUsize>,
+    /// Index of the chosen endpoint within the model's endpoint list.
+    pub endpoint_index: usize,
+    /// Number of endpoints the choice was made from.
+    pub endpoint_count: usize,
+    /// Strategy used to make the choice.
+    pub strategy: LoadBalanceStrategy,
+    /// In-flight count on the chosen endpoint before this request was added.
+    pub in_flight_at_pick: usize,
+}
+
+impl Drop for EndpointLease {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 /// Immutable registry of locally-served models.
 ///
 /// Wrapped in `Arc` and stored in `AppState`. No locks — the HashMap is
-/// read-only after construction.
+/// read-only after construction; endpoint selection uses atomics only.
 #[derive(Clone)]
 pub struct ModelRegistry {
     inner: Arc<Inner>,
 }
 
 struct Inner {
-    /// Map from model ID to definition.
-    models: HashMap<String, ModelDef>,
-    /// Fallback target URL from `--target-url` for models that don't specify one.
-    default_target_url: Option<String>,
+    /// Map from model ID to definition and its endpoint pool.
+    models: HashMap<String, ModelEntry>,
+    /// Compiled `[[aliases]]`, in config order.
+    aliases: Vec<(regex::Regex, AliasRule)>,
+    /// Per-URL in-flight counters, handed on to the next registry.
+    counters: HashMap<String, Arc<AtomicUsize>>,
+}
+
+struct ModelEntry {
+    def: Arc<ModelDef>,
+    /// None when the model has no URL of its own and there is no default.
+    pool: Option<EndpointPool>,
+    /// `auth`, resolved when the registry was built.
+    auth: Result<HeaderMap, String>,
+}
+
+/// The set of endpoints a model can be served from.
+struct EndpointPool {
+    strategy: LoadBalanceStrategy,
+    endpoints: Vec<PoolEndpoint>,
+    /// Monotonic request counter driving round-robin and weighted selection.
+    cursor: AtomicUsize,
+    total_weight: u64,
+}
+
+struct PoolEndpoint {
+    url: String,
+    weight: u32,
+    /// Shared per-URL counter, so replicas listed under several models
+    /// report their real load.
+    in_flight: Arc<AtomicUsize>,
 }
 
 impl ModelRegistry {
-    /// Build a new registry from config model definitions and a default target URL.
-    pub fn new(models: Vec<ModelDef>, default_target_url: Option<String>) -> Self {
-        let map: HashMap<String, ModelDef> = models
+    /// Build a new registry from config model definitions, alias rules and a
+    /// default target URL, reusing `previous` in-flight counters for URLs
+    /// that are still served.
+    pub fn new(
+        models: Vec<ModelDef>,
+        aliases: &[AliasRule],
+        default_target_url: Option<String>,
+        previous: Option<&ModelRegistry>,
+    ) -> Self {
+        let fresh = HashMap::new();
+        let previous = previous.map_or(&fresh, |p| &p.inner.counters);
+        let mut counters: HashMap<String, Arc<AtomicUsize>> = HashMap::new();
+        let mut counter_for = |url: &str| {
+            counters
+                .entry(url.to_string())
+                .or_insert_with(|| {
+                    previous
+                        .get(url)
+                        .cloned()
+                        .unwrap_or_else(|| Arc::new(AtomicUsize::new(0)))
+                })
+                .clone()
+        };
+
+        let map: HashMap<String, ModelEntry> = models
             .into_iter()
-            .map(|m| (m.id.clone(), m))
+            .map(|def| {
+                let urls: Vec<(String, u32)> = if !def.endpoints.is_empty() {
+                    def.endpoints
+                        .iter()
+                        .map(|e| (e.url.clone(), e.weight))
+                        .collect()
+                } else {
+                    def.target_url
+                        .clone()
+                        .or_else(|| default_target_url.clone())
+                        .map(|u| vec![(u, 1)])
+                        .unwrap_or_default()
+                };
+
+                let pool = if urls.is_empty() {
+                    None
+                } else {
+                    let endpoints: Vec<PoolEndpoint> = urls
+                        .into_iter()
+                        .map(|(url, weight)| PoolEndpoint {
+                            in_flight: counter_for(&url),
+                            url,
+                            weight,
+                        })
+                        .collect();
+                    let total_weight = endpoints.iter().map(|e| e.weight as u64).sum();
+                    Some(EndpointPool {
+                        strategy: def.strategy,
+                        endpoints,
+                        cursor: AtomicUsize::new(0),
+                        total_weight,
+                    })
+                };
+
+                (
+                    def.id.clone(),
+                    ModelEntry {
+                        auth: def.auth.resolve(),
+                        def: Arc::new(def),
+                        pool,
+                    },
+                )
+            })
+            .collect();
+
+        // Invalid rules are rejected by config validation; skip defensively.
+        let aliases = aliases
+            .iter()
+            .filter_map(|rule| match rule.compile() {
+                Ok(re) => Some((re, rule.clone())),
+                Err(e) => {
+                    tracing::warn!(alias = rule.describe(), error = %e, "Skipping invalid alias rule");
+                    None
+                }
+            })
             .collect();
 
+        // Keep busy counters of URLs no longer served, in case a later
+        // change brings them back while those requests are still running.
+        for (url, counter) in previous {
+            if counter.load(Ordering::Relaxed) > 0 {
+                counters
+                    .entry(url.clone())
+                    .or_insert_with(|| counter.clone());
+            }
+        }
+
         Self {
             inner: Arc::new(Inner {
                 models: map,
-                default_target_url,
+                aliases,
+                counters,
             }),
         }
     }
 
+    /// Build the registry from TOML `[[models]]` plus backward-compat
+    /// synthesis from CLI args.
+    ///
+    /// When no `[[models]]` are configured but `--model` is set, synthesize a
+    /// single model entry (served by `--target-url`) so the old CLI-only
+    /// workflow keeps working.
+    ///
+    /// In-flight counters are carried over from `previous`, so requests
+    /// leased before a reload still count against their endpoints.
+    pub fn from_config(config: &ProxyConfig, previous: Option<&ModelRegistry>) -> Self {
+        let mut model_defs = config.models.clone();
+        if model_defs.is_empty() {
+            if let Some(ref model_id) = config.model_override {
+                model_defs.push(ModelDef {
+                    id: model_id.clone(),
+                    target_url: None, // will use default_target_url
+                    ..Default::default()
+                });
+            }
+        }
+        Self::new(
+            model_defs,
+            &config.aliases,
+            config.target.url.clone(),
+            previous,
+        )
+    }
+
+    /// Map a requested model ID to the model ID that will serve it.
+    ///
+    /// Exact registry matches win; otherwise the first matching `[[aliases]]`
+    /// rule decides. Returns the resolved ID (the input itself when it stays
+    /// on Anthropic) and the matching alias rule, if any.
+    pub fn canonical_model<'a>(&'a self, model_id: &'a str) -> (&'a str, Option<&'a AliasRule>) {
+        if self.inner.models.contains_key(model_id) {
+            return (model_id, None);
+        }
+        match self
+            .inner
+            .aliases
+            .iter()
+            .find(|(re, _)| re.is_match(model_id))
+        {
+            Some((_, rule)) if rule.to == ALIAS_ANTHROPIC => (model_id, Some(rule)),
+            Some((_, rule)) => (rule.to.as_str(), Some(rule)),
+            None => (model_id, None),
+        }
+    }
+
+    /// Whether `model_id` is exactly a routable local model (aliases ignored).
+    pub fn is_local(&self, model_id: &str) -> bool {
+        self.inner
+            .models
+            .get(model_id)
+            .is_some_and(|e| e.pool.is_some())
+    }
+
     /// Resolve a model name to a routing target.
     ///
-    /// Returns `Local` if the model is in the registry (with its target URL,
-    /// falling back to the default target URL). Returns `Anthropic` otherwise.
-    pub fn resolve(&self, model_id: &str) -> RouteTarget {
+    /// The name is first mapped through `canonical_model` (exact match, then
+    /// aliases). Returns `Local` if that lands on a registered model, with one
+    /// endpoint picked from its pool (its own endpoints or target URL, falling
+    /// back to the default target URL). Endpoints marked down in `health` are
+    /// skipped while another endpoint is up. Returns `Anthropic` otherwise.
+    pub fn resolve(&self, model_id: &str, health: &TargetHealth) -> RouteTarget {
+        let (model_id, _) = self.canonical_model(model_id);
         match self.inner.models.get(model_id) {
-            Some(def) => {
-                let url = def
-                    .target_url
-                    .as_deref()
-                    .or(self.inner.default_target_url.as_deref());
-
-                match url {
-                    Some(u) => RouteTarget::Local {
-                        model_def: def.clone(),
-                        target_url: u.to_string(),
-                    },
-                    None => {
-                        tracing::warn!(
-                            model = model_id,
-                            "Local model has no target_url and no --target-url default; falling back to Anthropic"
-                        );
-                        RouteTarget::Anthropic
+            Some(entry) => match entry.pool {
+                Some(ref pool) => {
+                    let (target_url, lease) = pool.pick(health);
+                    RouteTarget::Local {
+                        model_def: entry.def.clone(),
+                        target_url,
+                        lease,
                     }
                 }
-            }
+                None => {
+                    tracing::warn!(
+                        model = model_id,
+                        "Local model has no target_url and no --target-url default; falling back to Anthropic"
+                    );
+                    RouteTarget::Anthropic
+                }
+            },
             None => RouteTarget::Anthropic,
         }
     }
 
+    /// Definition of a registered model, by exact ID.
+    pub fn get(&self, model_id: &str) -> Option<&ModelDef> {
+        self.inner.models.get(model_id).map(|e| e.def.as_ref())
+    }
+
+    /// Upstream credential headers of a registered model, read from their
+    /// env vars and files when this registry was built. Unknown models have
+    /// none.
+    pub fn auth(&self, model_id: &str) -> Result<HeaderMap, String> {
+        self.inner
+            .models
+            .get(model_id)
+            .map_or_else(|| Ok(HeaderMap::new()), |e| e.auth.clone())
+    }
+
     /// List all locally-registered models (for /v1/models).
     pub fn list_models(&self) -> Vec<&ModelDef> {
-        self.inner.models.values().collect()
+        self.inner.models.values().map(|e| e.def.as_ref()).collect()
+    }
+
+    /// Every distinct target URL with the IDs of the models it serves,
+    /// sorted by URL.
+    pub fn targets(&self) -> Vec<(String, Vec<String>)> {
+        let mut by_url: std::collections::BTreeMap<String, Vec<String>> = Default::default();
+        for (id, entry) in &self.inner.models {
+            for endpoint in entry.pool.iter().flat_map(|p| &p.endpoints) {
+                by_url
+                    .entry(endpoint.url.clone())
+                    .or_default()
+                    .push(id.clone());
+            }
+        }
+        by_url
+            .into_iter()
+            .map(|(url, mut models)| {
+                models.sort();
+                models.dedup();
+                (url, models)
+            })
+            .collect()
     }
 
     /// Number of registered local models.
@@ -116,6 +737,66 @@ impl ModelRegistry {
     }
 }
 
+impl EndpointPool {
+    /// Pick an endpoint according to the pool's strategy and reserve it.
+    ///
+    /// Endpoints that are down are passed over for the next one up; if every
+    /// endpoint is down the strategy's choice stands.
+    fn pick(&self, health: &TargetHealth) -> (String, EndpointLease) {
+        let n = self.endpoints.len();
+        let tick = self.cursor.fetch_add(1, Ordering::Relaxed);
+        let down = |i: usize| health.is_down(&self.endpoints[i].url);
+
+        let index = match self.strategy {
+            LoadBalanceStrategy::RoundRobin => tick % n,
+            LoadBalanceStrategy::LeastInFlight => {
+                // Start the scan at a rotating offset so ties are spread
+                // across endpoints instead of always landing on the first.
+                (0..n)
+                    .map(|i| (tick + i) % n)
+                    .min_by_key(|&i| (down(i), self.endpoints[i].in_flight.load(Ordering::Relaxed)))
+                    .unwrap_or(0)
+            }
+            LoadBalanceStrategy::Weighted => {
+                if self.total_weight == 0 {
+                    tick % n
+                } else {
+                    let mut slot = tick as u64 % self.total_weight;
+                    self.endpoints
+                        .iter()
+                        .position(|e| {
+                            if slot < e.weight as u64 {
+                                true
+                            } else {
+                                slot -= e.weight as u64;
+                                false
+                            }
+                        })
+                        .unwrap_or(0)
+                }
+            }
+        };
+
+        let index = (0..n)
+            .map(|i| (index + i) % n)
+            .find(|&i| !down(i))
+            .unwrap_or(index);
+
+        let endpoint = &self.endpoints[index];
+        let in_flight_at_pick = endpoint.in_flight.fetch_add(1, Ordering::Relaxed);
+        (
+            endpoint.url.clone(),
+            EndpointLease {
+                in_flight: endpoint.in_flight.clone(),
+                endpoint_index: index,
+                endpoint_count: n,
+                strategy: self.strategy,
+                in_flight_at_pick,
+            },
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -127,13 +808,14 @@ mod tests {
                 id: "glm-5-fp8".into(),
                 display_name: Some("GLM-5 FP8".into()),
                 target_url: Some("http://glm:8000".into()),
-                context_window: None,
-                max_output_tokens: None,
+                ..Default::default()
             }],
+            &[],
+            None,
             None,
         );
 
-        match reg.resolve("glm-5-fp8") {
+        match reg.resolve("glm-5-fp8", &TargetHealth::new()) {
             RouteTarget::Local { target_url, .. } => {
                 assert_eq!(target_url, "http://glm:8000");
             }
@@ -148,13 +830,14 @@ mod tests {
                 id: "glm-5-fp8".into(),
                 display_name: None,
                 target_url: None,
-                context_window: None,
-                max_output_tokens: None,
+                ..Default::default()
             }],
+            &[],
             Some("http://default:8000".into()),
+            None,
         );
 
-        match reg.resolve("glm-5-fp8") {
+        match reg.resolve("glm-5-fp8", &TargetHealth::new()) {
             RouteTarget::Local { target_url, .. } => {
                 assert_eq!(target_url, "http://default:8000");
             }
@@ -169,13 +852,14 @@ mod tests {
                 id: "glm-5-fp8".into(),
                 display_name: None,
                 target_url: Some("http://glm:8000".into()),
-                context_window: None,
-                max_output_tokens: None,
+                ..Default::default()
             }],
+            &[],
+            None,
             None,
         );
 
-        match reg.resolve("claude-sonnet-4-20250514") {
+        match reg.resolve("claude-sonnet-4-20250514", &TargetHealth::new()) {
             RouteTarget::Anthropic => {}
             RouteTarget::Local { .. } => panic!("expected Anthropic"),
         }
@@ -188,13 +872,14 @@ mod tests {
                 id: "glm-5-fp8".into(),
                 display_name: None,
                 target_url: None,
-                context_window: None,
-                max_output_tokens: None,
+                ..Default::default()
             }],
+            &[],
             None, // no default either
+            None,
         );
 
-        match reg.resolve("glm-5-fp8") {
+        match reg.resolve("glm-5-fp8", &TargetHealth::new()) {
             RouteTarget::Anthropic => {}
             RouteTarget::Local { .. } => panic!("expected Anthropic fallback"),
         }
@@ -204,12 +889,355 @@ mod tests {
     fn list_models_returns_all() {
         let reg = ModelRegistry::new(
             vec![
-                ModelDef { id: "a".into(), display_name: None, target_url: None, context_window: None, max_output_tokens: None },
-                ModelDef { id: "b".into(), display_name: None, target_url: None, context_window: None, max_output_tokens: None },
+                ModelDef {
+                    id: "a".into(),
+                    ..Default::default()
+                },
+                ModelDef {
+                    id: "b".into(),
+                    ..Default::default()
+                },
             ],
+            &[],
+            None,
             None,
         );
         assert_eq!(reg.len(), 2);
         assert_eq!(reg.list_models().len(), 2);
     }
+
+    fn replicated(strategy: LoadBalanceStrategy, weights: &[u32]) -> ModelRegistry {
+        ModelRegistry::new(
+            vec![ModelDef {
+                id: "glm-5-fp8".into(),
+                endpoints: weights
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &weight)| EndpointDef {
+                        url: format!("http://replica-{i}:8000"),
+                        weight,
+                    })
+                    .collect(),
+                strategy,
+                ..Default::default()
+            }],
+            &[],
+            None,
+            None,
+        )
+    }
+
+    fn pick_url(reg: &ModelRegistry) -> String {
+        match reg.resolve("glm-5-fp8", &TargetHealth::new()) {
+            RouteTarget::Local { target_url, .. } => target_url,
+            RouteTarget::Anthropic => panic!("expected Local"),
+        }
+    }
+
+    #[test]
+    fn round_robin_cycles_endpoints() {
+        let reg = replicated(LoadBalanceStrategy::RoundRobin, &[1, 1, 1]);
+        let picks: Vec<String> = (0..4).map(|_| pick_url(&reg)).collect();
+        assert_eq!(
+            picks,
+            vec![
+                "http://replica-0:8000",
+                "http://replica-1:8000",
+                "http://replica-2:8000",
+                "http://replica-0:8000",
+            ]
+        );
+    }
+
+    #[test]
+    fn endpoints_take_precedence_over_target_url() {
+        let reg = ModelRegistry::new(
+            vec![ModelDef {
+                id: "glm-5-fp8".into(),
+                target_url: Some("http://single:8000".into()),
+                endpoints: vec![EndpointDef {
+                    url: "http://replica-0:8000".into(),
+                    weight: 1,
+                }],
+                ..Default::default()
+            }],
+            &[],
+            Some("http://default:8000".into()),
+            None,
+        );
+        assert_eq!(pick_url(&reg), "http://replica-0:8000");
+    }
+
+    #[test]
+    fn least_in_flight_avoids_busy_endpoint() {
+        let reg = replicated(LoadBalanceStrategy::LeastInFlight, &[1, 1]);
+
+        // Hold a lease on the first pick; the next two picks must avoid it
+        // until it is released.
+        let held = reg.resolve("glm-5-fp8", &TargetHealth::new());
+        let busy = match held {
+            RouteTarget::Local { ref target_url, .. } => target_url.clone(),
+            RouteTarget::Anthropic => panic!("expected Local"),
+        };
+        assert_ne!(pick_url(&reg), busy);
+        assert_ne!(pick_url(&reg), busy);
+
+        match reg.resolve("glm-5-fp8", &TargetHealth::new()) {
+            RouteTarget::Local { lease, .. } => {
+                assert_eq!(lease.endpoint_count, 2);
+                assert_eq!(lease.strategy, LoadBalanceStrategy::LeastInFlight);
+            }
+            RouteTarget::Anthropic => panic!("expected Local"),
+        }
+        drop(held);
+    }
+
+    #[test]
+    fn lease_drop_releases_in_flight() {
+        let reg = replicated(LoadBalanceStrategy::LeastInFlight, &[1]);
+        let first = reg.resolve("glm-5-fp8", &TargetHealth::new());
+        match reg.resolve("glm-5-fp8", &TargetHealth::new()) {
+            RouteTarget::Local { lease, .. } => assert_eq!(lease.in_flight_at_pick, 1),
+            RouteTarget::Anthropic => panic!("expected Local"),
+        }
+        drop(first);
+        match reg.resolve("glm-5-fp8", &TargetHealth::new()) {
+            RouteTarget::Local { lease, .. } => assert_eq!(lease.in_flight_at_pick, 0),
+            RouteTarget::Anthropic => panic!("expected Local"),
+        }
+    }
+
+    #[test]
+    fn weighted_distributes_by_weight() {
+        let reg = replicated(LoadBalanceStrategy::Weighted, &[3, 1]);
+        let picks: Vec<String> = (0..8).map(|_| pick_url(&reg)).collect();
+        let heavy = picks
+            .iter()
+            .filter(|u| *u == "http://replica-0:8000")
+            .count();
+        assert_eq!(heavy, 6);
+        assert_eq!(picks.len() - heavy, 2);
+    }
+
+    #[test]
+    fn down_endpoints_are_skipped_while_another_is_up() {
+        let reg = replicated(LoadBalanceStrategy::RoundRobin, &[1, 1]);
+        let health = TargetHealth::new();
+        let settings = crate::config::HealthCheckConfig {
+            unhealthy_threshold: 1,
+            ..Default::default()
+        };
+        health.record_probe("http://replica-0:8000", Err("timeout".into()), &settings);
+
+        for _ in 0..4 {
+            match reg.resolve("glm-5-fp8", &health) {
+                RouteTarget::Local { target_url, .. } => {
+                    assert_eq!(target_url, "http://replica-1:8000")
+                }
+                RouteTarget::Anthropic => panic!("expected Local"),
+            }
+        }
+
+        // With every endpoint down the strategy's choice stands.
+        health.record_probe("http://replica-1:8000", Err("timeout".into()), &settings);
+        assert!(matches!(
+            reg.resolve("glm-5-fp8", &health),
+            RouteTarget::Local { .. }
+        ));
+    }
+
+    #[test]
+    fn strategy_deserializes_snake_case() {
+        let def: ModelDef = serde_json::from_value(serde_json::json!({
+            "id": "m",
+            "strategy": "least_in_flight",
+            "endpoints": [{"url": "http://a:8000"}, {"url": "http://b:8000", "weight": 4}]
+        }))
+        .unwrap();
+        assert_eq!(def.strategy, LoadBalanceStrategy::LeastInFlight);
+        assert_eq!(def.endpoints[0].weight, 1);
+        assert_eq!(def.endpoints[1].weight, 4);
+    }
+
+    #[test]
+    fn fallback_hops_parse() {
+        let def = ModelDef {
+            id: "glm-5-fp8".into(),
+            fallback: vec![
+                "glm-4-6".into(),
+                "anthropic".into(),
+                "anthropic:claude-sonnet-4-5".into(),
+                "anthropic-mirror".into(),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            def.fallback_hops(),
+            vec![
+                FallbackHop::Local("glm-4-6".into()),
+                FallbackHop::Anthropic { model: None },
+                FallbackHop::Anthropic {
+                    model: Some("claude-sonnet-4-5".into())
+                },
+                FallbackHop::Local("anthropic-mirror".into()),
+            ]
+        );
+    }
+
+    fn aliased(aliases: Vec<AliasRule>) -> ModelRegistry {
+        ModelRegistry::new(
+            vec![
+                ModelDef {
+                    id: "small-local".into(),
+                    target_url: Some("http://small:8000".into()),
+                    ..Default::default()
+                },
+                ModelDef {
+                    id: "big-local".into(),
+                    target_url: Some("http://big:8000".into()),
+                    ..Default::default()
+                },
+            ],
+            &aliases,
+            None,
+            None,
+        )
+    }
+
+    fn glob(pattern: &str, to: &str) -> AliasRule {
+        AliasRule {
+            pattern: Some(pattern.into()),
+            regex: None,
+            to: to.into(),
+        }
+    }
+
+    #[test]
+    fn glob_alias_routes_to_local_model() {
+        let reg = aliased(vec![glob("claude-*-haiku-*", "small-local")]);
+        let (resolved, rule) = reg.canonical_model("claude-3-5-haiku-20241022");
+        assert_eq!(resolved, "small-local");
+        assert_eq!(rule.map(|r| r.describe()), Some("claude-*-haiku-*"));
+
+        match reg.resolve("claude-3-5-haiku-20241022", &TargetHealth::new()) {
+            RouteTarget::Local { target_url, .. } => assert_eq!(target_url, "http://small:8000"),
+            RouteTarget::Anthropic => panic!("expected Local"),
+        }
+    }
+
+    #[test]
+    fn first_matching_alias_wins() {
+        let reg = aliased(vec![
+            glob("claude-opus-*", "anthropic"),
+            glob("claude-*", "big-local"),
+        ]);
+        assert_eq!(reg.canonical_model("claude-opus-4-6").0, "claude-opus-4-6");
+        assert!(matches!(
+            reg.resolve("claude-opus-4-6", &TargetHealth::new()),
+            RouteTarget::Anthropic
+        ));
+        assert_eq!(reg.canonical_model("claude-sonnet-4-5").0, "big-local");
+    }
+
+    #[test]
+    fn exact_match_beats_alias() {
+        let reg = aliased(vec![glob("*", "big-local")]);
+        assert_eq!(reg.canonical_model("small-local").0, "small-local");
+    }
+
+    #[test]
+    fn regex_alias_matches() {
+        let reg = aliased(vec![AliasRule {
+            pattern: None,
+            regex: Some("^claude-(sonnet|opus)-4".into()),
+            to: "big-local".into(),
+        }]);
+        assert_eq!(
+            reg.canonical_model("claude-sonnet-4-5-20250929").0,
+            "big-local"
+        );
+        let (resolved, rule) = reg.canonical_model("claude-3-7-sonnet");
+        assert_eq!(resolved, "claude-3-7-sonnet");
+        assert!(rule.is_none());
+    }
+
+    #[test]
+    fn glob_is_anchored_and_escaped() {
+        let re = glob("claude-3.5-*", "x").compile().unwrap();
+        assert!(re.is_match("claude-3.5-sonnet"));
+        assert!(!re.is_match("claude-3x5-sonnet"));
+        assert!(!re.is_match("my-claude-3.5-sonnet"));
+    }
+
+    #[test]
+    fn alias_needs_exactly_one_matcher() {
+        let rule = AliasRule {
+            pattern: Some("a*".into()),
+            regex: Some("a.*".into()),
+            to: "x".into(),
+        };
+        assert!(rule.compile().is_err());
+    }
+
+    #[test]
+    fn params_defaults_fill_gaps_and_overrides_win() {
+        let params: ModelParams = serde_json::from_value(serde_json::json!({
+            "defaults": {"temperature": 0.6, "top_k": 20, "max_tokens": 4096},
+            "overrides": {"top_p": 0.95, "chat_template_kwargs": {"enable_thinking": true}}
+        }))
+        .unwrap();
+        let mut req = serde_json::json!({"temperature": 1.0, "max_tokens": null, "top_p": 0.5});
+        let obj = req.as_object_mut().unwrap();
+        params.apply_defaults(obj);
+        params.apply_overrides(obj);
+        assert_eq!(
+            req,
+            serde_json::json!({
+                "temperature": 1.0,
+                "top_k": 20,
+                "max_tokens": 4096,
+                "top_p": 0.95,
+                "chat_template_kwargs": {"enable_thinking": true}
+            })
+        );
+    }
+
+    #[test]
+    fn auth_reads_files_and_rejects_conflicts() {
+        let path = std::env::temp_dir().join(format!("cc-proxy-token-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "tok\n").unwrap();
+        let auth = ModelAuth {
+            bearer_token_file: Some(path.to_string_lossy().into_owned()),
+            headers: [("x-tenant".to_string(), "team-a".to_string())].into(),
+            ..Default::default()
+        };
+        let headers = auth.resolve().unwrap();
+        assert_eq!(headers["authorization"], "Bearer tok");
+        assert_eq!(headers["x-tenant"], "team-a");
+
+        // The registry reads secrets once, when it is built
+        let registry = ModelRegistry::new(
+            vec![ModelDef {
+                id: "m".into(),
+                auth: auth.clone(),
+                ..Default::default()
+            }],
+            &[],
+            None,
+            None,
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(registry.auth("m").unwrap()["authorization"], "Bearer tok");
+        assert!(auth.resolve().unwrap_err().starts_with("cannot read"));
+
+        let both = ModelAuth {
+            api_key_env: Some("CC_PROXY_TEST_KEY".into()),
+            api_key_file: Some("/dev/null".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            both.resolve().unwrap_err(),
+            "set only one of api_key_env and api_key_file"
+        );
+    }
 }
@@ -337,18 +337,24 @@ pub fn set_response_attributes(span: &Span, response_bytes: &[u8], is_streaming:
 /// Parsed response data extracted from either JSON or SSE response bodies.
 /// Separated from span-setting so it can be tested independently.
 #[derive(Debug, Default, PartialEq)]
-struct ParsedResponse {
-    role: Option<String>,
-    text_content: String,
-    tool_calls: Vec<ParsedToolCall>,
-    input_tokens: Option<i64>,
-    output_tokens: Option<i64>,
+pub struct ParsedResponse {
+    pub role: Option<String>,
+    pub text_content: String,
+    pub tool_calls: Vec<ParsedToolCall>,
+    pub input_tokens: Option<i64>,
+    pub output_tokens: Option<i64>,
+    pub stop_reason: Option<String>,
 }
 
 #[derive(Debug, PartialEq)]
-struct ParsedToolCall {
-    name: String,
-    arguments: String,
+pub struct ParsedToolCall {
+    pub name: String,
+    pub arguments: String,
+}
+
+/// Parse an Anthropic response body, JSON or SSE (detected from the body).
+pub fn parse_response(response_bytes: &[u8]) -> Option<ParsedResponse> {
+    parse_nonstreaming_response(response_bytes).or_else(|| parse_streaming_response(response_bytes))
 }
 
 /// Parse a non-streaming Anthropic JSON response body.
@@ -400,6 +406,10 @@ fn parse_nonstreaming_response(response_bytes: &[u8]) -> Option<ParsedResponse>
         tool_calls,
         input_tokens,
         output_tokens,
+        stop_reason: body
+            .get("stop_reason")
+            .and_then(|v| v.as_str())
+            .map(String::from),
     })
 }
 
@@ -410,6 +420,7 @@ fn parse_streaming_response(response_bytes: &[u8]) -> Option<ParsedResponse> {
     let mut input_tokens: Option<i64> = None;
     let mut output_tokens: Option<i64> = None;
     let mut role: Option<String> = None;
+    let mut stop_reason: Option<String> = None;
 
     struct ContentBlock {
         block_type: String,
@@ -500,6 +511,13 @@ fn parse_streaming_response(response_bytes: &[u8]) -> Option<ParsedResponse> {
                 }
             }
             Some("message_delta") => {
+                if let Some(sr) = data
+                    .get("delta")
+                    .and_then(|d| d.get("stop_reason"))
+                    .and_then(|v| v.as_str())
+                {
+                    stop_reason = Some(sr.to_string());
+                }
                 if let Some(usage) = data.get("usage") {
                     if let Some(ot) = usage.get("output_tokens").and_then(|v| v.as_i64()) {
                         output_tokens = Some(ot);
@@ -520,10 +538,8 @@ fn parse_streaming_response(response_bytes: &[u8]) -> Option<ParsedResponse> {
 
     for block in &blocks {
         match block.block_type.as_str() {
-            "text" => {
-                if !block.text.is_empty() {
-                    text_parts.push(block.text.as_str());
-                }
+            "text" if !block.text.is_empty() => {
+                text_parts.push(block.text.as_str());
             }
             "tool_use" => {
                 tool_calls.push(ParsedToolCall {
@@ -541,6 +557,7 @@ fn parse_streaming_response(response_bytes: &[u8]) -> Option<ParsedResponse> {
         tool_calls,
         input_tokens,
         output_tokens,
+        stop_reason,
     })
 }
 
@@ -715,6 +732,7 @@ mod tests {
         assert_eq!(parsed.tool_calls.len(), 1);
         assert_eq!(parsed.tool_calls[0].name, "bash");
         assert_eq!(parsed.tool_calls[0].arguments, r#"{"cmd": "ls"}"#);
+        assert_eq!(parsed.stop_reason.as_deref(), Some("tool_use"));
         assert_eq!(parsed.input_tokens, Some(50));
         assert_eq!(parsed.output_tokens, Some(20));
     }
@@ -1,104 +1,314 @@
-//! Compare dispatcher: fire-and-forget Anthropic-format POST to target URL.
+//! Compare dispatcher: fire-and-forget copies of a request to one or more
+//! compare targets.
 //!
-//! The target speaks Anthropic API format natively — no conversion
-//! needed. The same rewritten request bytes are forwarded directly to
-//! `target_url/v1/messages`.
+//! Each `CompareTarget` is a candidate model (or the default target) with its
+//! own URL, protocol, semaphore and timeout. Anthropic-format targets get the
+//! rewritten request bytes at `/v1/messages`; OpenAI-format targets get them
+//! converted to `/v1/chat/completions` and their responses are translated
+//! back before they are summarized.
 //!
 //! All compare requests are fire-and-forget: failures never affect the primary
 //! path. Errors are logged as warnings and never propagated.
+//!
+//! Each compare response is paired with the primary response the client
+//! received (joined by correlation ID and model in `CompareJoin`) and diffed;
+//! the diff is emitted as a `compare_result` span and log record, appended
+//! to the `CompareStore` and added to the rolling `CompareStats`. Pairs of
+//! requests sampled for judging are scored by the `Judge` before they are
+//! emitted and stored.
+//!
+//! Both compare spans have no `tracing` parent (the request span would stay
+//! open until they finish) but are given the `proxy_request` span as their
+//! OpenTelemetry parent, so they land in the primary request's trace.
 
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use bytes::Bytes;
-use tokio::sync::Semaphore;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::Context;
 use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
+use super::compare_queue::{CompareQueues, QueueStats};
+use super::compare_stats::CompareStats;
+use super::compare_store::{CompareRecord, CompareStore, RequestSummary};
 use super::correlation::CORRELATION_HEADER;
+use super::diff::{self, CompareDiff, ResponseSummary};
+use super::judge::{Judge, JudgeRequest};
+use super::primary::{self, ResponseObserver};
+use crate::config::CompareConfig;
+use crate::convert::anthropic_to_openai::anthropic_to_openai;
+use crate::models::{ModelRegistry, RouteTarget, TargetProtocol};
 use crate::openinference;
+use crate::targets::{CircuitBreakers, TargetHealth};
 
-/// Dispatches compare requests to a target endpoint that speaks Anthropic format.
+/// Where one compare copy of a request goes.
+pub struct CompareTarget {
+    /// Model the copy is sent as; pairs are stored and diffed per model.
+    pub model: String,
+    pub endpoint: CompareEndpoint,
+    pub protocol: TargetProtocol,
+    /// Anthropic-format request body, already rewritten for the target
+    /// (model, params, capabilities). Converted here for OpenAI targets.
+    pub body: Bytes,
+    /// The target's resolved credentials, if any.
+    pub auth: reqwest::header::HeaderMap,
+    pub timeout: Duration,
+    /// Compare requests in flight to this model before more are queued.
+    pub max_concurrent: usize,
+}
+
+/// Where a compare copy is sent.
+pub enum CompareEndpoint {
+    /// Base URL of the default target.
+    Url(String),
+    /// One of the local model's endpoints, picked (and reserved until the
+    /// compare request finishes) once the request leaves its queue.
+    Local {
+        registry: ModelRegistry,
+        targets: Arc<TargetHealth>,
+    },
+}
+
+/// Dispatches compare requests to compare targets.
 #[derive(Clone)]
 pub struct CompareDispatcher {
     client: reqwest::Client,
-    semaphore: Arc<Semaphore>,
-    target_url: String,
     timeout: Duration,
+    max_concurrent: usize,
+    queues: Arc<CompareQueues>,
+    breakers: Arc<CircuitBreakers>,
+    join: CompareJoin,
+    judge: Arc<Judge>,
 }
 
 impl CompareDispatcher {
     /// Create a new dispatcher.
     ///
-    /// - `target_url`: base URL of the target (e.g. `https://model.example.com`)
-    /// - `timeout_secs`: per-request timeout in seconds
-    /// - `max_concurrent`: semaphore capacity for in-flight compare requests
+    /// - `timeout_secs`: default per-request timeout in seconds
+    /// - `max_concurrent`: default capacity for in-flight compare requests per model
     /// - `client`: shared reqwest client
+    /// - `breakers`: circuit breakers shared with the primary path
+    /// - `store`: where completed compare pairs are recorded, if anywhere
+    /// - `stats`: rolling aggregates of completed compare pairs
     pub fn new(
-        target_url: String,
         timeout_secs: u64,
         max_concurrent: usize,
         client: reqwest::Client,
+        breakers: Arc<CircuitBreakers>,
+        store: Option<Arc<CompareStore>>,
+        stats: Arc<CompareStats>,
     ) -> Self {
-        let semaphore = Arc::new(Semaphore::new(max_concurrent));
+        let judge = Arc::new(Judge::new(client.clone()));
         Self {
             client,
-            semaphore,
-            target_url,
             timeout: Duration::from_secs(timeout_secs),
+            max_concurrent,
+            queues: Arc::new(CompareQueues::default()),
+            breakers,
+            // A primary stream can outlive the compare timeout; pairs still
+            // incomplete well after that (client disconnected) are dropped.
+            join: CompareJoin::new(
+                Duration::from_secs(timeout_secs.saturating_mul(2).max(60)),
+                store,
+                stats,
+                judge.clone(),
+            ),
+            judge,
+        }
+    }
+
+    /// The default target: `body` sent as `model` to `target_url` (the
+    /// current `target.url`) in Anthropic format, with the `[target]` timeout
+    /// and concurrency.
+    pub fn default_target(
+        &self,
+        target_url: String,
+        model: String,
+        body: Bytes,
+        auth: reqwest::header::HeaderMap,
+    ) -> CompareTarget {
+        CompareTarget {
+            model,
+            endpoint: CompareEndpoint::Url(target_url),
+            protocol: TargetProtocol::Anthropic,
+            body,
+            auth,
+            timeout: self.timeout,
+            max_concurrent: self.max_concurrent,
         }
     }
 
-    /// Fire-and-forget: spawns a tokio task to POST the request bytes to the
-    /// target and returns immediately. Logs `total_latency_ms` (includes full
-    /// body read) alongside the existing `latency_ms` (TTFB).
-    pub fn dispatch(&self, request_bytes: Bytes, correlation_id: String) {
+    /// Observer for the primary response of a dispatched request, completing
+    /// its compare pairs.
+    pub fn primary_observer(&self, correlation_id: String) -> ResponseObserver {
+        let join = self.join.clone();
+        Box::new(move |summary| {
+            join.record_primary(&correlation_id, summary);
+        })
+    }
+
+    /// Queue depth, in-flight count and drop counts per compare model.
+    pub fn queue_stats(&self) -> BTreeMap<String, QueueStats> {
+        self.queues.snapshot()
+    }
+
+    /// Queue depth, in-flight count and drop counts per judge model.
+    pub fn judge_queue_stats(&self) -> BTreeMap<String, QueueStats> {
+        self.judge.queue_stats()
+    }
+
+    /// Fire-and-forget: spawns a tokio task to POST the request to `target`
+    /// and returns immediately. Logs `total_latency_ms` (includes full body
+    /// read) alongside the existing `latency_ms` (TTFB). `request` describes
+    /// the request in the stored compare record; `config` sizes the queue
+    /// the request waits in when its model is at capacity. With `judge`,
+    /// the completed pair is scored before it is emitted and stored.
+    pub fn dispatch(
+        &self,
+        target: CompareTarget,
+        correlation_id: String,
+        request: RequestSummary,
+        config: &CompareConfig,
+        judge: Option<Arc<JudgeRequest>>,
+    ) {
         let client = self.client.clone();
-        let semaphore = self.semaphore.clone();
-        let url = format!("{}/v1/messages", self.target_url);
-        let timeout = self.timeout;
+        let queues = self.queues.clone();
+        let queue_depth = config.queue_depth;
+        let queue_max_wait = Duration::from_secs(config.queue_max_wait_secs);
+        let breakers = self.breakers.clone();
+        let join = self.join.clone();
+        // Called inside `proxy_request`: compare spans join its trace
+        let origin = RequestOrigin {
+            primary_trace: tracing::Span::current().context(),
+            judge,
+        };
+        join.expect(&correlation_id, request, &origin);
+        let primary_trace = origin.primary_trace;
 
         tokio::spawn(async move {
+            let CompareTarget {
+                model,
+                endpoint,
+                protocol,
+                body: request_bytes,
+                auth,
+                timeout,
+                max_concurrent,
+            } = target;
             let span = tracing::info_span!(
                 "compare_request",
                 correlation_id = %correlation_id,
+                model = %model,
                 latency_ms = tracing::field::Empty,
                 total_latency_ms = tracing::field::Empty,
                 status = tracing::field::Empty,
+                queue_wait_ms = tracing::field::Empty,
+                dropped = tracing::field::Empty,
+                primary_trace_id = tracing::field::Empty,
             );
+            link_primary_trace(&span, &primary_trace);
 
             async {
                 // Set OpenInference request attributes (kind, model, input
                 // messages, tools, invocation params) from the request JSON.
                 // This runs inside .instrument(span) so Span::current() is the
                 // properly-entered compare_request span.
-                if let Ok(parsed) = serde_json::from_slice::<serde_json::Value>(&request_bytes) {
-                    openinference::set_request_attributes(
-                        &tracing::Span::current(),
-                        &parsed,
-                    );
+                let parsed = serde_json::from_slice::<serde_json::Value>(&request_bytes).ok();
+                if let Some(ref parsed) = parsed {
+                    openinference::set_request_attributes(&tracing::Span::current(), parsed);
                 }
 
-                // Non-blocking acquire — drop if at capacity
-                let _permit = match semaphore.try_acquire_owned() {
+                // Wait our turn if the model is at capacity
+                let queued_at = Instant::now();
+                let acquired = queues
+                    .acquire(&model, max_concurrent, queue_depth, queue_max_wait)
+                    .await;
+                let span = tracing::Span::current();
+                span.record("queue_wait_ms", queued_at.elapsed().as_millis() as u64);
+                let _permit = match acquired {
                     Ok(permit) => permit,
-                    Err(_) => {
+                    Err(reason) => {
+                        join.abandon(&correlation_id, &model);
+                        span.record("dropped", reason.as_str());
                         tracing::warn!(
                             correlation_id = %correlation_id,
-                            "Compare semaphore full, dropping request"
+                            model = %model,
+                            reason = reason.as_str(),
+                            "Compare queue dropped request"
                         );
                         return;
                     }
                 };
 
+                let (target_url, _lease) = match endpoint {
+                    CompareEndpoint::Url(url) => (url, None),
+                    CompareEndpoint::Local { registry, targets } => {
+                        match registry.resolve(&model, &targets) {
+                            RouteTarget::Local {
+                                target_url, lease, ..
+                            } => (target_url, Some(lease)),
+                            RouteTarget::Anthropic => {
+                                join.abandon(&correlation_id, &model);
+                                tracing::warn!(
+                                    correlation_id = %correlation_id,
+                                    model = %model,
+                                    "Compare model has no local endpoint, dropping request"
+                                );
+                                return;
+                            }
+                        }
+                    }
+                };
+                let url = format!("{}{}", target_url, protocol.messages_path());
+
+                // Don't pile compare traffic onto a target that is failing
+                if breakers.try_acquire(&target_url).is_err() {
+                    join.abandon(&correlation_id, &model);
+                    tracing::Span::current().record("status", 0_u16);
+                    tracing::warn!(
+                        correlation_id = %correlation_id,
+                        model = %model,
+                        "Compare target circuit open, dropping request"
+                    );
+                    return;
+                }
+
+                let wire_body = match protocol {
+                    TargetProtocol::Anthropic => request_bytes,
+                    TargetProtocol::Openai => {
+                        match parsed
+                            .as_ref()
+                            .ok_or_else(|| anyhow::anyhow!("request is not JSON"))
+                            .and_then(anthropic_to_openai)
+                            .and_then(|v| Ok(serde_json::to_vec(&v)?))
+                        {
+                            Ok(converted) => Bytes::from(converted),
+                            Err(e) => {
+                                join.abandon(&correlation_id, &model);
+                                tracing::warn!(
+                                    error = %e,
+                                    "Failed to convert compare request to OpenAI format"
+                                );
+                                return;
+                            }
+                        }
+                    }
+                };
+
                 let start = Instant::now();
 
                 let result = tokio::time::timeout(
                     timeout,
                     client
                         .post(&url)
+                        .timeout(timeout)
                         .header("content-type", "application/json")
                         .header(CORRELATION_HEADER, &correlation_id)
-                        .body(request_bytes)
+                        .headers(auth)
+                        .body(wire_body)
                         .send(),
                 )
                 .await;
@@ -106,18 +316,34 @@ impl CompareDispatcher {
                 let latency = start.elapsed().as_millis() as u64;
                 tracing::Span::current().record("latency_ms", latency);
 
+                let success =
+                    matches!(result, Ok(Ok(ref resp)) if !resp.status().is_server_error());
+                breakers.record(&target_url, success);
+
                 match result {
                     Ok(Ok(resp)) => {
                         let status = resp.status().as_u16();
                         tracing::Span::current().record("status", status);
+                        let is_sse = resp
+                            .headers()
+                            .get("content-type")
+                            .and_then(|v| v.to_str().ok())
+                            .is_some_and(|ct| ct.starts_with("text/event-stream"));
 
-                        match resp.bytes().await {
-                            Ok(body) => {
+                        match read_body(resp, start).await {
+                            Ok((body, ttft_ms)) => {
                                 // Record total latency including full body read
                                 let total_latency = start.elapsed().as_millis() as u64;
                                 tracing::Span::current()
                                     .record("total_latency_ms", total_latency);
 
+                                let body = match protocol {
+                                    TargetProtocol::Anthropic => body,
+                                    TargetProtocol::Openai => {
+                                        primary::translate_openai_body(status, is_sse, &body)
+                                    }
+                                };
+
                                 // Extract token usage — handle both JSON and SSE formats
                                 let (input, output) = extract_usage(&body);
 
@@ -140,6 +366,16 @@ impl CompareDispatcher {
                                     output_tokens = ?output,
                                     "Compare request complete"
                                 );
+                                join.record_target(
+                                    &correlation_id,
+                                    &model,
+                                    ResponseSummary::from_body(
+                                        status,
+                                        &body,
+                                        ttft_ms,
+                                        total_latency,
+                                    ),
+                                );
                             }
                             Err(e) => {
                                 tracing::warn!(
@@ -148,6 +384,12 @@ impl CompareDispatcher {
                                     latency_ms = latency,
                                     "Failed to read compare response body"
                                 );
+                                let total_latency = start.elapsed().as_millis() as u64;
+                                join.record_target(
+                                    &correlation_id,
+                                    &model,
+                                    ResponseSummary::failed(total_latency),
+                                );
                             }
                         }
                     }
@@ -158,10 +400,20 @@ impl CompareDispatcher {
                             latency_ms = latency,
                             "Compare request failed"
                         );
+                        join.record_target(
+                            &correlation_id,
+                            &model,
+                            ResponseSummary::failed(latency),
+                        );
                     }
                     Err(_) => {
                         tracing::Span::current().record("status", 0_u16);
                         tracing::warn!(latency_ms = latency, "Compare request timed out");
+                        join.record_target(
+                            &correlation_id,
+                            &model,
+                            ResponseSummary::failed(latency),
+                        );
                     }
                 }
             }
@@ -171,6 +423,317 @@ impl CompareDispatcher {
     }
 }
 
+/// Read a response body to the end, noting when its first chunk arrived
+/// (milliseconds since `start`).
+async fn read_body(
+    mut resp: reqwest::Response,
+    start: Instant,
+) -> Result<(Vec<u8>, Option<u64>), reqwest::Error> {
+    let mut body = Vec::new();
+    let mut ttft_ms = None;
+    while let Some(chunk) = resp.chunk().await? {
+        ttft_ms.get_or_insert_with(|| start.elapsed().as_millis() as u64);
+        body.extend_from_slice(&chunk);
+    }
+    Ok((body, ttft_ms))
+}
+
+/// What every pair of a dispatched request shares.
+#[derive(Clone, Default)]
+struct RequestOrigin {
+    /// OTel context of the `proxy_request` span, parent of the compare spans.
+    primary_trace: Context,
+    /// Set when the request was sampled for judging.
+    judge: Option<Arc<JudgeRequest>>,
+}
+
+/// A dispatched request awaiting its primary response and compare responses.
+#[derive(Default)]
+struct PendingCompare {
+    origin: RequestOrigin,
+    primary: Option<ResponseSummary>,
+    /// Per compare model still to be diffed: its request summary and, once
+    /// it arrived before the primary, its response.
+    targets: HashMap<String, (RequestSummary, Option<ResponseSummary>)>,
+}
+
+/// Pairs the primary response with each compare response by correlation ID
+/// and model. Targets are registered at dispatch and each pair is diffed as
+/// soon as both halves have arrived.
+#[derive(Clone)]
+struct CompareJoin {
+    pending: Arc<Mutex<HashMap<String, (Instant, PendingCompare)>>>,
+    ttl: Duration,
+    store: Option<Arc<CompareStore>>,
+    stats: Arc<CompareStats>,
+    judge: Arc<Judge>,
+}
+
+impl CompareJoin {
+    fn new(
+        ttl: Duration,
+        store: Option<Arc<CompareStore>>,
+        stats: Arc<CompareStats>,
+        judge: Arc<Judge>,
+    ) -> Self {
+        Self {
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            ttl,
+            store,
+            stats,
+            judge,
+        }
+    }
+
+    /// Register a dispatched compare request (one per model), dropping
+    /// requests older than the TTL.
+    fn expect(&self, correlation_id: &str, request: RequestSummary, origin: &RequestOrigin) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.retain(|_, (since, _)| since.elapsed() < self.ttl);
+        pending
+            .entry(correlation_id.to_string())
+            .or_insert_with(|| {
+                let entry = PendingCompare {
+                    origin: origin.clone(),
+                    ..Default::default()
+                };
+                (Instant::now(), entry)
+            })
+            .1
+            .targets
+            .insert(request.model.clone(), (request, None));
+    }
+
+    /// Forget a compare request whose response will never arrive.
+    fn abandon(&self, correlation_id: &str, model: &str) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, entry)) = pending.get_mut(correlation_id) {
+            entry.targets.remove(model);
+            if entry.targets.is_empty() {
+                pending.remove(correlation_id);
+            }
+        }
+    }
+
+    /// Store the primary response; emits, stores and returns the diff of
+    /// every compare response that already arrived.
+    fn record_primary(&self, correlation_id: &str, summary: ResponseSummary) -> Vec<CompareDiff> {
+        let (primary, ready, origin) = {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            let Some((_, entry)) = pending.get_mut(correlation_id) else {
+                return Vec::new();
+            };
+            let origin = entry.origin.clone();
+            let arrived: Vec<String> = entry
+                .targets
+                .iter()
+                .filter(|(_, (_, response))| response.is_some())
+                .map(|(model, _)| model.clone())
+                .collect();
+            let ready: Vec<_> = arrived
+                .iter()
+                .filter_map(|model| entry.targets.remove(model))
+                .collect();
+            if entry.targets.is_empty() {
+                pending.remove(correlation_id);
+            } else {
+                entry.primary = Some(summary.clone());
+            }
+            (summary, ready, origin)
+        };
+        ready
+            .into_iter()
+            .filter_map(|(request, target)| {
+                Some(self.complete(correlation_id, &origin, request, primary.clone(), target?))
+            })
+            .collect()
+    }
+
+    /// Store a compare response; emits, stores and returns its diff when
+    /// the primary response already arrived. Unregistered (abandoned or
+    /// expired) requests are ignored.
+    fn record_target(
+        &self,
+        correlation_id: &str,
+        model: &str,
+        summary: ResponseSummary,
+    ) -> Option<CompareDiff> {
+        let (request, primary, origin) = {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            let (_, entry) = pending.get_mut(correlation_id)?;
+            let Some(primary) = entry.primary.clone() else {
+                entry.targets.get_mut(model)?.1 = Some(summary);
+                return None;
+            };
+            let (request, _) = entry.targets.remove(model)?;
+            let origin = entry.origin.clone();
+            if entry.targets.is_empty() {
+                pending.remove(correlation_id);
+            }
+            (request, primary, origin)
+        };
+        Some(self.complete(correlation_id, &origin, request, primary, summary))
+    }
+
+    /// Add a completed pair to the stats, then emit and store it — once
+    /// judged, when its request was sampled for judging.
+    fn complete(
+        &self,
+        correlation_id: &str,
+        origin: &RequestOrigin,
+        request: RequestSummary,
+        primary: ResponseSummary,
+        target: ResponseSummary,
+    ) -> CompareDiff {
+        let diff = diff::compare(&primary, &target);
+        self.stats.record(&request.model, &primary, &target, &diff);
+        let mut record = CompareRecord::new(
+            correlation_id,
+            primary_trace_id(&origin.primary_trace),
+            request,
+            primary,
+            target,
+            diff.clone(),
+        );
+        let Some(judge_request) = origin.judge.clone() else {
+            self.finish(record, &origin.primary_trace);
+            return diff;
+        };
+
+        let join = self.clone();
+        let primary_trace = origin.primary_trace.clone();
+        tokio::spawn(async move {
+            let span = tracing::info_span!(
+                parent: None,
+                "compare_judge",
+                correlation_id = %record.correlation_id,
+                model = %record.request.model,
+                judge_model = %judge_request.model,
+                status = tracing::field::Empty,
+                latency_ms = tracing::field::Empty,
+                primary_trace_id = tracing::field::Empty,
+            );
+            link_primary_trace(&span, &primary_trace);
+            let result = join
+                .judge
+                .score(&judge_request, &record.primary, &record.target)
+                .instrument(span)
+                .await;
+            if let Some(ref error) = result.error {
+                tracing::warn!(
+                    correlation_id = %record.correlation_id,
+                    model = %record.request.model,
+                    judge_model = %result.model,
+                    error = %error,
+                    "Compare judge failed"
+                );
+            }
+            record.judge = Some(result);
+            join.finish(record, &primary_trace);
+        });
+        diff
+    }
+
+    fn finish(&self, record: CompareRecord, primary_trace: &Context) {
+        emit_result(&record, primary_trace);
+        if let Some(store) = &self.store {
+            store.append(record);
+        }
+    }
+}
+
+/// Emit a completed (and possibly judged) pair as a `compare_result` span
+/// and log record.
+fn emit_result(record: &CompareRecord, primary_trace: &Context) {
+    let CompareRecord {
+        correlation_id,
+        request,
+        primary,
+        target,
+        diff,
+        judge,
+        ..
+    } = record;
+    let model = &request.model;
+    let mismatches: Vec<&str> = diff.mismatches.iter().map(|m| m.as_str()).collect();
+    let mismatches = mismatches.join(",");
+    let span = tracing::info_span!(
+        parent: None,
+        "compare_result",
+        correlation_id = %correlation_id,
+        model = %model,
+        primary_status = primary.status,
+        target_status = target.status,
+        stop_reason_match = diff.stop_reason_match,
+        tool_names_match = diff.tool_names_match,
+        argument_diff_count = diff.argument_diffs.len() as u64,
+        text_similarity = diff.text_similarity,
+        input_tokens_delta = tracing::field::Empty,
+        output_tokens_delta = tracing::field::Empty,
+        ttft_delta_ms = tracing::field::Empty,
+        latency_delta_ms = diff.latency_delta_ms,
+        mismatches = %mismatches,
+        diff_json = tracing::field::Empty,
+        primary_trace_id = tracing::field::Empty,
+        judge_model = tracing::field::Empty,
+        judge_score = tracing::field::Empty,
+        judge_rationale = tracing::field::Empty,
+        judge_error = tracing::field::Empty,
+    );
+    link_primary_trace(&span, primary_trace);
+    if let Some(judge) = judge {
+        span.record("judge_model", judge.model.as_str());
+        if let Some(score) = judge.score {
+            span.record("judge_score", score);
+        }
+        if let Some(ref rationale) = judge.rationale {
+            span.record("judge_rationale", rationale.as_str());
+        }
+        if let Some(ref error) = judge.error {
+            span.record("judge_error", error.as_str());
+        }
+    }
+    if let Some(d) = diff.input_tokens_delta {
+        span.record("input_tokens_delta", d);
+    }
+    if let Some(d) = diff.output_tokens_delta {
+        span.record("output_tokens_delta", d);
+    }
+    if let Some(d) = diff.ttft_delta_ms {
+        span.record("ttft_delta_ms", d);
+    }
+    if let Ok(json) = serde_json::to_string(diff) {
+        span.record("diff_json", json.as_str());
+    }
+    let _enter = span.enter();
+    tracing::info!(
+        correlation_id = %correlation_id,
+        model = %model,
+        mismatches = %mismatches,
+        text_similarity = diff.text_similarity,
+        latency_delta_ms = diff.latency_delta_ms,
+        "Compare result"
+    );
+}
+
+/// Trace ID of the primary request, when it is traced.
+fn primary_trace_id(primary_trace: &Context) -> Option<String> {
+    let span_context = primary_trace.span().span_context().clone();
+    span_context
+        .is_valid()
+        .then(|| span_context.trace_id().to_string())
+}
+
+/// Parent a not-yet-entered compare span to the primary request's span, so
+/// it lands in the same trace, and record that trace's ID on it.
+fn link_primary_trace(span: &tracing::Span, primary_trace: &Context) {
+    if let Some(trace_id) = primary_trace_id(primary_trace) {
+        // Fails only when tracing is off
+        let _ = span.set_parent(primary_trace.clone());
+        span.record("primary_trace_id", trace_id.as_str());
+    }
+}
+
 /// Extract input/output token counts from a response body.
 ///
 /// Handles both formats:
@@ -249,3 +812,121 @@ fn extract_usage(body: &[u8]) -> (Option<u64>, Option<u64>) {
 
     (input_tokens, output_tokens)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(status: u16) -> ResponseSummary {
+        ResponseSummary {
+            status,
+            ..Default::default()
+        }
+    }
+
+    fn stats() -> Arc<CompareStats> {
+        Arc::new(CompareStats::new(10))
+    }
+
+    fn judge() -> Arc<Judge> {
+        Arc::new(Judge::new(reqwest::Client::new()))
+    }
+
+    fn request(model: &str) -> RequestSummary {
+        RequestSummary::new(None, "claude-sonnet-4-5", model)
+    }
+
+    #[test]
+    fn join_diffs_once_both_halves_arrive() {
+        let join = CompareJoin::new(Duration::from_secs(60), None, stats(), judge());
+        join.expect("c1", request("m1"), &RequestOrigin::default());
+        assert!(join.record_target("c1", "m1", summary(500)).is_none());
+        let diffs = join.record_primary("c1", summary(200));
+        assert_eq!(diffs.len(), 1);
+        assert!(!diffs[0].status_match);
+        // The pair is gone once emitted
+        assert!(join.record_target("c1", "m1", summary(200)).is_none());
+    }
+
+    #[test]
+    fn join_diffs_each_candidate_against_the_primary() {
+        let join = CompareJoin::new(Duration::from_secs(60), None, stats(), judge());
+        join.expect("c1", request("m1"), &RequestOrigin::default());
+        join.expect("c1", request("m2"), &RequestOrigin::default());
+        join.expect("c1", request("m3"), &RequestOrigin::default());
+        assert!(join.record_target("c1", "m1", summary(200)).is_none());
+        join.abandon("c1", "m3");
+
+        // m1 arrived first and is diffed with the primary; m2 once it lands
+        assert_eq!(join.record_primary("c1", summary(200)).len(), 1);
+        let diff = join
+            .record_target("c1", "m2", summary(500))
+            .expect("primary already arrived");
+        assert!(!diff.status_match);
+        assert!(join.pending.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn completed_pairs_are_stored() {
+        let dir = std::env::temp_dir().join(format!("cc-proxy-compare-{}", uuid::Uuid::new_v4()));
+        let store =
+            Arc::new(CompareStore::open(dir.join("compare.jsonl").to_str().unwrap()).unwrap());
+        let join = CompareJoin::new(
+            Duration::from_secs(60),
+            Some(store.clone()),
+            stats(),
+            judge(),
+        );
+        join.expect("c1", request("my-model"), &RequestOrigin::default());
+        join.record_primary("c1", summary(200));
+        join.record_target("c1", "my-model", summary(200));
+        store.flush().await;
+
+        let (records, _) = store.query(&Default::default(), 10).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].correlation_id, "c1");
+        assert_eq!(records[0].request, request("my-model"));
+        assert!(records[0].diff.status_match);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn abandoned_and_expired_requests_are_ignored() {
+        let join = CompareJoin::new(Duration::ZERO, None, stats(), judge());
+        join.expect("dropped", request("m1"), &RequestOrigin::default());
+        join.abandon("dropped", "m1");
+        assert!(join.record_primary("dropped", summary(200)).is_empty());
+
+        join.expect("old", request("m1"), &RequestOrigin::default());
+        join.expect("new", request("m1"), &RequestOrigin::default()); // purges "old"
+        assert!(join.record_primary("old", summary(200)).is_empty());
+        assert!(join.record_target("old", "m1", summary(200)).is_none());
+    }
+
+    #[test]
+    fn compare_spans_join_the_primary_trace() {
+        use opentelemetry::trace::TracerProvider;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        tracing::subscriber::with_default(subscriber, || {
+            let primary = tracing::info_span!("proxy_request");
+            let primary_trace = primary.context();
+            let trace_id = primary_trace_id(&primary_trace).expect("primary is traced");
+
+            let span = tracing::info_span!(
+                parent: None,
+                "compare_request",
+                primary_trace_id = tracing::field::Empty,
+            );
+            link_primary_trace(&span, &primary_trace);
+            let span_context = span.context().span().span_context().clone();
+            assert_eq!(span_context.trace_id().to_string(), trace_id);
+        });
+        // Untraced requests leave nothing to link
+        assert_eq!(primary_trace_id(&Context::new()), None);
+    }
+}
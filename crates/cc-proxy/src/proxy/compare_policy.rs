@@ -0,0 +1,218 @@
+//! Which Anthropic-routed requests compare mode mirrors to the target.
+//!
+//! Checked in order: the model include/exclude lists, the quota-check skip,
+//! the request size limit, then the sampling percentage. The outcome is
+//! recorded as `compare_decision` on the `proxy_request` span.
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::config::CompareConfig;
+use crate::models;
+
+/// Outcome of the compare policy for one request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareDecision {
+    Sampled,
+    /// Left out by the sampling percentage.
+    NotSampled,
+    /// Not in `include_models`, or in `exclude_models`.
+    ExcludedModel,
+    QuotaCheck,
+    TooLarge,
+}
+
+impl CompareDecision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompareDecision::Sampled => "sampled",
+            CompareDecision::NotSampled => "skipped:not_sampled",
+            CompareDecision::ExcludedModel => "skipped:excluded_model",
+            CompareDecision::QuotaCheck => "skipped:quota_check",
+            CompareDecision::TooLarge => "skipped:too_large",
+        }
+    }
+}
+
+/// Decide whether to mirror a request for `model` whose body is `body_len`
+/// bytes.
+pub fn decide(
+    config: &CompareConfig,
+    request: Option<&Value>,
+    model: &str,
+    body_len: usize,
+) -> CompareDecision {
+    if !config.include_models.is_empty() && !config.include_models.matches(model) {
+        return CompareDecision::ExcludedModel;
+    }
+    if config.exclude_models.matches(model) {
+        return CompareDecision::ExcludedModel;
+    }
+    if config.skip_quota_checks && request.is_some_and(is_quota_check) {
+        return CompareDecision::QuotaCheck;
+    }
+    if config.max_request_bytes.is_some_and(|max| body_len > max) {
+        return CompareDecision::TooLarge;
+    }
+    if !sampled(config.sample_percent) {
+        return CompareDecision::NotSampled;
+    }
+    CompareDecision::Sampled
+}
+
+/// Returns true if the request looks like a Claude Code quota check:
+/// - Exactly one message with role `user`
+/// - No tools defined
+/// - `max_tokens` <= 32
+fn is_quota_check(req: &Value) -> bool {
+    let messages = match req.get("messages").and_then(|v| v.as_array()) {
+        Some(m) => m,
+        None => return false,
+    };
+    let max_tokens = req
+        .get("max_tokens")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(u64::MAX);
+    let has_tools = req
+        .get("tools")
+        .and_then(|v| v.as_array())
+        .is_some_and(|t| !t.is_empty());
+    let single_user_msg =
+        messages.len() == 1 && messages[0].get("role").and_then(|v| v.as_str()) == Some("user");
+
+    single_user_msg && !has_tools && max_tokens <= 32
+}
+
+/// Random draw against a percentage (0–100).
+pub fn sampled(percent: f64) -> bool {
+    if percent >= 100.0 {
+        return true;
+    }
+    let draw = (uuid::Uuid::new_v4().as_u128() % 10_000) as f64 / 100.0;
+    draw < percent
+}
+
+/// `*`/`?` globs matched against the whole model ID, as alias patterns are.
+/// Compiled when the config is loaded, so a bad pattern fails the load.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(try_from = "Vec<String>")]
+pub struct ModelGlobs {
+    patterns: Vec<String>,
+    set: regex::RegexSet,
+}
+
+impl ModelGlobs {
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Whether any of the globs matches `model`.
+    pub fn matches(&self, model: &str) -> bool {
+        self.set.is_match(model)
+    }
+}
+
+impl TryFrom<Vec<String>> for ModelGlobs {
+    type Error = String;
+
+    fn try_from(patterns: Vec<String>) -> Result<Self, Self::Error> {
+        let set = regex::RegexSet::new(patterns.iter().map(|p| models::glob_to_regex(p)))
+            .map_err(|e| format!("invalid model glob: {e}"))?;
+        Ok(Self { patterns, set })
+    }
+}
+
+impl PartialEq for ModelGlobs {
+    fn eq(&self, other: &Self) -> bool {
+        self.patterns == other.patterns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn globs(patterns: &[&str]) -> ModelGlobs {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        ModelGlobs::try_from(patterns).unwrap()
+    }
+
+    fn request(max_tokens: u64) -> Value {
+        json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": max_tokens,
+            "messages": [{"role": "user", "content": "quota"}]
+        })
+    }
+
+    #[test]
+    fn checks_models_quota_checks_and_size() {
+        let config = CompareConfig {
+            include_models: globs(&["claude-*"]),
+            exclude_models: globs(&["claude-*-haiku-*"]),
+            max_request_bytes: Some(1000),
+            ..Default::default()
+        };
+        let req = request(4096);
+        let decide = |model, req: &Value, len| decide(&config, Some(req), model, len);
+
+        assert_eq!(
+            decide("claude-sonnet-4-5", &req, 10),
+            CompareDecision::Sampled
+        );
+        assert_eq!(decide("gpt-4o", &req, 10), CompareDecision::ExcludedModel);
+        assert_eq!(
+            decide("claude-3-5-haiku-20241022", &req, 10),
+            CompareDecision::ExcludedModel
+        );
+        assert_eq!(
+            decide("claude-sonnet-4-5", &request(1), 10),
+            CompareDecision::QuotaCheck
+        );
+        assert_eq!(
+            decide("claude-sonnet-4-5", &req, 1001),
+            CompareDecision::TooLarge
+        );
+    }
+
+    #[test]
+    fn sampling_percent_bounds() {
+        let never = CompareConfig {
+            sample_percent: 0.0,
+            ..Default::default()
+        };
+        let req = request(4096);
+        for _ in 0..100 {
+            assert_eq!(
+                decide(&never, Some(&req), "m", 10),
+                CompareDecision::NotSampled
+            );
+            assert_eq!(
+                decide(&CompareConfig::default(), Some(&req), "m", 10),
+                CompareDecision::Sampled
+            );
+        }
+    }
+
+    #[test]
+    fn glob_matching() {
+        let matches = |pattern, model| globs(&[pattern]).matches(model);
+        assert!(matches("claude-*-haiku-*", "claude-3-5-haiku-20241022"));
+        assert!(matches("*", ""));
+        assert!(matches("a?c", "abc"));
+        assert!(matches("*a*b", "xxaybzab"));
+        assert!(!matches("claude-*", "my-claude-x"));
+        assert!(!matches("a?c", "ac"));
+        assert!(!globs(&[]).matches("claude-sonnet-4-5"));
+    }
+
+    #[test]
+    fn detects_quota_checks() {
+        assert!(is_quota_check(&request(1)));
+        assert!(!is_quota_check(&request(4096)));
+        let mut with_tools = request(1);
+        with_tools["tools"] = json!([{"name": "Read"}]);
+        assert!(!is_quota_check(&with_tools));
+    }
+}
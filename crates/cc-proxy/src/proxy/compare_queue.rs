@@ -0,0 +1,221 @@
+//! Bounded per-model queues in front of compare requests.
+//!
+//! Each compare model runs up to its `max_concurrent` requests at once. Up
+//! to `compare.queue_depth` more wait for a slot in arrival order (the
+//! semaphore is fair); a request is dropped when the queue is full or when
+//! it has waited `compare.queue_max_wait_secs`. Queue depth and drop counts
+//! are served by `GET /api/compare/stats`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Why a compare request was dropped before it was sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    QueueFull,
+    WaitedTooLong,
+}
+
+impl DropReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DropReason::QueueFull => "queue_full",
+            DropReason::WaitedTooLong => "waited_too_long",
+        }
+    }
+}
+
+/// One model's queue. A changed capacity replaces it (carrying the drop
+/// counts over); requests already holding or waiting on the old semaphore
+/// finish against it.
+struct ModelQueue {
+    capacity: usize,
+    semaphore: Arc<Semaphore>,
+    queued: AtomicUsize,
+    queue_full: AtomicU64,
+    waited_too_long: AtomicU64,
+}
+
+impl ModelQueue {
+    fn new(capacity: usize, queue_full: u64, waited_too_long: u64) -> Self {
+        Self {
+            capacity,
+            semaphore: Arc::new(Semaphore::new(capacity)),
+            queued: AtomicUsize::new(0),
+            queue_full: AtomicU64::new(queue_full),
+            waited_too_long: AtomicU64::new(waited_too_long),
+        }
+    }
+
+    fn drop_request(&self, reason: DropReason) -> DropReason {
+        let counter = match reason {
+            DropReason::QueueFull => &self.queue_full,
+            DropReason::WaitedTooLong => &self.waited_too_long,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        reason
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DroppedCounts {
+    pub queue_full: u64,
+    pub waited_too_long: u64,
+}
+
+/// Current state of one model's queue.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct QueueStats {
+    pub max_concurrent: usize,
+    pub in_flight: usize,
+    /// Requests waiting for a slot.
+    pub depth: usize,
+    /// Drops since startup.
+    pub dropped: DroppedCounts,
+}
+
+/// Compare queues, per compare model.
+#[derive(Default)]
+pub struct CompareQueues {
+    models: Mutex<HashMap<String, Arc<ModelQueue>>>,
+}
+
+impl CompareQueues {
+    fn queue(&self, model: &str, capacity: usize) -> Arc<ModelQueue> {
+        let mut models = self.models.lock().unwrap_or_else(|e| e.into_inner());
+        let queue = models
+            .entry(model.to_string())
+            .or_insert_with(|| Arc::new(ModelQueue::new(capacity, 0, 0)));
+        if queue.capacity != capacity {
+            *queue = Arc::new(ModelQueue::new(
+                capacity,
+                queue.queue_full.load(Ordering::Relaxed),
+                queue.waited_too_long.load(Ordering::Relaxed),
+            ));
+        }
+        queue.clone()
+    }
+
+    /// Wait for one of `model`'s `capacity` slots behind at most `depth`
+    /// other requests, for at most `max_wait`.
+    pub async fn acquire(
+        &self,
+        model: &str,
+        capacity: usize,
+        depth: usize,
+        max_wait: Duration,
+    ) -> Result<OwnedSemaphorePermit, DropReason> {
+        let queue = self.queue(model, capacity);
+        // Free permits only exist while nobody is queued, so this never
+        // jumps ahead of a waiting request
+        if let Ok(permit) = queue.semaphore.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+        if queue.queued.fetch_add(1, Ordering::SeqCst) >= depth {
+            queue.queued.fetch_sub(1, Ordering::SeqCst);
+            return Err(queue.drop_request(DropReason::QueueFull));
+        }
+        let acquired =
+            tokio::time::timeout(max_wait, queue.semaphore.clone().acquire_owned()).await;
+        queue.queued.fetch_sub(1, Ordering::SeqCst);
+        match acquired {
+            Ok(Ok(permit)) => Ok(permit),
+            // The semaphore is never closed; treat it like a timeout anyway
+            Ok(Err(_)) | Err(_) => Err(queue.drop_request(DropReason::WaitedTooLong)),
+        }
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, QueueStats> {
+        let models = self.models.lock().unwrap_or_else(|e| e.into_inner());
+        models
+            .iter()
+            .map(|(model, queue)| {
+                let stats = QueueStats {
+                    max_concurrent: queue.capacity,
+                    in_flight: queue
+                        .capacity
+                        .saturating_sub(queue.semaphore.available_permits()),
+                    depth: queue.queued.load(Ordering::SeqCst),
+                    dropped: DroppedCounts {
+                        queue_full: queue.queue_full.load(Ordering::Relaxed),
+                        waited_too_long: queue.waited_too_long.load(Ordering::Relaxed),
+                    },
+                };
+                (model.clone(), stats)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    #[tokio::test]
+    async fn queues_in_order_then_drops_when_full() {
+        let queues = Arc::new(CompareQueues::default());
+        let running = queues.acquire("m1", 1, 2, WAIT).await.unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        for i in 0..2 {
+            let (waiter, tx) = (queues.clone(), tx.clone());
+            tokio::spawn(async move {
+                let _permit = waiter.acquire("m1", 1, 2, WAIT).await.unwrap();
+                tx.send(i).unwrap();
+            });
+            // Let each waiter enqueue before the next
+            while queues.snapshot()["m1"].depth <= i {
+                tokio::task::yield_now().await;
+            }
+        }
+        assert_eq!(
+            queues.acquire("m1", 1, 2, WAIT).await.unwrap_err(),
+            DropReason::QueueFull
+        );
+        let stats = &queues.snapshot()["m1"];
+        assert_eq!((stats.in_flight, stats.depth), (1, 2));
+        assert_eq!(stats.dropped.queue_full, 1);
+
+        drop(running);
+        assert_eq!(rx.recv().await, Some(0));
+        assert_eq!(rx.recv().await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn drops_after_max_wait() {
+        let queues = CompareQueues::default();
+        let _running = queues.acquire("m1", 1, 10, WAIT).await.unwrap();
+        assert_eq!(
+            queues
+                .acquire("m1", 1, 10, Duration::from_millis(20))
+                .await
+                .unwrap_err(),
+            DropReason::WaitedTooLong
+        );
+        // Depth 0 drops as soon as the model is at capacity
+        assert_eq!(
+            queues.acquire("m1", 1, 0, WAIT).await.unwrap_err(),
+            DropReason::QueueFull
+        );
+
+        let stats = &queues.snapshot()["m1"];
+        assert_eq!(stats.depth, 0);
+        assert_eq!(
+            stats.dropped,
+            DroppedCounts {
+                queue_full: 1,
+                waited_too_long: 1
+            }
+        );
+        // A new capacity starts a new queue but keeps the counts
+        let _other = queues.acquire("m1", 2, 0, WAIT).await.unwrap();
+        assert_eq!(queues.snapshot()["m1"].dropped.waited_too_long, 1);
+    }
+}
@@ -0,0 +1,216 @@
+//! Rolling per-model compare aggregates for `GET /api/compare/stats`.
+//!
+//! Each compare model keeps its most recent `compare.stats_window` pairs in
+//! memory. Latency percentiles and throughput cover successful (2xx)
+//! responses only; errors count towards the error rate instead.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use super::diff::{CompareDiff, ResponseSummary};
+
+/// One side of a pair, reduced to what the aggregates need.
+struct SideSample {
+    ok: bool,
+    ttft_ms: Option<u64>,
+    total_ms: u64,
+    output_tokens: Option<u64>,
+}
+
+impl SideSample {
+    fn new(summary: &ResponseSummary) -> Self {
+        Self {
+            ok: (200..300).contains(&summary.status),
+            ttft_ms: summary.ttft_ms,
+            total_ms: summary.total_ms,
+            output_tokens: summary.output_tokens,
+        }
+    }
+}
+
+struct Sample {
+    primary: SideSample,
+    target: SideSample,
+    /// Same tool calls with the same arguments.
+    tool_calls_agree: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Percentiles {
+    pub p50: Option<u64>,
+    pub p90: Option<u64>,
+    pub p99: Option<u64>,
+}
+
+impl Percentiles {
+    /// Nearest-rank percentiles of `values`.
+    fn of(mut values: Vec<u64>) -> Self {
+        values.sort_unstable();
+        let rank = |p: f64| {
+            let n = values.len();
+            (n > 0).then(|| values[((p * n as f64).ceil() as usize).clamp(1, n) - 1])
+        };
+        Self {
+            p50: rank(0.50),
+            p90: rank(0.90),
+            p99: rank(0.99),
+        }
+    }
+}
+
+/// Aggregates of one side (primary or target) of a model's pairs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SideStats {
+    pub ttft_ms: Percentiles,
+    pub total_ms: Percentiles,
+    /// Output tokens over total latency, across successful responses.
+    pub output_tokens_per_sec: Option<f64>,
+    /// Share of responses that were not 2xx (including no response).
+    pub error_rate: f64,
+}
+
+impl SideStats {
+    fn of<'a>(samples: impl Iterator<Item = &'a SideSample>) -> Self {
+        let (mut ttft, mut total) = (Vec::new(), Vec::new());
+        let (mut tokens, mut token_ms) = (0u64, 0u64);
+        let (mut count, mut errors) = (0u64, 0u64);
+        for s in samples {
+            count += 1;
+            if !s.ok {
+                errors += 1;
+                continue;
+            }
+            ttft.extend(s.ttft_ms);
+            total.push(s.total_ms);
+            if let Some(n) = s.output_tokens {
+                tokens += n;
+                token_ms += s.total_ms;
+            }
+        }
+        Self {
+            ttft_ms: Percentiles::of(ttft),
+            total_ms: Percentiles::of(total),
+            output_tokens_per_sec: (token_ms > 0).then(|| tokens as f64 * 1000.0 / token_ms as f64),
+            error_rate: errors as f64 / count.max(1) as f64,
+        }
+    }
+}
+
+/// Aggregates of one compare model's recent pairs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ModelCompareStats {
+    pub samples: usize,
+    pub primary: SideStats,
+    pub target: SideStats,
+    /// Share of pairs with the same tool calls and arguments.
+    pub tool_call_agreement_rate: f64,
+}
+
+/// Rolling windows of compare pairs, per compare model.
+pub struct CompareStats {
+    window: usize,
+    models: Mutex<HashMap<String, VecDeque<Sample>>>,
+}
+
+impl CompareStats {
+    /// Keep the last `window` pairs per model.
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            models: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    pub fn record(
+        &self,
+        model: &str,
+        primary: &ResponseSummary,
+        target: &ResponseSummary,
+        diff: &CompareDiff,
+    ) {
+        let sample = Sample {
+            primary: SideSample::new(primary),
+            target: SideSample::new(target),
+            tool_calls_agree: diff.tool_names_match && diff.argument_diffs.is_empty(),
+        };
+        let mut models = self.models.lock().unwrap_or_else(|e| e.into_inner());
+        let samples = models.entry(model.to_string()).or_default();
+        if samples.len() == self.window {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, ModelCompareStats> {
+        let models = self.models.lock().unwrap_or_else(|e| e.into_inner());
+        models
+            .iter()
+            .map(|(model, samples)| {
+                let agree = samples.iter().filter(|s| s.tool_calls_agree).count();
+                let stats = ModelCompareStats {
+                    samples: samples.len(),
+                    primary: SideStats::of(samples.iter().map(|s| &s.primary)),
+                    target: SideStats::of(samples.iter().map(|s| &s.target)),
+                    tool_call_agreement_rate: agree as f64 / samples.len().max(1) as f64,
+                };
+                (model.clone(), stats)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::diff;
+
+    fn response(status: u16, ttft_ms: u64, total_ms: u64, output_tokens: u64) -> ResponseSummary {
+        ResponseSummary {
+            status,
+            ttft_ms: Some(ttft_ms),
+            total_ms,
+            output_tokens: Some(output_tokens),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn percentiles_use_nearest_rank() {
+        let p = Percentiles::of((1..=100).rev().collect());
+        assert_eq!((p.p50, p.p90, p.p99), (Some(50), Some(90), Some(99)));
+        assert_eq!(Percentiles::of(vec![7]).p99, Some(7));
+        assert_eq!(Percentiles::of(Vec::new()), Percentiles::default());
+    }
+
+    #[test]
+    fn aggregates_rolling_window_per_model() {
+        let stats = CompareStats::new(2);
+        let primary = response(200, 100, 1000, 50);
+        for target in [
+            response(500, 10, 10, 0), // rolled out of the window
+            response(200, 200, 2000, 40),
+            ResponseSummary::failed(30_000),
+        ] {
+            let diff = diff::compare(&primary, &target);
+            stats.record("m1", &primary, &target, &diff);
+        }
+
+        let snapshot = stats.snapshot();
+        let m1 = &snapshot["m1"];
+        assert_eq!(m1.samples, 2);
+        assert_eq!(m1.primary.error_rate, 0.0);
+        assert_eq!(m1.primary.output_tokens_per_sec, Some(50.0));
+        assert_eq!(m1.target.error_rate, 0.5);
+        assert_eq!(m1.target.ttft_ms.p50, Some(200));
+        assert_eq!(m1.target.total_ms.p99, Some(2000));
+        assert_eq!(m1.target.output_tokens_per_sec, Some(20.0));
+        // Neither side called tools, so both pairs agree
+        assert_eq!(m1.tool_call_agreement_rate, 1.0);
+    }
+}
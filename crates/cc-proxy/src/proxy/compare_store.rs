@@ -0,0 +1,491 @@
+//! Persistent store of compare results.
+//!
+//! Every completed compare pair is appended to a JSONL file
+//! (`compare.store_file`) as a `CompareRecord`: a summary of the request,
+//! both responses and their diff. Records are handed to a writer task, so
+//! completing a pair never waits on the disk. `GET /api/compare` and
+//! `GET /api/compare/summary` read the file back, so results survive restarts
+//! and can be aggregated without an OTLP collector.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+use super::diff::{CompareDiff, Mismatch, ResponseSummary};
+use super::judge::JudgeResult;
+
+/// The request a compare pair answered, without its content.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RequestSummary {
+    /// Model the compare request was sent as.
+    pub model: String,
+    /// Model the client requested (answered by the primary).
+    pub primary_model: String,
+    pub stream: bool,
+    pub message_count: usize,
+    pub tool_count: usize,
+    pub max_tokens: Option<u64>,
+}
+
+impl RequestSummary {
+    pub fn new(request: Option<&Value>, primary_model: &str, model: &str) -> Self {
+        let count = |key: &str| {
+            request
+                .and_then(|r| r.get(key))
+                .and_then(|v| v.as_array())
+                .map_or(0, Vec::len)
+        };
+        Self {
+            model: model.to_string(),
+            primary_model: primary_model.to_string(),
+            stream: request
+                .and_then(|r| r.get("stream"))
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false),
+            message_count: count("messages"),
+            tool_count: count("tools"),
+            max_tokens: request
+                .and_then(|r| r.get("max_tokens"))
+                .and_then(|v| v.as_u64()),
+        }
+    }
+}
+
+/// One line of the store.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompareRecord {
+    pub correlation_id: String,
+    pub created_at: String,
+    /// Unix time in milliseconds, for time-range queries.
+    pub timestamp_ms: u64,
+    /// Trace of the primary request, which the compare spans belong to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub primary_trace_id: Option<String>,
+    pub request: RequestSummary,
+    pub primary: ResponseSummary,
+    pub target: ResponseSummary,
+    pub diff: CompareDiff,
+    /// The judge's verdict, for pairs sampled for judging.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub judge: Option<JudgeResult>,
+}
+
+impl CompareRecord {
+    pub fn new(
+        correlation_id: &str,
+        primary_trace_id: Option<String>,
+        request: RequestSummary,
+        primary: ResponseSummary,
+        target: ResponseSummary,
+        diff: CompareDiff,
+    ) -> Self {
+        let now = SystemTime::now();
+        Self {
+            correlation_id: correlation_id.to_string(),
+            created_at: crate::batches::rfc3339(now),
+            timestamp_ms: now
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            primary_trace_id,
+            request,
+            primary,
+            target,
+            diff,
+            judge: None,
+        }
+    }
+}
+
+/// Record filter shared by the list and summary endpoints. All set
+/// conditions must hold.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CompareFilter {
+    /// Matches either the compare or the primary model.
+    pub model: Option<String>,
+    /// Unix seconds, inclusive.
+    pub since: Option<u64>,
+    /// Unix seconds, exclusive.
+    pub until: Option<u64>,
+    /// Only pairs with this mismatch.
+    pub mismatch: Option<Mismatch>,
+    /// Only pairs without (`true`) or with (`false`) any mismatch.
+    pub matched: Option<bool>,
+}
+
+impl CompareFilter {
+    fn matches(&self, record: &CompareRecord) -> bool {
+        let secs = record.timestamp_ms / 1000;
+        self.model
+            .as_deref()
+            .is_none_or(|m| record.request.model == m || record.request.primary_model == m)
+            && self.since.is_none_or(|s| secs >= s)
+            && self.until.is_none_or(|u| secs < u)
+            && self
+                .mismatch
+                .is_none_or(|m| record.diff.mismatches.contains(&m))
+            && self
+                .matched
+                .is_none_or(|m| record.diff.mismatches.is_empty() == m)
+    }
+}
+
+/// Match rates over a set of records.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MatchSummary {
+    pub total: u64,
+    /// Pairs without any mismatch.
+    pub matched: u64,
+    pub match_rate: f64,
+    /// Per mismatch kind, the share of pairs that agree on it.
+    pub match_rates: BTreeMap<&'static str, f64>,
+    pub mean_text_similarity: f64,
+    pub mean_latency_delta_ms: f64,
+    /// Pairs the judge scored.
+    pub judged: u64,
+    pub mean_judge_score: Option<f64>,
+}
+
+/// `GET /api/compare/summary` body.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CompareSummary {
+    #[serde(flatten)]
+    pub overall: MatchSummary,
+    /// Keyed by compare model.
+    pub by_model: BTreeMap<String, MatchSummary>,
+}
+
+#[derive(Default)]
+struct Tally {
+    total: u64,
+    matched: u64,
+    mismatches: BTreeMap<&'static str, u64>,
+    similarity: f64,
+    latency_delta: f64,
+    judged: u64,
+    judge_score: f64,
+}
+
+impl Tally {
+    fn add(&mut self, record: &CompareRecord) {
+        let diff = &record.diff;
+        self.total += 1;
+        if diff.mismatches.is_empty() {
+            self.matched += 1;
+        }
+        for m in &diff.mismatches {
+            *self.mismatches.entry(m.as_str()).or_default() += 1;
+        }
+        self.similarity += diff.text_similarity;
+        self.latency_delta += diff.latency_delta_ms as f64;
+        if let Some(score) = record.judge.as_ref().and_then(|j| j.score) {
+            self.judged += 1;
+            self.judge_score += score;
+        }
+    }
+
+    fn summary(&self) -> MatchSummary {
+        let n = self.total.max(1) as f64;
+        MatchSummary {
+            total: self.total,
+            matched: self.matched,
+            match_rate: self.matched as f64 / n,
+            match_rates: Mismatch::ALL
+                .iter()
+                .map(|m| {
+                    let misses = self.mismatches.get(m.as_str()).copied().unwrap_or(0);
+                    (m.as_str(), 1.0 - misses as f64 / n)
+                })
+                .collect(),
+            mean_text_similarity: self.similarity / n,
+            mean_latency_delta_ms: self.latency_delta / n,
+            judged: self.judged,
+            mean_judge_score: (self.judged > 0).then(|| self.judge_score / self.judged as f64),
+        }
+    }
+}
+
+/// Records waiting for the writer; more are dropped.
+const WRITE_QUEUE: usize = 1024;
+
+enum Write {
+    Record(Box<CompareRecord>),
+    #[cfg(test)]
+    Flush(tokio::sync::oneshot::Sender<()>),
+}
+
+/// Append-only JSONL file of compare records.
+pub struct CompareStore {
+    path: PathBuf,
+    writer: mpsc::Sender<Write>,
+}
+
+impl CompareStore {
+    /// Use `path`, creating its parent directory, and start its writer task.
+    /// The file itself is created on the first record.
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        let path = PathBuf::from(path);
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let (writer, writes) = mpsc::channel(WRITE_QUEUE);
+        tokio::spawn(write_records(path.clone(), writes));
+        Ok(Self { path, writer })
+    }
+
+    /// Queue `record` for the writer task, without waiting for it to be
+    /// written.
+    pub fn append(&self, record: CompareRecord) {
+        let correlation_id = record.correlation_id.clone();
+        if self
+            .writer
+            .try_send(Write::Record(Box::new(record)))
+            .is_err()
+        {
+            tracing::warn!(
+                correlation_id = %correlation_id,
+                "Compare store writer is behind, dropping compare result"
+            );
+        }
+    }
+
+    /// Wait until records appended so far are written.
+    #[cfg(test)]
+    pub async fn flush(&self) {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        if self.writer.send(Write::Flush(tx)).await.is_ok() {
+            let _ = rx.await;
+        }
+    }
+
+    /// Matching records, newest first, and whether more than `limit` matched.
+    /// Reads the whole file, so call it off the async workers.
+    pub fn query(
+        &self,
+        filter: &CompareFilter,
+        limit: usize,
+    ) -> std::io::Result<(Vec<CompareRecord>, bool)> {
+        // Only the newest `limit + 1` are kept while scanning
+        let mut newest = VecDeque::with_capacity(limit + 1);
+        self.scan(filter, |record| {
+            if newest.len() > limit {
+                newest.pop_front();
+            }
+            newest.push_back(record);
+        })?;
+        let has_more = newest.len() > limit;
+        Ok((newest.into_iter().rev().take(limit).collect(), has_more))
+    }
+
+    /// Match rates of the matching records, overall and per compare model.
+    /// Reads the whole file, like `query`.
+    pub fn summary(&self, filter: &CompareFilter) -> std::io::Result<CompareSummary> {
+        let mut overall = Tally::default();
+        let mut by_model: BTreeMap<String, Tally> = BTreeMap::new();
+        self.scan(filter, |record| {
+            overall.add(&record);
+            by_model
+                .entry(record.request.model.clone())
+                .or_default()
+                .add(&record);
+        })?;
+        Ok(CompareSummary {
+            overall: overall.summary(),
+            by_model: by_model
+                .into_iter()
+                .map(|(model, tally)| (model, tally.summary()))
+                .collect(),
+        })
+    }
+
+    /// Feed every matching record to `f`, oldest first. Lines that don't
+    /// parse (e.g. a write cut short by a crash) are skipped.
+    fn scan(
+        &self,
+        filter: &CompareFilter,
+        mut f: impl FnMut(CompareRecord),
+    ) -> std::io::Result<()> {
+        let file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for line in std::io::BufReader::new(file).lines() {
+            let Ok(record) = serde_json::from_str::<CompareRecord>(&line?) else {
+                continue;
+            };
+            if filter.matches(&record) {
+                f(record);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writer task: owns the file handle and appends each queued record as one
+/// line.
+async fn write_records(path: PathBuf, mut writes: mpsc::Receiver<Write>) {
+    let mut file = None;
+    while let Some(write) = writes.recv().await {
+        match write {
+            Write::Record(record) => {
+                if let Err(e) = write_record(&path, &mut file, &record).await {
+                    // Reopen for the next record
+                    file = None;
+                    tracing::warn!(
+                        correlation_id = %record.correlation_id,
+                        path = %path.display(),
+                        error = %e,
+                        "Failed to store compare result"
+                    );
+                }
+            }
+            #[cfg(test)]
+            Write::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+async fn write_record(
+    path: &Path,
+    file: &mut Option<tokio::fs::File>,
+    record: &CompareRecord,
+) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    let file = match file {
+        Some(file) => file,
+        None => file.insert(
+            tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await?,
+        ),
+    };
+    file.write_all(&line).await?;
+    file.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::diff;
+
+    fn record(id: &str, model: &str, timestamp_ms: u64, target_status: u16) -> CompareRecord {
+        let primary = ResponseSummary {
+            status: 200,
+            text: "hello world".into(),
+            ..Default::default()
+        };
+        let target = ResponseSummary {
+            status: target_status,
+            text: "hello world".into(),
+            ..Default::default()
+        };
+        let diff = diff::compare(&primary, &target);
+        let mut record = CompareRecord::new(
+            id,
+            None,
+            RequestSummary::new(None, "claude-sonnet-4-5", model),
+            primary,
+            target,
+            diff,
+        );
+        record.timestamp_ms = timestamp_ms;
+        record
+    }
+
+    #[tokio::test]
+    async fn filters_and_pages_newest_first() {
+        let dir = std::env::temp_dir().join(format!("cc-proxy-compare-{}", uuid::Uuid::new_v4()));
+        let store = CompareStore::open(dir.join("compare.jsonl").to_str().unwrap()).unwrap();
+        store.append(record("a", "m1", 1_000, 200));
+        store.append(record("b", "m2", 2_000, 500));
+        store.append(record("c", "m1", 3_000, 500));
+        store.flush().await;
+
+        let (all, has_more) = store.query(&CompareFilter::default(), 2).unwrap();
+        let ids: Vec<&str> = all.iter().map(|r| r.correlation_id.as_str()).collect();
+        assert_eq!(ids, ["c", "b"]);
+        assert!(has_more);
+        let (all, has_more) = store.query(&CompareFilter::default(), 3).unwrap();
+        assert_eq!((all.len(), has_more), (3, false));
+
+        let filter = CompareFilter {
+            model: Some("m1".into()),
+            mismatch: Some(Mismatch::Status),
+            ..Default::default()
+        };
+        let (hits, _) = store.query(&filter, 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].correlation_id, "c");
+
+        let filter = CompareFilter {
+            since: Some(2),
+            until: Some(3),
+            ..Default::default()
+        };
+        let (hits, _) = store.query(&filter, 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].correlation_id, "b");
+
+        // Primary model matches too
+        let filter = CompareFilter {
+            model: Some("claude-sonnet-4-5".into()),
+            matched: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(store.query(&filter, 10).unwrap().0.len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn summary_reports_match_rates_per_model() {
+        let dir = std::env::temp_dir().join(format!("cc-proxy-compare-{}", uuid::Uuid::new_v4()));
+        let store = CompareStore::open(dir.join("compare.jsonl").to_str().unwrap()).unwrap();
+        assert_eq!(
+            store
+                .summary(&CompareFilter::default())
+                .unwrap()
+                .overall
+                .total,
+            0
+        );
+
+        store.append(record("a", "m1", 1_000, 200));
+        store.append(record("b", "m1", 2_000, 500));
+        let mut judged = record("c", "m2", 3_000, 200);
+        judged.judge = Some(JudgeResult {
+            model: "judge".into(),
+            score: Some(8.0),
+            rationale: Some("Equivalent.".into()),
+            error: None,
+        });
+        store.append(judged);
+        store.flush().await;
+
+        let summary = store.summary(&CompareFilter::default()).unwrap();
+        assert_eq!(summary.overall.total, 3);
+        assert_eq!(summary.overall.matched, 2);
+        let m1 = &summary.by_model["m1"];
+        assert_eq!(m1.match_rate, 0.5);
+        assert_eq!(m1.match_rates["status"], 0.5);
+        assert_eq!(m1.match_rates["text"], 1.0);
+        assert_eq!(m1.mean_judge_score, None);
+        assert_eq!(summary.by_model["m2"].match_rate, 1.0);
+        assert_eq!(summary.by_model["m2"].judged, 1);
+        assert_eq!(summary.by_model["m2"].mean_judge_score, Some(8.0));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
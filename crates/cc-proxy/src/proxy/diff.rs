@@ -0,0 +1,362 @@
+//! Structured diff of a compare pair: the primary response the client
+//! received and the target's response to the same request.
+//!
+//! Both sides are reduced to a `ResponseSummary` (JSON and SSE bodies alike)
+//! and compared field by field. Deltas are target minus primary.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::openinference;
+
+/// Text similarity below which a pair counts as a `text` mismatch.
+pub const TEXT_MISMATCH_BELOW: f64 = 0.5;
+
+/// One side of a compare pair as observed by the proxy.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResponseSummary {
+    /// HTTP status; 0 when no response arrived (connect error, timeout).
+    pub status: u16,
+    pub stop_reason: Option<String>,
+    pub text: String,
+    pub tool_calls: Vec<ToolCall>,
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+    /// Milliseconds from request sent to the first body byte.
+    pub ttft_ms: Option<u64>,
+    /// Milliseconds from request sent to the last body byte.
+    pub total_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub name: String,
+    /// Parsed arguments; the raw string when they are not valid JSON.
+    pub input: Value,
+}
+
+impl ResponseSummary {
+    /// Summarize an Anthropic response body (JSON or SSE).
+    pub fn from_body(status: u16, body: &[u8], ttft_ms: Option<u64>, total_ms: u64) -> Self {
+        let parsed = openinference::parse_response(body).unwrap_or_default();
+        Self {
+            status,
+            stop_reason: parsed.stop_reason,
+            text: parsed.text_content,
+            tool_calls: parsed
+                .tool_calls
+                .into_iter()
+                .map(|tc| ToolCall {
+                    input: serde_json::from_str(&tc.arguments)
+                        .unwrap_or(Value::String(tc.arguments)),
+                    name: tc.name,
+                })
+                .collect(),
+            input_tokens: parsed.input_tokens.map(|n| n.max(0) as u64),
+            output_tokens: parsed.output_tokens.map(|n| n.max(0) as u64),
+            ttft_ms,
+            total_ms,
+        }
+    }
+
+    /// A request that got no response.
+    pub fn failed(total_ms: u64) -> Self {
+        Self {
+            total_ms,
+            ..Default::default()
+        }
+    }
+}
+
+/// Kind of disagreement between the two sides of a pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mismatch {
+    Status,
+    StopReason,
+    ToolNames,
+    Arguments,
+    Text,
+}
+
+impl Mismatch {
+    pub const ALL: [Mismatch; 5] = [
+        Mismatch::Status,
+        Mismatch::StopReason,
+        Mismatch::ToolNames,
+        Mismatch::Arguments,
+        Mismatch::Text,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mismatch::Status => "status",
+            Mismatch::StopReason => "stop_reason",
+            Mismatch::ToolNames => "tool_names",
+            Mismatch::Arguments => "arguments",
+            Mismatch::Text => "text",
+        }
+    }
+}
+
+/// How a tool-call argument differs, seen from the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffKind {
+    Changed,
+    /// Present in the primary arguments only.
+    Missing,
+    /// Present in the target arguments only.
+    Extra,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArgumentDiff {
+    /// Position of the tool call in both responses.
+    pub index: usize,
+    pub tool: String,
+    /// JSON Pointer into the arguments (`""` for the whole value).
+    pub path: String,
+    pub kind: DiffKind,
+}
+
+/// Structured comparison of a primary and a target response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompareDiff {
+    pub status_match: bool,
+    pub stop_reason_match: bool,
+    pub tool_names_match: bool,
+    /// Argument differences of tool calls with the same name at the same
+    /// position.
+    pub argument_diffs: Vec<ArgumentDiff>,
+    /// Word-level Dice similarity of the text output, 0.0–1.0.
+    pub text_similarity: f64,
+    pub input_tokens_delta: Option<i64>,
+    pub output_tokens_delta: Option<i64>,
+    pub ttft_delta_ms: Option<i64>,
+    pub latency_delta_ms: i64,
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// Diff a target response against the primary one.
+pub fn compare(primary: &ResponseSummary, target: &ResponseSummary) -> CompareDiff {
+    let status_match = primary.status == target.status;
+    let stop_reason_match = primary.stop_reason == target.stop_reason;
+    let tool_names_match = primary
+        .tool_calls
+        .iter()
+        .map(|tc| &tc.name)
+        .eq(target.tool_calls.iter().map(|tc| &tc.name));
+
+    let mut argument_diffs = Vec::new();
+    for (index, (p, t)) in primary
+        .tool_calls
+        .iter()
+        .zip(&target.tool_calls)
+        .enumerate()
+    {
+        if p.name != t.name {
+            continue;
+        }
+        let mut paths = Vec::new();
+        json_diff(&p.input, &t.input, String::new(), &mut paths);
+        argument_diffs.extend(paths.into_iter().map(|(path, kind)| ArgumentDiff {
+            index,
+            tool: p.name.clone(),
+            path,
+            kind,
+        }));
+    }
+
+    let text_similarity = text_similarity(&primary.text, &target.text);
+    let delta = |p: Option<u64>, t: Option<u64>| Some(t? as i64 - p? as i64);
+
+    let mut mismatches = Vec::new();
+    if !status_match {
+        mismatches.push(Mismatch::Status);
+    }
+    if !stop_reason_match {
+        mismatches.push(Mismatch::StopReason);
+    }
+    if !tool_names_match {
+        mismatches.push(Mismatch::ToolNames);
+    }
+    if !argument_diffs.is_empty() {
+        mismatches.push(Mismatch::Arguments);
+    }
+    if text_similarity < TEXT_MISMATCH_BELOW {
+        mismatches.push(Mismatch::Text);
+    }
+
+    CompareDiff {
+        status_match,
+        stop_reason_match,
+        tool_names_match,
+        argument_diffs,
+        text_similarity,
+        input_tokens_delta: delta(primary.input_tokens, target.input_tokens),
+        output_tokens_delta: delta(primary.output_tokens, target.output_tokens),
+        ttft_delta_ms: delta(primary.ttft_ms, target.ttft_ms),
+        latency_delta_ms: target.total_ms as i64 - primary.total_ms as i64,
+        mismatches,
+    }
+}
+
+/// Collect the JSON Pointer paths where `target` differs from `primary`.
+fn json_diff(primary: &Value, target: &Value, path: String, out: &mut Vec<(String, DiffKind)>) {
+    match (primary, target) {
+        (Value::Object(p), Value::Object(t)) => {
+            for (key, pv) in p {
+                let child = format!("{path}/{}", key.replace('~', "~0").replace('/', "~1"));
+                match t.get(key) {
+                    Some(tv) => json_diff(pv, tv, child, out),
+                    None => out.push((child, DiffKind::Missing)),
+                }
+            }
+            for key in t.keys().filter(|k| !p.contains_key(*k)) {
+                let child = format!("{path}/{}", key.replace('~', "~0").replace('/', "~1"));
+                out.push((child, DiffKind::Extra));
+            }
+        }
+        (Value::Array(p), Value::Array(t)) => {
+            for i in 0..p.len().max(t.len()) {
+                let child = format!("{path}/{i}");
+                match (p.get(i), t.get(i)) {
+                    (Some(pv), Some(tv)) => json_diff(pv, tv, child, out),
+                    (Some(_), None) => out.push((child, DiffKind::Missing)),
+                    _ => out.push((child, DiffKind::Extra)),
+                }
+            }
+        }
+        (p, t) if p != t => out.push((path, DiffKind::Changed)),
+        _ => {}
+    }
+}
+
+/// Dice coefficient over the multisets of whitespace-separated words. Two
+/// empty texts are identical.
+fn text_similarity(a: &str, b: &str) -> f64 {
+    let mut counts: HashMap<&str, (usize, usize)> = HashMap::new();
+    for word in a.split_whitespace() {
+        counts.entry(word).or_default().0 += 1;
+    }
+    for word in b.split_whitespace() {
+        counts.entry(word).or_default().1 += 1;
+    }
+    let (total_a, total_b, common) = counts.values().fold((0, 0, 0), |(ta, tb, c), &(na, nb)| {
+        (ta + na, tb + nb, c + na.min(nb))
+    });
+    if total_a + total_b == 0 {
+        return 1.0;
+    }
+    2.0 * common as f64 / (total_a + total_b) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn summary(stop_reason: &str, text: &str, tools: &[(&str, Value)]) -> ResponseSummary {
+        ResponseSummary {
+            status: 200,
+            stop_reason: Some(stop_reason.into()),
+            text: text.into(),
+            tool_calls: tools
+                .iter()
+                .map(|(name, input)| ToolCall {
+                    name: name.to_string(),
+                    input: input.clone(),
+                })
+                .collect(),
+            input_tokens: Some(100),
+            output_tokens: Some(20),
+            ttft_ms: Some(300),
+            total_ms: 1_000,
+        }
+    }
+
+    #[test]
+    fn identical_responses_match() {
+        let p = summary(
+            "tool_use",
+            "Let me look.",
+            &[("Read", json!({"path": "a.rs"}))],
+        );
+        let diff = compare(&p, &p.clone());
+        assert!(diff.mismatches.is_empty());
+        assert_eq!(diff.text_similarity, 1.0);
+        assert_eq!(diff.latency_delta_ms, 0);
+        assert_eq!(diff.output_tokens_delta, Some(0));
+    }
+
+    #[test]
+    fn reports_argument_paths_and_deltas() {
+        let p = summary(
+            "tool_use",
+            "Running the tests now",
+            &[("Bash", json!({"command": "cargo test", "timeout": 60}))],
+        );
+        let mut t = summary(
+            "tool_use",
+            "Running the tests",
+            &[(
+                "Bash",
+                json!({"command": "cargo test --all", "description": "x"}),
+            )],
+        );
+        t.output_tokens = Some(35);
+        t.ttft_ms = None;
+        t.total_ms = 2_500;
+
+        let diff = compare(&p, &t);
+        assert_eq!(diff.mismatches, vec![Mismatch::Arguments]);
+        let paths: Vec<(&str, DiffKind)> = diff
+            .argument_diffs
+            .iter()
+            .map(|d| (d.path.as_str(), d.kind))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                ("/command", DiffKind::Changed),
+                ("/timeout", DiffKind::Missing),
+                ("/description", DiffKind::Extra),
+            ]
+        );
+        assert_eq!(diff.output_tokens_delta, Some(15));
+        assert_eq!(diff.ttft_delta_ms, None);
+        assert_eq!(diff.latency_delta_ms, 1_500);
+        // 3 shared words of 4 + 3
+        assert!((diff.text_similarity - 6.0 / 7.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn different_tools_and_stop_reasons_mismatch() {
+        let p = summary("tool_use", "", &[("Read", json!({}))]);
+        let t = summary("end_turn", "I cannot do that.", &[]);
+        let diff = compare(&p, &t);
+        assert_eq!(
+            diff.mismatches,
+            vec![Mismatch::StopReason, Mismatch::ToolNames, Mismatch::Text]
+        );
+        assert!(diff.argument_diffs.is_empty());
+    }
+
+    #[test]
+    fn summarizes_sse_bodies() {
+        let body = b"event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"role\":\"assistant\",\"usage\":{\"input_tokens\":7}}}\n\nevent: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"tool_use\",\"name\":\"Read\"}}\n\nevent: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{\\\"path\\\":\\\"a\\\"}\"}}\n\nevent: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"tool_use\"},\"usage\":{\"output_tokens\":3}}\n\n";
+        let s = ResponseSummary::from_body(200, body, Some(5), 9);
+        assert_eq!(s.stop_reason.as_deref(), Some("tool_use"));
+        assert_eq!(
+            s.tool_calls,
+            [ToolCall {
+                name: "Read".into(),
+                input: json!({"path": "a"})
+            }]
+        );
+        assert_eq!((s.input_tokens, s.output_tokens), (Some(7), Some(3)));
+    }
+}
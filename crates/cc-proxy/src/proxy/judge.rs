@@ -0,0 +1,381 @@
+//! LLM-as-judge scoring of compare pairs.
+//!
+//! With `[compare.judge]` set, a sampled share of mirrored requests carry a
+//! `JudgeRequest`. Once one of their pairs is complete, the judge model gets
+//! the client's conversation, both responses and the configured rubric, and
+//! answers with a JSON score and rationale. The outcome goes on the
+//! `compare_result` span and into the stored record; a failed judge call is
+//! recorded as an error and never affects the pair itself.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::compare_queue::{CompareQueues, QueueStats};
+use super::diff::ResponseSummary;
+use super::primary;
+use crate::convert::anthropic_to_openai::anthropic_to_openai;
+use crate::models::TargetProtocol;
+
+/// How to judge the pairs of one sampled request.
+pub struct JudgeRequest {
+    pub model: String,
+    /// Base URL of the judge: a local model's endpoint or `passthrough.url`.
+    pub target_url: String,
+    pub protocol: TargetProtocol,
+    pub auth: HeaderMap,
+    pub timeout: Duration,
+    /// Judge requests in flight before more are queued.
+    pub max_concurrent: usize,
+    /// Queue bounds, from `compare.queue_depth` and `compare.queue_max_wait_secs`.
+    pub queue_depth: usize,
+    pub queue_max_wait: Duration,
+    pub max_tokens: u64,
+    pub rubric: String,
+    /// The client's conversation, rendered by `render_context`.
+    pub context: String,
+}
+
+/// Outcome of judging one pair.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JudgeResult {
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rationale: Option<String>,
+    /// Why there is no score: the call failed or was dropped, or the answer
+    /// could not be parsed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl JudgeResult {
+    fn failed(model: &str, error: impl Into<String>) -> Self {
+        Self {
+            model: model.to_string(),
+            score: None,
+            rationale: None,
+            error: Some(error.into()),
+        }
+    }
+}
+
+/// Sends judge requests, with their own per-model concurrency limit.
+pub struct Judge {
+    client: reqwest::Client,
+    queues: CompareQueues,
+}
+
+impl Judge {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            queues: CompareQueues::default(),
+        }
+    }
+
+    /// Queue depth, in-flight count and drop counts per judge model.
+    pub fn queue_stats(&self) -> BTreeMap<String, QueueStats> {
+        self.queues.snapshot()
+    }
+
+    /// Score `target` against `primary`. Records `status` and `latency_ms`
+    /// on the current span.
+    pub async fn score(
+        &self,
+        request: &JudgeRequest,
+        primary: &ResponseSummary,
+        target: &ResponseSummary,
+    ) -> JudgeResult {
+        let model = request.model.as_str();
+        let _permit = match self
+            .queues
+            .acquire(
+                model,
+                request.max_concurrent,
+                request.queue_depth,
+                request.queue_max_wait,
+            )
+            .await
+        {
+            Ok(permit) => permit,
+            Err(reason) => return JudgeResult::failed(model, reason.as_str()),
+        };
+
+        let body = serde_json::json!({
+            "model": model,
+            "max_tokens": request.max_tokens,
+            "temperature": 0,
+            "messages": [{
+                "role": "user",
+                "content": prompt(&request.rubric, &request.context, primary, target),
+            }],
+        });
+        let body = match request.protocol {
+            TargetProtocol::Anthropic => body,
+            TargetProtocol::Openai => match anthropic_to_openai(&body) {
+                Ok(converted) => converted,
+                Err(e) => return JudgeResult::failed(model, format!("conversion failed: {e}")),
+            },
+        };
+
+        let start = Instant::now();
+        let url = format!("{}{}", request.target_url, request.protocol.messages_path());
+        let result = self
+            .client
+            .post(&url)
+            .timeout(request.timeout)
+            .header("anthropic-version", primary::ANTHROPIC_VERSION)
+            .headers(request.auth.clone())
+            .json(&body)
+            .send()
+            .await;
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => return JudgeResult::failed(model, format!("request failed: {e}")),
+        };
+        let status = response.status().as_u16();
+        let span = tracing::Span::current();
+        span.record("status", status);
+        let bytes = match response.bytes().await {
+            Ok(bytes) => bytes,
+            Err(e) => return JudgeResult::failed(model, format!("request failed: {e}")),
+        };
+        span.record("latency_ms", start.elapsed().as_millis() as u64);
+        if !(200..300).contains(&status) {
+            return JudgeResult::failed(model, format!("status {status}"));
+        }
+
+        let bytes = match request.protocol {
+            TargetProtocol::Anthropic => bytes.to_vec(),
+            TargetProtocol::Openai => primary::translate_openai_body(status, false, &bytes),
+        };
+        let answer = ResponseSummary::from_body(status, &bytes, None, 0).text;
+        match parse_verdict(&answer) {
+            Some((score, rationale)) => JudgeResult {
+                model: model.to_string(),
+                score: Some(score),
+                rationale: Some(rationale),
+                error: None,
+            },
+            None => JudgeResult::failed(model, "answer has no JSON score"),
+        }
+    }
+}
+
+/// Render the client's request (system prompt, tools and messages) as plain
+/// text, keeping the last `max_chars` characters.
+pub fn render_context(request: &Value, max_chars: usize) -> String {
+    let mut out = String::new();
+    if let Some(system) = request.get("system") {
+        out.push_str(&format!("[system]\n{}\n\n", content_text(system)));
+    }
+    if let Some(tools) = request.get("tools").and_then(|t| t.as_array()) {
+        let names: Vec<&str> = tools
+            .iter()
+            .filter_map(|t| t.get("name").and_then(|n| n.as_str()))
+            .collect();
+        if !names.is_empty() {
+            out.push_str(&format!("[tools]\n{}\n\n", names.join(", ")));
+        }
+    }
+    for message in request
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .into_iter()
+        .flatten()
+    {
+        let role = message
+            .get("role")
+            .and_then(|r| r.as_str())
+            .unwrap_or("user");
+        let content = message.get("content").map(content_text).unwrap_or_default();
+        out.push_str(&format!("[{role}]\n{content}\n\n"));
+    }
+
+    let text = out.trim_end();
+    let len = text.chars().count();
+    if len <= max_chars {
+        return text.to_string();
+    }
+    let start = text
+        .char_indices()
+        .nth(len - max_chars)
+        .map_or(0, |(i, _)| i);
+    format!("[…]{}", &text[start..])
+}
+
+/// Text of a string or content-block array.
+fn content_text(content: &Value) -> String {
+    let Some(blocks) = content.as_array() else {
+        return content.as_str().unwrap_or_default().to_string();
+    };
+    let parts: Vec<String> = blocks
+        .iter()
+        .map(|block| match block.get("type").and_then(|t| t.as_str()) {
+            Some("text") => block
+                .get("text")
+                .and_then(|t| t.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            Some("tool_use") => format!(
+                "(tool call {}: {})",
+                block
+                    .get("name")
+                    .and_then(|n| n.as_str())
+                    .unwrap_or_default(),
+                block.get("input").cloned().unwrap_or_default()
+            ),
+            Some("tool_result") => format!(
+                "(tool result: {})",
+                block.get("content").map(content_text).unwrap_or_default()
+            ),
+            Some(other) => format!("({other})"),
+            None => String::new(),
+        })
+        .collect();
+    parts.join("\n")
+}
+
+/// One side of a pair as the judge sees it.
+fn response_text(response: &ResponseSummary) -> String {
+    if !(200..300).contains(&response.status) {
+        return format!("(request failed with status {})", response.status);
+    }
+    let mut parts = vec![response.text.clone()];
+    for call in &response.tool_calls {
+        parts.push(format!("(tool call {}: {})", call.name, call.input));
+    }
+    parts.retain(|p| !p.is_empty());
+    parts.join("\n")
+}
+
+fn prompt(
+    rubric: &str,
+    context: &str,
+    primary: &ResponseSummary,
+    target: &ResponseSummary,
+) -> String {
+    format!(
+        "{rubric}\n\n\
+         <conversation>\n{context}\n</conversation>\n\n\
+         <reference_response>\n{}\n</reference_response>\n\n\
+         <candidate_response>\n{}\n</candidate_response>\n\n\
+         Answer with only a JSON object: \
+         {{\"score\": <number>, \"rationale\": \"<one or two sentences>\"}}",
+        response_text(primary),
+        response_text(target),
+    )
+}
+
+/// The score and rationale of the first JSON object in a judge's answer.
+fn parse_verdict(answer: &str) -> Option<(f64, String)> {
+    let start = answer.find('{')?;
+    let end = answer.rfind('}')?;
+    let verdict: Value = serde_json::from_str(answer.get(start..=end)?).ok()?;
+    let score = verdict.get("score")?.as_f64()?;
+    let rationale = verdict
+        .get("rationale")
+        .and_then(|r| r.as_str())
+        .unwrap_or_default()
+        .to_string();
+    Some((score, rationale))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::future::IntoFuture;
+
+    #[test]
+    fn renders_conversation_and_keeps_its_end() {
+        let request = json!({
+            "system": [{"type": "text", "text": "Be brief."}],
+            "tools": [{"name": "Read"}, {"name": "Bash"}],
+            "messages": [
+                {"role": "user", "content": "Fix the bug"},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "t1", "name": "Read", "input": {"path": "a.rs"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "t1", "content": "fn main() {}"}
+                ]}
+            ]
+        });
+        let context = render_context(&request, 10_000);
+        assert_eq!(
+            context,
+            "[system]\nBe brief.\n\n[tools]\nRead, Bash\n\n[user]\nFix the bug\n\n\
+             [assistant]\n(tool call Read: {\"path\":\"a.rs\"})\n\n\
+             [user]\n(tool result: fn main() {})"
+        );
+        assert_eq!(render_context(&request, 13), "[…]fn main() {})");
+    }
+
+    #[test]
+    fn parses_verdicts() {
+        assert_eq!(
+            parse_verdict("Sure.\n```json\n{\"score\": 7.5, \"rationale\": \"Close.\"}\n```"),
+            Some((7.5, "Close.".to_string()))
+        );
+        assert_eq!(parse_verdict("{\"score\": 3}"), Some((3.0, String::new())));
+        assert_eq!(parse_verdict("{\"score\": \"high\"}"), None);
+        assert_eq!(parse_verdict("no verdict"), None);
+    }
+
+    #[tokio::test]
+    async fn scores_pairs_with_the_judge_model() {
+        let app = axum::Router::new().route(
+            "/v1/messages",
+            axum::routing::post(|axum::Json(body): axum::Json<Value>| async move {
+                let prompt = body["messages"][0]["content"].as_str().unwrap_or_default();
+                let verdict = if prompt.starts_with("Grade it.") && prompt.contains("[user]\nhi") {
+                    r#"{"score": 9, "rationale": "Same answer."}"#
+                } else {
+                    "no idea"
+                };
+                axum::Json(json!({
+                    "type": "message",
+                    "content": [{"type": "text", "text": verdict}],
+                    "stop_reason": "end_turn"
+                }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        let request = JudgeRequest {
+            model: "judge".into(),
+            target_url: url,
+            protocol: TargetProtocol::Anthropic,
+            auth: HeaderMap::new(),
+            timeout: Duration::from_secs(5),
+            max_concurrent: 1,
+            queue_depth: 0,
+            queue_max_wait: Duration::from_secs(1),
+            max_tokens: 256,
+            rubric: "Grade it.".into(),
+            context: render_context(
+                &json!({"messages": [{"role": "user", "content": "hi"}]}),
+                100,
+            ),
+        };
+        let response = ResponseSummary {
+            status: 200,
+            text: "hello".into(),
+            ..Default::default()
+        };
+        let result = Judge::new(reqwest::Client::new())
+            .score(&request, &response, &response)
+            .await;
+        assert_eq!(result.score, Some(9.0));
+        assert_eq!(result.rationale.as_deref(), Some("Same answer."));
+        assert_eq!(result.error, None);
+    }
+}
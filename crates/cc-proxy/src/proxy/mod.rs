@@ -1,7 +1,13 @@
 //! Proxy routing: primary forwarding, shadow dispatch, compare dispatch, and correlation.
 
 pub mod compare;
+pub mod compare_policy;
+pub mod compare_queue;
+pub mod compare_stats;
+pub mod compare_store;
 pub mod correlation;
+pub mod diff;
+pub mod judge;
 pub mod primary;
 
 // shadow.rs is retained for reference but no longer compiled —
@@ -7,9 +7,10 @@
 //! attribute extraction without affecting the stream sent to the client.
 
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use axum::body::Body;
 use axum::http::{HeaderMap, HeaderValue, Method, StatusCode};
@@ -19,8 +20,17 @@ use futures_core::Stream;
 use tracing::Instrument;
 
 use super::correlation::CORRELATION_HEADER;
+use super::diff::ResponseSummary;
+use crate::config::RetryConfig;
+use crate::convert::openai_to_anthropic::{error_to_anthropic, openai_to_anthropic, SseTranslator};
+use crate::models::{EndpointLease, TargetProtocol};
 use crate::openinference;
 use crate::stats::ProxyStats;
+use crate::targets::{overloaded_response, CircuitBreakers, CircuitState};
+
+/// `anthropic-version` the proxy sends on requests it originates: judge calls
+/// and `/v1/chat/completions` clients that lack one.
+pub const ANTHROPIC_VERSION: &str = "2023-06-01";
 
 /// Headers that should NOT be forwarded (hop-by-hop headers).
 const HOP_BY_HOP_HEADERS: &[&str] = &[
@@ -35,10 +45,47 @@ const HOP_BY_HOP_HEADERS: &[&str] = &[
     "trailers",
 ];
 
+/// Client headers not forwarded to local targets: the body is rewritten and
+/// the target's own credentials replace the client's.
+const TARGET_SKIPPED_HEADERS: &[&str] = &["content-type", "content-length", "x-api-key"];
+
+/// A forward that failed before any response bytes reached the client:
+/// connect error, timeout, or a 5xx status from upstream.
+///
+/// Carries the response that would have been returned so the caller can
+/// either try another upstream or hand it to the client unchanged. Its body
+/// records nothing (root span attributes, stats, observer) unless it is
+/// handed on through `into_response`.
+pub struct FailedForward {
+    /// Short description for logs and spans (e.g. `"timeout"`, `"status 503"`).
+    pub reason: String,
+    pub response: Box<Response>,
+    /// Enables recording in the response's `TeeBody`, if it has one.
+    record: Option<Arc<AtomicBool>>,
+}
+
+impl FailedForward {
+    fn new(reason: impl Into<String>, response: Response) -> Self {
+        Self {
+            reason: reason.into(),
+            response: Box::new(response),
+            record: None,
+        }
+    }
+
+    pub fn into_response(self) -> Response {
+        if let Some(record) = self.record {
+            record.store(true, Ordering::Relaxed);
+        }
+        *self.response
+    }
+}
+
 /// A stream wrapper that passes through bytes unchanged while accumulating a
-/// copy of all data. When the inner stream completes, it calls
-/// `set_response_attributes()` on the held tracing span and drops the span
-/// clone (which closes the OTel span).
+/// copy of all data. When the inner stream completes — or the body is dropped
+/// first, as hyper does once a known `content-length` has been sent — it
+/// calls `set_response_attributes()` on the held tracing span and drops the
+/// span clone (which closes the OTel span).
 ///
 /// Also records timing attributes on the root span:
 /// - `ttft_ms`: milliseconds from `start` to first chunk received
@@ -53,8 +100,59 @@ struct TeeBody {
     start: Instant,
     /// Whether the first chunk has been seen (to record ttft_ms exactly once).
     first_chunk_seen: bool,
+    /// Endpoint reservation released when the body is dropped.
+    _lease: Option<EndpointLease>,
+    /// Upstream status, reported to `observer`.
+    status: u16,
+    /// Recorded `ttft_ms`, reported to `observer`.
+    ttft_ms: Option<u64>,
+    /// Called with the complete response once the stream ends.
+    observer: Option<ResponseObserver>,
+    /// Whether the response has been recorded.
+    finished: bool,
+    /// Whether the response reaches the client and should be recorded at
+    /// all; unset while it is a `FailedForward` that may be discarded.
+    record: Arc<AtomicBool>,
+}
+
+impl TeeBody {
+    /// Record timing, response attributes, stats and the observer, once.
+    fn finish(&mut self) {
+        if std::mem::replace(&mut self.finished, true) || !self.record.load(Ordering::Relaxed) {
+            return;
+        }
+        // Record total end-to-end streaming duration (request sent → last byte).
+        let total_ms = self.start.elapsed().as_millis() as u64;
+        self.span.record("total_duration_ms", total_ms);
+
+        let observer = self.observer.take();
+        if let Ok(buf) = self.buffer.lock() {
+            openinference::set_response_attributes(&self.span, &buf, self.is_streaming);
+            if let Some(ref stats) = self.stats {
+                extract_and_record_stats(stats, &buf, self.is_streaming);
+            }
+            if let Some(observer) = observer {
+                observer(ResponseSummary::from_body(
+                    self.status,
+                    &buf,
+                    self.ttft_ms,
+                    total_ms,
+                ));
+            }
+        }
+    }
+}
+
+impl Drop for TeeBody {
+    fn drop(&mut self) {
+        self.finish();
+    }
 }
 
+/// Receives a summary of the complete response once its body has streamed
+/// to the client (compare mode pairs it with the target's response).
+pub type ResponseObserver = Box<dyn FnOnce(ResponseSummary) + Send>;
+
 impl Stream for TeeBody {
     type Item = Result<Bytes, reqwest::Error>;
 
@@ -65,8 +163,9 @@ impl Stream for TeeBody {
                 // upstream byte arrives after the request was sent.
                 if !self.first_chunk_seen {
                     self.first_chunk_seen = true;
-                    self.span
-                        .record("ttft_ms", self.start.elapsed().as_millis() as u64);
+                    let ttft_ms = self.start.elapsed().as_millis() as u64;
+                    self.span.record("ttft_ms", ttft_ms);
+                    self.ttft_ms = Some(ttft_ms);
                 }
                 if let Ok(mut buf) = self.buffer.lock() {
                     buf.extend_from_slice(&chunk);
@@ -75,17 +174,8 @@ impl Stream for TeeBody {
             }
             Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
             Poll::Ready(None) => {
-                // Record total end-to-end streaming duration (request sent → last byte).
-                self.span
-                    .record("total_duration_ms", self.start.elapsed().as_millis() as u64);
-
                 // Stream complete — set response attributes
-                if let Ok(buf) = self.buffer.lock() {
-                    openinference::set_response_attributes(&self.span, &buf, self.is_streaming);
-                    if let Some(ref stats) = self.stats {
-                        extract_and_record_stats(stats, &buf, self.is_streaming);
-                    }
-                }
+                self.finish();
                 Poll::Ready(None)
             }
             Poll::Pending => Poll::Pending,
@@ -93,23 +183,114 @@ impl Stream for TeeBody {
     }
 }
 
+/// Translates an OpenAI chat-completions response body into Anthropic
+/// Messages format as it streams, ahead of `TeeBody` so tracing and stats
+/// see the Anthropic form. SSE is translated chunk by chunk; JSON bodies
+/// (responses and errors) are buffered and converted at end of stream.
+struct OpenAiTranslateBody {
+    inner: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
+    translation: Translation,
+    done: bool,
+}
+
+enum Translation {
+    Sse(SseTranslator),
+    Json { status: u16, buffer: Vec<u8> },
+}
+
+impl Stream for OpenAiTranslateBody {
+    type Item = Result<Bytes, reqwest::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => match self.translation {
+                    Translation::Sse(ref mut translator) => {
+                        let out = translator.push(&chunk);
+                        if !out.is_empty() {
+                            return Poll::Ready(Some(Ok(Bytes::from(out))));
+                        }
+                    }
+                    Translation::Json { ref mut buffer, .. } => buffer.extend_from_slice(&chunk),
+                },
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => {
+                    if self.done {
+                        return Poll::Ready(None);
+                    }
+                    self.done = true;
+                    let out = match self.translation {
+                        Translation::Sse(ref mut translator) => translator.finish(),
+                        Translation::Json { status, ref buffer } => {
+                            json_to_anthropic(status, buffer)
+                        }
+                    };
+                    if out.is_empty() {
+                        return Poll::Ready(None);
+                    }
+                    return Poll::Ready(Some(Ok(Bytes::from(out))));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Copy the client's `headers` onto an upstream request, except hop-by-hop
+/// headers, the correlation header (the caller sets its own) and `skip`.
+fn forward_request_headers(
+    mut builder: reqwest::RequestBuilder,
+    headers: &HeaderMap,
+    skip: &[&str],
+) -> reqwest::RequestBuilder {
+    for (name, value) in headers.iter() {
+        let name = name.as_str();
+        if HOP_BY_HOP_HEADERS.contains(&name) || name == CORRELATION_HEADER || skip.contains(&name)
+        {
+            continue;
+        }
+        builder = builder.header(name, value);
+    }
+    builder
+}
+
+/// Copy the upstream's response `headers` onto the client response, except
+/// hop-by-hop headers and `skip`.
+fn forward_response_headers(
+    mut builder: axum::http::response::Builder,
+    headers: &reqwest::header::HeaderMap,
+    skip: &[&str],
+) -> axum::http::response::Builder {
+    for (name, value) in headers.iter() {
+        let name = name.as_str();
+        if HOP_BY_HOP_HEADERS.contains(&name) || skip.contains(&name) {
+            continue;
+        }
+        builder = builder.header(name, value);
+    }
+    builder
+}
+
 /// Forward the raw request body to Anthropic and stream the response back.
 ///
 /// Used by the `/v1/messages` handler. The caller constructs the full URL
 /// from `upstream_base_url`. The `root_span` is the parent `proxy_request`
 /// span — TeeBody holds a clone of it so OpenInference response attributes
 /// are set on the root trace (keeping it open until streaming completes).
+/// `observer`, if any, receives the complete response.
 #[allow(clippy::too_many_arguments)]
 pub async fn forward_to_anthropic(
     client: &reqwest::Client,
     url: &str,
+    retry: &RetryConfig,
     headers: &HeaderMap,
     body: Bytes,
     correlation_id: &str,
     is_streaming: bool,
     root_span: tracing::Span,
     stats: ProxyStats,
-) -> Response {
+    observer: Option<ResponseObserver>,
+) -> Result<Response, FailedForward> {
     let host = url
         .trim_start_matches("https://")
         .trim_start_matches("http://")
@@ -121,32 +302,21 @@ pub async fn forward_to_anthropic(
 
     async {
         // Build the upstream request
-        let mut req_builder = client
+        let req_builder = client
             .post(url)
             .body(body)
             .header("content-type", "application/json")
             .header(CORRELATION_HEADER, correlation_id);
 
-        // Forward non-hop-by-hop headers from the original request
-        for (name, value) in headers.iter() {
-            let name_str = name.as_str().to_lowercase();
-            if HOP_BY_HOP_HEADERS.contains(&name_str.as_str()) {
-                continue;
-            }
-            // Skip content-type and correlation header (already set above)
-            if name_str == "content-type" || name_str == CORRELATION_HEADER {
-                continue;
-            }
-            // Skip content-length — reqwest sets it from the actual body,
-            // and the body may have changed size (e.g. model_override rewrite)
-            if name_str == "content-length" {
-                continue;
-            }
-            req_builder = req_builder.header(name, value);
-        }
+        // Forward non-hop-by-hop headers from the original request. Skip
+        // content-type (already set above) and content-length — reqwest sets
+        // it from the actual body, and the body may have changed size (e.g.
+        // model_override rewrite)
+        let req_builder =
+            forward_request_headers(req_builder, headers, &["content-type", "content-length"]);
 
-        // Send the request
-        let upstream_result = req_builder.send().await;
+        // Send the request, retrying 429/529 before anything is streamed
+        let upstream_result = send_with_retry(req_builder, retry).await;
 
         build_response(
             upstream_result,
@@ -155,6 +325,9 @@ pub async fn forward_to_anthropic(
             is_streaming,
             &root_span,
             Some(stats),
+            None,
+            TargetProtocol::Anthropic,
+            observer,
         )
     }
     .instrument(span)
@@ -164,20 +337,32 @@ pub async fn forward_to_anthropic(
 /// Forward the raw request body to a target endpoint and stream the response back.
 ///
 /// Used in `TargetOnly` mode. Identical to `forward_to_anthropic` except:
-/// - Hits `target_base_url/v1/messages` instead of Anthropic
-/// - Does NOT forward the `x-api-key` header (target handles auth separately)
+/// - Hits the target instead of Anthropic: `/v1/messages`, or
+///   `/v1/chat/completions` for `openai` targets, whose `body` is already
+///   converted and whose response is translated back to Anthropic format
+/// - Does NOT forward the client's `x-api-key` header; `auth` (the model's
+///   resolved credentials) is sent instead, replacing same-named client headers
+/// - Holds the endpoint `lease` until the response body has been streamed,
+///   so the registry's in-flight counts cover the full request lifetime
+/// - Goes through the target's circuit breaker: fails fast with an
+///   `overloaded_error` while it is open, and reports the outcome otherwise
 #[allow(clippy::too_many_arguments)]
 pub async fn forward_to_target(
     client: &reqwest::Client,
     target_base_url: &str,
+    lease: EndpointLease,
+    breakers: &CircuitBreakers,
+    retry: &RetryConfig,
     headers: &HeaderMap,
+    auth: &HeaderMap,
+    protocol: TargetProtocol,
     body: Bytes,
     correlation_id: &str,
     is_streaming: bool,
     root_span: tracing::Span,
     stats: ProxyStats,
-) -> Response {
-    let url = format!("{}/v1/messages", target_base_url);
+) -> Result<Response, FailedForward> {
+    let url = format!("{}{}", target_base_url, protocol.messages_path());
     let host = target_base_url
         .trim_start_matches("https://")
         .trim_start_matches("http://")
@@ -185,35 +370,40 @@ pub async fn forward_to_target(
         .next()
         .unwrap_or(target_base_url);
     let span = cc_tracing::primary_forward_span!(correlation_id, host);
+    span.record(
+        "endpoint",
+        format!("{}/{}", lease.endpoint_index, lease.endpoint_count),
+    );
+    span.record("lb_strategy", lease.strategy.as_str());
+    span.record("endpoint_in_flight", lease.in_flight_at_pick as u64);
+
+    match breakers.try_acquire(target_base_url) {
+        Ok(state) => span.record("circuit_state", state.as_str()),
+        Err(retry_after) => {
+            span.record("circuit_state", CircuitState::Open.as_str());
+            let _enter = span.enter();
+            tracing::warn!(target_url = %target_base_url, "Circuit open, failing fast");
+            return Err(FailedForward::new(
+                "circuit open",
+                overloaded_response(retry_after),
+            ));
+        }
+    };
     let start = Instant::now();
 
-    async {
+    let result = async {
         // Build the upstream request
-        let mut req_builder = client
+        let req_builder = client
             .post(&url)
             .body(body)
             .header("content-type", "application/json")
             .header(CORRELATION_HEADER, correlation_id);
 
         // Forward non-hop-by-hop headers, but skip x-api-key (target handles auth)
-        for (name, value) in headers.iter() {
-            let name_str = name.as_str().to_lowercase();
-            if HOP_BY_HOP_HEADERS.contains(&name_str.as_str()) {
-                continue;
-            }
-            if name_str == "content-type" || name_str == CORRELATION_HEADER {
-                continue;
-            }
-            if name_str == "content-length" {
-                continue;
-            }
-            if name_str == "x-api-key" {
-                continue;
-            }
-            req_builder = req_builder.header(name, value);
-        }
+        let req_builder = forward_request_headers(req_builder, headers, TARGET_SKIPPED_HEADERS)
+            .headers(auth.clone());
 
-        let upstream_result = req_builder.send().await;
+        let upstream_result = send_with_retry(req_builder, retry).await;
 
         build_response(
             upstream_result,
@@ -222,10 +412,16 @@ pub async fn forward_to_target(
             is_streaming,
             &root_span,
             Some(stats),
+            Some(lease),
+            protocol,
+            None,
         )
     }
     .instrument(span)
-    .await
+    .await;
+
+    breakers.record(target_base_url, result.is_ok());
+    result
 }
 
 /// Forward any request (any HTTP method) to upstream and stream the response back.
@@ -251,22 +447,13 @@ pub async fn forward_raw(
 
     async {
         // Build the upstream request with the original method
-        let mut req_builder = client
+        let req_builder = client
             .request(method, url)
             .body(body)
             .header(CORRELATION_HEADER, correlation_id);
 
         // Forward non-hop-by-hop headers from the original request
-        for (name, value) in headers.iter() {
-            let name_str = name.as_str().to_lowercase();
-            if HOP_BY_HOP_HEADERS.contains(&name_str.as_str()) {
-                continue;
-            }
-            if name_str == CORRELATION_HEADER {
-                continue;
-            }
-            req_builder = req_builder.header(name, value);
-        }
+        let req_builder = forward_request_headers(req_builder, headers, &[]);
 
         // Send the request
         let upstream_result = req_builder.send().await;
@@ -277,8 +464,177 @@ pub async fn forward_raw(
     .await
 }
 
+/// Ask a local target's `/v1/messages/count_tokens` for a request's input
+/// tokens. Client headers are forwarded as in `forward_to_target`, with the
+/// model's `auth` on top. Any failure (connect error, non-2xx status, a body
+/// without `input_tokens`) is returned for the caller to fall back on.
+pub async fn count_tokens_on_target(
+    client: &reqwest::Client,
+    target_base_url: &str,
+    headers: &HeaderMap,
+    auth: &HeaderMap,
+    body: Bytes,
+    correlation_id: &str,
+) -> Result<u64, String> {
+    let url = format!("{target_base_url}/v1/messages/count_tokens");
+    let req_builder = client
+        .post(&url)
+        .body(body)
+        .header("content-type", "application/json")
+        .header(CORRELATION_HEADER, correlation_id);
+    let req_builder =
+        forward_request_headers(req_builder, headers, TARGET_SKIPPED_HEADERS).headers(auth.clone());
+
+    let resp = req_builder.send().await.map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("status {}", resp.status().as_u16()));
+    }
+    let body: serde_json::Value = resp.json().await.map_err(|e| e.to_string())?;
+    body.get("input_tokens")
+        .and_then(|t| t.as_u64())
+        .ok_or_else(|| "response has no input_tokens".to_string())
+}
+
+/// Send `req_builder`, retrying 429 and 529 responses with exponential
+/// backoff and jitter (or the upstream's `retry-after`) while attempts and the
+/// time budget in `retry` allow. Runs before any bytes reach the client.
+///
+/// Each attempt gets its own `upstream_attempt` span under the current
+/// (`primary_forward`) span. Connect errors and timeouts are not retried here;
+/// the fallback chain handles those.
+async fn send_with_retry(
+    req_builder: reqwest::RequestBuilder,
+    retry: &RetryConfig,
+) -> Result<reqwest::Response, reqwest::Error> {
+    let started = Instant::now();
+    let budget = Duration::from_secs(retry.budget_secs);
+    let mut attempt: u32 = 0;
+    let mut builder = req_builder;
+
+    loop {
+        attempt += 1;
+        // Keep a copy for the next attempt. The body is in-memory `Bytes`,
+        // so cloning succeeds.
+        let next = (attempt <= retry.max_retries)
+            .then(|| builder.try_clone())
+            .flatten();
+
+        let span = cc_tracing::upstream_attempt_span!(attempt);
+        let attempt_start = Instant::now();
+        let result = builder.send().instrument(span.clone()).await;
+        span.record("latency_ms", attempt_start.elapsed().as_millis() as u64);
+
+        let resp = match result {
+            Ok(resp) => resp,
+            Err(e) => {
+                span.record("status", 0_u16);
+                return Err(e);
+            }
+        };
+        let status = resp.status().as_u16();
+        span.record("status", status);
+
+        let Some(next) = next.filter(|_| matches!(status, 429 | 529)) else {
+            return Ok(resp);
+        };
+        let delay = retry_delay(resp.headers(), attempt, retry);
+        if started.elapsed() + delay > budget {
+            tracing::warn!(
+                parent: &span,
+                status = status,
+                attempt = attempt,
+                delay_ms = delay.as_millis() as u64,
+                "Retry budget exhausted, returning upstream response"
+            );
+            return Ok(resp);
+        }
+
+        span.record("backoff_ms", delay.as_millis() as u64);
+        tracing::warn!(
+            parent: &span,
+            status = status,
+            attempt = attempt,
+            delay_ms = delay.as_millis() as u64,
+            "Upstream overloaded, retrying"
+        );
+        drop(resp);
+        tokio::time::sleep(delay).await;
+        builder = next;
+    }
+}
+
+/// Delay before retry number `attempt`: the upstream's `retry-after-ms` or
+/// `retry-after` (seconds) when present, capped at the retry budget (which
+/// ends retrying), otherwise exponential backoff from `base_delay_ms`, capped
+/// at `max_delay_ms`, with equal jitter.
+fn retry_delay(
+    headers: &reqwest::header::HeaderMap,
+    attempt: u32,
+    retry: &RetryConfig,
+) -> Duration {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .filter(|v| v.is_finite() && *v >= 0.0)
+    };
+    let upstream = header("retry-after-ms")
+        .map(|ms| ms / 1000.0)
+        .or_else(|| header("retry-after"));
+    if let Some(secs) = upstream {
+        let budget = Duration::from_secs(retry.budget_secs);
+        return Duration::try_from_secs_f64(secs).map_or(budget, |d| d.min(budget));
+    }
+
+    let exp = retry
+        .base_delay_ms
+        .saturating_mul(1u64 << (attempt - 1).min(20))
+        .min(retry.max_delay_ms);
+    // Equal jitter: half fixed, half random, so concurrent clients spread out.
+    let random = uuid::Uuid::new_v4().as_u128() as u64;
+    let jitter = if exp / 2 == 0 {
+        0
+    } else {
+        random % (exp / 2 + 1)
+    };
+    Duration::from_millis(exp - exp / 2 + jitter)
+}
+
+/// Convert a complete OpenAI JSON body (completion or error) to Anthropic
+/// format.
+fn json_to_anthropic(status: u16, body: &[u8]) -> Vec<u8> {
+    let converted = if (200..300).contains(&status) {
+        match serde_json::from_slice(body) {
+            Ok(resp) => openai_to_anthropic(&resp),
+            Err(_) => error_to_anthropic(502, body),
+        }
+    } else {
+        error_to_anthropic(status, body)
+    };
+    serde_json::to_vec(&converted).unwrap_or_default()
+}
+
+/// Translate a complete OpenAI chat-completions response body (JSON or
+/// `chat.completion.chunk` SSE) into its Anthropic Messages form, as
+/// `OpenAiTranslateBody` does while streaming.
+pub fn translate_openai_body(status: u16, is_sse: bool, body: &[u8]) -> Vec<u8> {
+    if is_sse && (200..300).contains(&status) {
+        let mut translator = SseTranslator::new();
+        let mut out = translator.push(body);
+        out.extend(translator.finish());
+        out
+    } else {
+        json_to_anthropic(status, body)
+    }
+}
+
 /// Build an axum Response from the upstream reqwest result, streaming the body
 /// through a `TeeBody` that captures bytes for OpenInference response attributes.
+///
+/// Returns `Err(FailedForward)` for connect errors, timeouts and 5xx statuses —
+/// nothing has been sent to the client yet, so the caller may retry elsewhere.
+#[allow(clippy::too_many_arguments)]
 fn build_response(
     upstream_result: Result<reqwest::Response, reqwest::Error>,
     start: Instant,
@@ -286,7 +642,10 @@ fn build_response(
     is_streaming: bool,
     span: &tracing::Span,
     stats: Option<ProxyStats>,
-) -> Response {
+    lease: Option<EndpointLease>,
+    protocol: TargetProtocol,
+    observer: Option<ResponseObserver>,
+) -> Result<Response, FailedForward> {
     let upstream_resp = match upstream_result {
         Ok(resp) => resp,
         Err(e) => {
@@ -296,10 +655,16 @@ fn build_response(
 
             if e.is_timeout() {
                 tracing::error!(error = %e, "Upstream timeout");
-                return (StatusCode::GATEWAY_TIMEOUT, "upstream timeout").into_response();
+                return Err(FailedForward::new(
+                    "timeout",
+                    (StatusCode::GATEWAY_TIMEOUT, "upstream timeout").into_response(),
+                ));
             }
             tracing::error!(error = %e, "Upstream connection error");
-            return (StatusCode::BAD_GATEWAY, "upstream connection error").into_response();
+            return Err(FailedForward::new(
+                "connect error",
+                (StatusCode::BAD_GATEWAY, "upstream connection error").into_response(),
+            ));
         }
     };
 
@@ -315,17 +680,15 @@ fn build_response(
     );
 
     // Build the response, streaming the upstream body through TeeBody
-    let mut response_builder = Response::builder()
+    let response_builder = Response::builder()
         .status(StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::BAD_GATEWAY));
 
-    // Forward response headers from upstream
-    for (name, value) in upstream_resp.headers().iter() {
-        let name_str = name.as_str().to_lowercase();
-        if HOP_BY_HOP_HEADERS.contains(&name_str.as_str()) {
-            continue;
-        }
-        response_builder = response_builder.header(name, value);
-    }
+    // Forward response headers from upstream. Translation changes the body
+    // length.
+    let translate = protocol == TargetProtocol::Openai;
+    let skip: &[&str] = if translate { &["content-length"] } else { &[] };
+    let mut response_builder =
+        forward_response_headers(response_builder, upstream_resp.headers(), skip);
 
     // Add correlation ID to response
     response_builder = response_builder.header(
@@ -343,22 +706,62 @@ fn build_response(
         span.record("anthropic_request_id", req_id);
     }
 
-    // Wrap the upstream byte stream in TeeBody to capture output for OpenInference
+    let is_sse = upstream_resp
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("text/event-stream"));
+    let upstream_body: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>> =
+        if translate {
+            Box::pin(OpenAiTranslateBody {
+                inner: Box::pin(upstream_resp.bytes_stream()),
+                translation: if is_sse && status.is_success() {
+                    Translation::Sse(SseTranslator::new())
+                } else {
+                    Translation::Json {
+                        status: status.as_u16(),
+                        buffer: Vec::new(),
+                    }
+                },
+                done: false,
+            })
+        } else {
+            Box::pin(upstream_resp.bytes_stream())
+        };
+
+    // Wrap the upstream byte stream in TeeBody to capture output for
+    // OpenInference. A 5xx body is only recorded if it is returned as-is.
+    let record = Arc::new(AtomicBool::new(!status.is_server_error()));
     let tee = TeeBody {
-        inner: Box::pin(upstream_resp.bytes_stream()),
+        inner: upstream_body,
         buffer: Arc::new(Mutex::new(Vec::new())),
         span: span.clone(),
         is_streaming,
         stats,
         start,
         first_chunk_seen: false,
+        _lease: lease,
+        status: status.as_u16(),
+        ttft_ms: None,
+        observer,
+        finished: false,
+        record: record.clone(),
     };
     let body = Body::from_stream(tee);
 
-    response_builder.body(body).unwrap_or_else(|e| {
+    let response = response_builder.body(body).unwrap_or_else(|e| {
         tracing::error!(error = %e, "Failed to build response");
         (StatusCode::INTERNAL_SERVER_ERROR, "internal error").into_response()
-    })
+    });
+
+    if status.is_server_error() {
+        return Err(FailedForward {
+            reason: format!("status {}", status.as_u16()),
+            response: Box::new(response),
+            record: Some(record),
+        });
+    }
+    Ok(response)
 }
 
 /// Build an axum Response without TeeBody (used by passthrough/fallback).
@@ -395,17 +798,12 @@ fn build_response_simple(
     );
 
     // Build the response, streaming the upstream body verbatim
-    let mut response_builder = Response::builder()
+    let response_builder = Response::builder()
         .status(StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::BAD_GATEWAY));
 
     // Forward response headers from upstream
-    for (name, value) in upstream_resp.headers().iter() {
-        let name_str = name.as_str().to_lowercase();
-        if HOP_BY_HOP_HEADERS.contains(&name_str.as_str()) {
-            continue;
-        }
-        response_builder = response_builder.header(name, value);
-    }
+    let mut response_builder =
+        forward_response_headers(response_builder, upstream_resp.headers(), &[]);
 
     // Add correlation ID to response
     response_builder = response_builder.header(
@@ -541,3 +939,489 @@ fn extract_streaming_stats(stats: &ProxyStats, response_bytes: &[u8]) {
         }
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::future::IntoFuture;
+
+    /// Serve `app` on a free local port and return its base URL.
+    pub async fn spawn_app(app: axum::Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, app).into_future());
+        format!("http://{addr}")
+    }
+
+    /// Start a mock target whose `/v1/messages` always returns `status`.
+    pub async fn mock_target(status: u16) -> String {
+        let app = axum::Router::new().route(
+            "/v1/messages",
+            axum::routing::post(move || async move {
+                (
+                    StatusCode::from_u16(status).unwrap(),
+                    r#"{"type":"message","content":[]}"#,
+                )
+            }),
+        );
+        spawn_app(app).await
+    }
+
+    /// What `forward_with` sends: by default a non-streaming Anthropic
+    /// request without client headers or credentials.
+    #[derive(Default)]
+    struct TestRequest {
+        headers: HeaderMap,
+        auth: HeaderMap,
+        protocol: TargetProtocol,
+        stream: bool,
+    }
+
+    async fn forward(url: &str) -> Result<Response, FailedForward> {
+        let breakers = CircuitBreakers::new(Default::default());
+        forward_with(
+            url,
+            &breakers,
+            &RetryConfig::default(),
+            TestRequest::default(),
+        )
+        .await
+    }
+
+    async fn forward_with(
+        url: &str,
+        breakers: &CircuitBreakers,
+        retry: &RetryConfig,
+        request: TestRequest,
+    ) -> Result<Response, FailedForward> {
+        let registry = crate::models::ModelRegistry::new(
+            vec![crate::models::ModelDef {
+                id: "m".into(),
+                target_url: Some(url.to_string()),
+                ..Default::default()
+            }],
+            &[],
+            None,
+            None,
+        );
+        let lease = match registry.resolve("m", &crate::targets::TargetHealth::new()) {
+            crate::models::RouteTarget::Local { lease, .. } => lease,
+            crate::models::RouteTarget::Anthropic => panic!("expected Local"),
+        };
+        let body = serde_json::json!({"model": "m", "stream": request.stream});
+        forward_to_target(
+            &reqwest::Client::new(),
+            url,
+            lease,
+            breakers,
+            retry,
+            &request.headers,
+            &request.auth,
+            request.protocol,
+            Bytes::from(body.to_string()),
+            "test-correlation-id",
+            request.stream,
+            tracing::Span::none(),
+            ProxyStats::new(),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn observer_fires_when_the_body_is_dropped_early() {
+        let url = mock_target(200).await;
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let resp = forward_to_anthropic(
+            &reqwest::Client::new(),
+            &format!("{url}/v1/messages"),
+            &RetryConfig::default(),
+            &HeaderMap::new(),
+            Bytes::from_static(br#"{"model":"m"}"#),
+            "test-correlation-id",
+            false,
+            tracing::Span::none(),
+            ProxyStats::new(),
+            Some(Box::new(move |summary| {
+                let _ = tx.send(summary.status);
+            })),
+        )
+        .await
+        .ok()
+        .expect("200 is forwarded");
+        assert!(resp.headers().contains_key("content-length"));
+
+        // Never polled to its end, as when hyper stops at content-length
+        drop(resp);
+        assert_eq!(rx.await, Ok(200));
+    }
+
+    #[tokio::test]
+    async fn failed_forward_is_recorded_only_when_returned() {
+        let url = format!("{}/v1/messages", mock_target(503).await);
+        let (client, retry, headers) = (
+            reqwest::Client::new(),
+            RetryConfig::default(),
+            HeaderMap::new(),
+        );
+        let forward = |tx: tokio::sync::oneshot::Sender<u16>| {
+            forward_to_anthropic(
+                &client,
+                &url,
+                &retry,
+                &headers,
+                Bytes::from_static(br#"{"model":"m"}"#),
+                "test-correlation-id",
+                false,
+                tracing::Span::none(),
+                ProxyStats::new(),
+                Some(Box::new(move |summary| {
+                    let _ = tx.send(summary.status);
+                })),
+            )
+        };
+
+        // Discarded, as when a fallback hop takes over
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let failed = forward(tx).await.expect_err("503 is a failed forward");
+        drop(failed);
+        assert!(rx.await.is_err());
+
+        // Handed to the client
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let failed = forward(tx).await.expect_err("503 is a failed forward");
+        let body = failed.into_response().into_body();
+        axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(rx.await, Ok(503));
+    }
+
+    #[tokio::test]
+    async fn model_auth_replaces_client_credentials() {
+        let app = axum::Router::new().route(
+            "/v1/messages",
+            axum::routing::post(|headers: HeaderMap| async move {
+                let get = |name: &str| {
+                    headers
+                        .get(name)
+                        .and_then(|v| v.to_str().ok())
+                        .unwrap_or("")
+                        .to_string()
+                };
+                format!(
+                    "{}|{}|{}",
+                    get("x-api-key"),
+                    get("authorization"),
+                    get("x-app")
+                )
+            }),
+        );
+        let url = spawn_app(app).await;
+
+        let mut client_headers = HeaderMap::new();
+        client_headers.insert("x-api-key", "sk-ant-client".parse().unwrap());
+        client_headers.insert("authorization", "Bearer client".parse().unwrap());
+        client_headers.insert("x-app", "cli".parse().unwrap());
+        let mut auth = HeaderMap::new();
+        auth.insert("authorization", "Bearer gateway".parse().unwrap());
+
+        let request = TestRequest {
+            headers: client_headers,
+            auth,
+            ..Default::default()
+        };
+        let breakers = CircuitBreakers::new(Default::default());
+        let resp = forward_with(&url, &breakers, &RetryConfig::default(), request)
+            .await
+            .ok()
+            .expect("200 should succeed");
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"|Bearer gateway|cli");
+    }
+
+    #[tokio::test]
+    async fn count_tokens_reads_the_target_count() {
+        let app = axum::Router::new().route(
+            "/v1/messages/count_tokens",
+            axum::routing::post(|headers: HeaderMap, body: Bytes| async move {
+                let req: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                let authorized = headers
+                    .get("authorization")
+                    .is_some_and(|v| v == "Bearer gateway");
+                if req["model"] != "m" || !authorized {
+                    return (StatusCode::BAD_REQUEST, "{}".to_string());
+                }
+                (StatusCode::OK, r#"{"input_tokens":42}"#.to_string())
+            }),
+        );
+        let url = spawn_app(app).await;
+
+        let client = reqwest::Client::new();
+        let mut auth = HeaderMap::new();
+        auth.insert("authorization", "Bearer gateway".parse().unwrap());
+        let count = |model: &'static str, auth: HeaderMap| {
+            let (client, url) = (client.clone(), url.clone());
+            async move {
+                let body = Bytes::from(format!(r#"{{"model":"{model}","messages":[]}}"#));
+                count_tokens_on_target(&client, &url, &HeaderMap::new(), &auth, body, "cid").await
+            }
+        };
+        assert_eq!(count("m", auth.clone()).await, Ok(42));
+        assert_eq!(count("other", auth).await, Err("status 400".to_string()));
+        assert!(count("m", HeaderMap::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn openai_target_responses_are_translated() {
+        let app = axum::Router::new().route(
+            "/v1/chat/completions",
+            axum::routing::post(|body: Bytes| async move {
+                let req: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                if req["stream"] == true {
+                    (
+                        [("content-type", "text/event-stream")],
+                        concat!(
+                            "data: {\"id\":\"c1\",\"model\":\"m\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"},\"finish_reason\":\"stop\"}]}\n\n",
+                            "data: [DONE]\n\n"
+                        ),
+                    )
+                } else {
+                    (
+                        [("content-type", "application/json")],
+                        r#"{"id":"c1","model":"m","choices":[{"index":0,"message":{"role":"assistant","content":"Hi"},"finish_reason":"stop"}],"usage":{"prompt_tokens":3,"completion_tokens":1}}"#,
+                    )
+                }
+            }),
+        );
+        let url = spawn_app(app).await;
+
+        let breakers = CircuitBreakers::new(Default::default());
+        for stream in [false, true] {
+            let request = TestRequest {
+                protocol: TargetProtocol::Openai,
+                stream,
+                ..Default::default()
+            };
+            let resp = forward_with(&url, &breakers, &RetryConfig::default(), request)
+                .await
+                .ok()
+                .expect("200 should succeed");
+            let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body = std::str::from_utf8(&body).unwrap();
+            if stream {
+                assert!(body.starts_with("event: message_start\n"));
+                assert!(body.contains(r#""delta":{"text":"Hi","type":"text_delta"}"#));
+                assert!(
+                    body.ends_with("event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n")
+                );
+            } else {
+                let msg: serde_json::Value = serde_json::from_str(body).unwrap();
+                assert_eq!(msg["type"], "message");
+                assert_eq!(msg["content"][0]["text"], "Hi");
+                assert_eq!(msg["usage"]["input_tokens"], 3);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn success_status_is_ok() {
+        let url = mock_target(200).await;
+        let resp = forward(&url).await.ok().expect("200 should succeed");
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn client_error_is_not_a_failed_forward() {
+        // 4xx is the client's problem — retrying elsewhere would not help.
+        let url = mock_target(400).await;
+        let resp = forward(&url).await.ok().expect("400 is returned as-is");
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn server_error_is_failed_forward() {
+        let url = mock_target(503).await;
+        let failed = forward(&url).await.expect_err("503 should fail");
+        assert_eq!(failed.reason, "status 503");
+        assert_eq!(
+            failed.into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[tokio::test]
+    async fn connect_error_is_failed_forward() {
+        // Bind then drop a listener to get a port nothing is listening on.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let failed = forward(&format!("http://{addr}"))
+            .await
+            .expect_err("connect should fail");
+        assert_eq!(failed.reason, "connect error");
+        assert_eq!(failed.into_response().status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn open_circuit_fails_fast_with_overloaded_error() {
+        let url = mock_target(503).await;
+        let breakers = CircuitBreakers::new(crate::config::CircuitBreakerConfig {
+            failure_threshold: 2,
+            ..Default::default()
+        });
+        for _ in 0..2 {
+            let failed = forward_with(
+                &url,
+                &breakers,
+                &RetryConfig::default(),
+                TestRequest::default(),
+            )
+            .await
+            .expect_err("503");
+            assert_eq!(failed.reason, "status 503");
+        }
+
+        let failed = forward_with(
+            &url,
+            &breakers,
+            &RetryConfig::default(),
+            TestRequest::default(),
+        )
+        .await
+        .expect_err("circuit should be open");
+        assert_eq!(failed.reason, "circuit open");
+        let resp = failed.into_response();
+        assert_eq!(resp.status().as_u16(), 529);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["type"], "overloaded_error");
+    }
+
+    /// Mock target answering `/v1/messages` with `status` (and `retry-after`)
+    /// for the first `failures` calls, then 200. Returns the URL and a call
+    /// counter.
+    async fn flaky_target(
+        status: u16,
+        retry_after: &'static str,
+        failures: usize,
+    ) -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let app = axum::Router::new().route(
+            "/v1/messages",
+            axum::routing::post(move || {
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if n < failures {
+                        (
+                            StatusCode::from_u16(status).unwrap(),
+                            [("retry-after", retry_after)],
+                            r#"{"type":"error"}"#,
+                        )
+                            .into_response()
+                    } else {
+                        (StatusCode::OK, r#"{"type":"message","content":[]}"#).into_response()
+                    }
+                }
+            }),
+        );
+        (spawn_app(app).await, calls)
+    }
+
+    fn retries(max_retries: u32) -> RetryConfig {
+        RetryConfig {
+            max_retries,
+            base_delay_ms: 1,
+            max_delay_ms: 5,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn overloaded_responses_are_retried() {
+        let (url, calls) = flaky_target(529, "0", 2).await;
+        let breakers = CircuitBreakers::new(Default::default());
+        let resp = forward_with(&url, &breakers, &retries(3), TestRequest::default())
+            .await
+            .ok()
+            .expect("third attempt succeeds");
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_stop_at_max_retries() {
+        let (url, calls) = flaky_target(429, "0", usize::MAX).await;
+        let breakers = CircuitBreakers::new(Default::default());
+        let resp = forward_with(&url, &breakers, &retries(1), TestRequest::default())
+            .await
+            .ok()
+            .expect("429 is returned as-is");
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn retry_after_beyond_budget_is_not_waited_for() {
+        let (url, calls) = flaky_target(429, "60", usize::MAX).await;
+        let breakers = CircuitBreakers::new(Default::default());
+        let retry = RetryConfig {
+            budget_secs: 5,
+            ..retries(3)
+        };
+        let resp = forward_with(&url, &breakers, &retry, TestRequest::default())
+            .await
+            .ok()
+            .expect("429 is returned as-is");
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn retry_delay_prefers_retry_after_then_backs_off() {
+        let retry = RetryConfig {
+            base_delay_ms: 100,
+            max_delay_ms: 300,
+            ..Default::default()
+        };
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("retry-after", "2".parse().unwrap());
+        assert_eq!(retry_delay(&headers, 1, &retry), Duration::from_secs(2));
+        headers.insert("retry-after-ms", "1500".parse().unwrap());
+        assert_eq!(
+            retry_delay(&headers, 1, &retry),
+            Duration::from_millis(1500)
+        );
+
+        let none = reqwest::header::HeaderMap::new();
+        for (attempt, cap) in [(1, 100), (2, 200), (3, 300), (6, 300)] {
+            let d = retry_delay(&none, attempt, &retry).as_millis() as u64;
+            assert!(d >= cap / 2 && d <= cap, "attempt {attempt}: {d}ms");
+        }
+    }
+
+    #[test]
+    fn retry_delay_caps_huge_retry_after_at_budget() {
+        let retry = RetryConfig {
+            budget_secs: 30,
+            ..Default::default()
+        };
+        let budget = Duration::from_secs(30);
+        for (name, value) in [
+            ("retry-after", "1e300"),
+            ("retry-after", "86400"),
+            ("retry-after-ms", "1e300"),
+        ] {
+            let mut headers = reqwest::header::HeaderMap::new();
+            headers.insert(name, value.parse().unwrap());
+            assert_eq!(retry_delay(&headers, 1, &retry), budget, "{name}: {value}");
+        }
+    }
+}
@@ -0,0 +1,864 @@
+//! Hot-reload of configuration and the model registry.
+//!
+//! The active `ProxyConfig` and `ModelRegistry` live together in an immutable
+//! `Snapshot` behind an `ArcSwap`. Handlers load the snapshot once per request,
+//! so in-flight requests finish on the snapshot they started with while new
+//! requests see the reloaded one. Reads are lock-free.
+//!
+//! Reloads are triggered by SIGHUP, by `POST /api/config/reload`, or by the
+//! optional mtime watcher (`server.config_watch_secs`).
+//!
+//! Models changed through `/api/models` are kept in a `ModelOverlay` that is
+//! layered over `[[models]]` on every reload and optionally persisted to
+//! `server.models_state_file`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use arc_swap::ArcSwap;
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+use crate::config::ProxyConfig;
+use crate::models::{ModelDef, ModelRegistry};
+
+/// An immutable view of the configuration and the registry built from it.
+pub struct Snapshot {
+    pub config: ProxyConfig,
+    pub model_registry: ModelRegistry,
+    /// `[compare.judge]` credentials, read when the snapshot was built.
+    pub judge_auth: Result<HeaderMap, String>,
+}
+
+impl Snapshot {
+    /// Build the registry for `config`, keeping `previous` in-flight counts,
+    /// and read every configured secret.
+    fn new(config: ProxyConfig, previous: Option<&ModelRegistry>) -> Self {
+        let model_registry = ModelRegistry::from_config(&config, previous);
+        let judge_auth = config
+            .compare
+            .judge
+            .as_ref()
+            .map_or_else(|| Ok(HeaderMap::new()), |judge| judge.auth.resolve());
+        Self {
+            config,
+            model_registry,
+            judge_auth,
+        }
+    }
+}
+
+/// Settings given on the command line. Re-applied on every reload so they
+/// keep taking precedence over TOML and env vars.
+#[derive(Debug, Clone, Default)]
+pub struct CliOverrides {
+    pub target_url: Option<String>,
+    pub model_override: Option<String>,
+    pub allow_anthropic_only: bool,
+}
+
+/// Result of a successful reload.
+#[derive(Debug, Serialize)]
+pub struct ReloadReport {
+    /// Human-readable list of changes that took effect.
+    pub changes: Vec<String>,
+    /// Changed settings that only take effect after a restart.
+    pub restart_required: Vec<String>,
+}
+
+/// Why a reload was rejected. The previous snapshot stays active.
+#[derive(Debug, Serialize)]
+pub struct ReloadError {
+    pub errors: Vec<String>,
+}
+
+/// Why a `/api/models` change was rejected. The active snapshot is unchanged.
+#[derive(Debug)]
+pub enum ModelChangeError {
+    NotFound(String),
+    AlreadyExists(String),
+    Invalid(Vec<String>),
+    /// The change was valid but could not be written to the state file.
+    Persist(String),
+}
+
+/// Models added, replaced or removed at runtime, layered over `[[models]]`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelOverlay {
+    /// Runtime definitions by ID; replace a TOML model with the same ID.
+    #[serde(default)]
+    pub upserts: BTreeMap<String, ModelDef>,
+    /// TOML model IDs removed at runtime.
+    #[serde(default)]
+    pub removed: BTreeSet<String>,
+}
+
+impl ModelOverlay {
+    fn load(path: &str) -> anyhow::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Ok(serde_json::from_str(&text)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write via a temp file + rename so a crash never leaves a torn file.
+    fn save(&self, path: &str) -> std::io::Result<()> {
+        let tmp = format!("{path}.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp, path)
+    }
+
+    fn apply(&self, models: &mut Vec<ModelDef>) {
+        models.retain(|m| !self.removed.contains(&m.id) && !self.upserts.contains_key(&m.id));
+        models.extend(self.upserts.values().cloned());
+    }
+
+    fn is_empty(&self) -> bool {
+        self.upserts.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Thread-safe handle to the live configuration. Cheap to clone (Arc).
+#[derive(Clone)]
+pub struct LiveConfig {
+    current: Arc<ArcSwap<Snapshot>>,
+    config_path: Arc<String>,
+    overrides: Arc<CliOverrides>,
+    /// Runtime model changes and where to persist them (fixed at startup).
+    overlay: Arc<Mutex<ModelOverlay>>,
+    state_file: Option<Arc<String>>,
+    /// Serializes reloads and model changes so they can't interleave
+    /// load-and-swap.
+    reload_lock: Arc<Mutex<()>>,
+}
+
+impl LiveConfig {
+    /// Load, validate and build the initial snapshot.
+    pub fn load(config_path: &str, overrides: CliOverrides) -> anyhow::Result<Self> {
+        let base = build_snapshot(config_path, &overrides, &ModelOverlay::default(), None)
+            .map_err(|e| anyhow::anyhow!("invalid configuration: {}", e.errors.join("; ")))?;
+        let state_file = base.config.server.models_state_file.clone();
+        let overlay = match state_file {
+            Some(ref path) => ModelOverlay::load(path)
+                .map_err(|e| anyhow::anyhow!("failed to load models state file {path}: {e}"))?,
+            None => ModelOverlay::default(),
+        };
+        let snapshot = if overlay.is_empty() {
+            base
+        } else {
+            build_snapshot(config_path, &overrides, &overlay, None).map_err(|e| {
+                anyhow::anyhow!("invalid models state file: {}", e.errors.join("; "))
+            })?
+        };
+        Ok(Self {
+            current: Arc::new(ArcSwap::from_pointee(snapshot)),
+            config_path: Arc::new(config_path.to_string()),
+            overrides: Arc::new(overrides),
+            overlay: Arc::new(Mutex::new(overlay)),
+            state_file: state_file.map(Arc::new),
+            reload_lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    /// The currently active snapshot. Hold the returned `Arc` for the whole
+    /// request so a concurrent reload doesn't change settings mid-flight.
+    pub fn current(&self) -> Arc<Snapshot> {
+        self.current.load_full()
+    }
+
+    /// Path of the TOML file this config was loaded from.
+    pub fn config_path(&self) -> &str {
+        &self.config_path
+    }
+
+    /// Re-read the TOML file and env vars, validate, and swap in a new
+    /// snapshot. On error the active snapshot is left untouched.
+    pub fn reload(&self) -> Result<ReloadReport, ReloadError> {
+        let _guard = self.reload_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let overlay = self
+            .overlay
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        let current = self.current();
+        let next = build_snapshot(
+            &self.config_path,
+            &self.overrides,
+            &overlay,
+            Some(&current.model_registry),
+        )?;
+        let report = diff(&current.config, &next.config);
+        self.current.store(Arc::new(next));
+        Ok(report)
+    }
+
+    /// Register a new local model.
+    pub fn add_model(&self, def: ModelDef) -> Result<ModelDef, ModelChangeError> {
+        self.change_models(|overlay, models| {
+            if models.iter().any(|m| m.id == def.id) {
+                return Err(ModelChangeError::AlreadyExists(def.id.clone()));
+            }
+            overlay.removed.remove(&def.id);
+            overlay.upserts.insert(def.id.clone(), def.clone());
+            Ok(def)
+        })
+    }
+
+    /// Merge `patch` (a partial model definition; `null` clears a field) into
+    /// an existing model. The ID cannot be changed.
+    pub fn update_model(
+        &self,
+        id: &str,
+        patch: &serde_json::Value,
+    ) -> Result<ModelDef, ModelChangeError> {
+        self.change_models(|overlay, models| {
+            let existing = models
+                .iter()
+                .find(|m| m.id == id)
+                .ok_or_else(|| ModelChangeError::NotFound(id.to_string()))?;
+            let Some(fields) = patch.as_object() else {
+                return Err(ModelChangeError::Invalid(vec![
+                    "body must be a JSON object".to_string(),
+                ]));
+            };
+
+            let mut merged = serde_json::to_value(existing)
+                .map_err(|e| ModelChangeError::Invalid(vec![e.to_string()]))?;
+            if let Some(obj) = merged.as_object_mut() {
+                for (key, value) in fields {
+                    if value.is_null() {
+                        obj.remove(key);
+                    } else {
+                        obj.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+            let def: ModelDef = serde_json::from_value(merged)
+                .map_err(|e| ModelChangeError::Invalid(vec![e.to_string()]))?;
+            if def.id != id {
+                return Err(ModelChangeError::Invalid(vec![
+                    "id cannot be changed".to_string()
+                ]));
+            }
+            overlay.upserts.insert(def.id.clone(), def.clone());
+            Ok(def)
+        })
+    }
+
+    /// Remove a local model. Requests for it go to Anthropic afterwards.
+    pub fn remove_model(&self, id: &str) -> Result<(), ModelChangeError> {
+        self.change_models(|overlay, models| {
+            if !models.iter().any(|m| m.id == id) {
+                return Err(ModelChangeError::NotFound(id.to_string()));
+            }
+            overlay.upserts.remove(id);
+            overlay.removed.insert(id.to_string());
+            Ok(())
+        })
+    }
+
+    /// Apply `change` to a copy of the overlay, validate the resulting
+    /// config, persist the overlay, then swap in the new snapshot.
+    fn change_models<T>(
+        &self,
+        change: impl FnOnce(&mut ModelOverlay, &[ModelDef]) -> Result<T, ModelChangeError>,
+    ) -> Result<T, ModelChangeError> {
+        let _guard = self.reload_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let current = self.current();
+        let mut overlay = self
+            .overlay
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        let result = change(&mut overlay, &current.config.models)?;
+
+        let mut config = current.config.clone();
+        overlay.apply(&mut config.models);
+        config.validate().map_err(ModelChangeError::Invalid)?;
+
+        if let Some(ref path) = self.state_file {
+            overlay
+                .save(path)
+                .map_err(|e| ModelChangeError::Persist(format!("{path}: {e}")))?;
+        }
+
+        let next = Snapshot::new(config, Some(&current.model_registry));
+        self.current.store(Arc::new(next));
+        *self.overlay.lock().unwrap_or_else(|e| e.into_inner()) = overlay;
+        Ok(result)
+    }
+
+    /// Reload and log the outcome. Used by the signal and file-watch triggers.
+    pub fn reload_and_log(&self, trigger: &str) {
+        match self.reload() {
+            Ok(report) => tracing::info!(
+                trigger = trigger,
+                changes = ?report.changes,
+                restart_required = ?report.restart_required,
+                "Configuration reloaded"
+            ),
+            Err(e) => tracing::error!(
+                trigger = trigger,
+                errors = ?e.errors,
+                "Configuration reload rejected, keeping previous config"
+            ),
+        }
+    }
+}
+
+/// Load the config file + env, apply CLI overrides and runtime model changes,
+/// validate, and build the registry (keeping `previous` in-flight counts).
+fn build_snapshot(
+    config_path: &str,
+    overrides: &CliOverrides,
+    overlay: &ModelOverlay,
+    previous: Option<&ModelRegistry>,
+) -> Result<Snapshot, ReloadError> {
+    let mut config = ProxyConfig::load(config_path).map_err(|e| ReloadError {
+        errors: vec![format!("failed to load {config_path}: {e}")],
+    })?;
+
+    // Apply CLI overrides (take precedence over TOML and env vars)
+    if let Some(ref url) = overrides.target_url {
+        config.target.url = Some(url.clone());
+    }
+    if overrides.model_override.is_some() {
+        config.model_override = overrides.model_override.clone();
+    }
+    config.anthropic_only_allowed = overrides.allow_anthropic_only;
+    overlay.apply(&mut config.models);
+
+    config.validate().map_err(|errors| ReloadError { errors })?;
+
+    Ok(Snapshot::new(config, previous))
+}
+
+/// Describe what changed between two configs.
+fn diff(old: &ProxyConfig, new: &ProxyConfig) -> ReloadReport {
+    let mut changes = Vec::new();
+    let mut restart_required = Vec::new();
+    let checks_on = |c: &ProxyConfig| c.health_check.interval_secs.is_some_and(|s| s > 0);
+
+    // Models: compare by serialized form so every field counts.
+    let as_json = |c: &ProxyConfig| -> std::collections::BTreeMap<String, String> {
+        c.models
+            .iter()
+            .map(|m| (m.id.clone(), serde_json::to_string(m).unwrap_or_default()))
+            .collect()
+    };
+    let (old_models, new_models) = (as_json(old), as_json(new));
+    for (id, def) in &new_models {
+        match old_models.get(id) {
+            None => changes.push(format!("model '{id}' added")),
+            Some(prev) if prev != def => changes.push(format!("model '{id}' updated")),
+            Some(_) => {}
+        }
+    }
+    for id in old_models.keys() {
+        if !new_models.contains_key(id) {
+            changes.push(format!("model '{id}' removed"));
+        }
+    }
+
+    let aliases_json = |c: &ProxyConfig| serde_json::to_string(&c.aliases).unwrap_or_default();
+    if aliases_json(old) != aliases_json(new) {
+        changes.push(format!("aliases updated ({} rules)", new.aliases.len()));
+    }
+
+    let routes_json = |c: &ProxyConfig| serde_json::to_string(&c.routes).unwrap_or_default();
+    if routes_json(old) != routes_json(new) {
+        changes.push(format!("routes updated ({} rules)", new.routes.len()));
+    }
+
+    if old.target.temperature != new.target.temperature
+        || old.target.top_p != new.target.top_p
+        || old.target.max_tokens != new.target.max_tokens
+    {
+        changes.push("target defaults (temperature/top_p/max_tokens) updated".to_string());
+    }
+    if old.target.url != new.target.url {
+        let url = |u: &Option<String>| u.clone().unwrap_or_else(|| "(none)".to_string());
+        changes.push(format!(
+            "target.url: {} -> {}",
+            url(&old.target.url),
+            url(&new.target.url)
+        ));
+    }
+    if old.passthrough.url != new.passthrough.url {
+        changes.push(format!(
+            "passthrough.url: {} -> {}",
+            old.passthrough.url, new.passthrough.url
+        ));
+    }
+    if old.passthrough.passthrough_auth != new.passthrough.passthrough_auth {
+        changes.push("passthrough.passthrough_auth updated".to_string());
+    }
+    if old.retry != new.retry {
+        changes.push("retry updated".to_string());
+    }
+    // interval_secs on/off is restart-only (below); the running checker
+    // picks up everything else on its next round
+    let checks = |c: &ProxyConfig| crate::config::HealthCheckConfig {
+        interval_secs: None,
+        ..c.health_check.clone()
+    };
+    if checks(old) != checks(new)
+        || (old.health_check.interval_secs != new.health_check.interval_secs
+            && checks_on(old)
+            && checks_on(new))
+    {
+        changes.push("health_check updated".to_string());
+    }
+
+    let (oc, nc) = (&old.compare, &new.compare);
+    if oc.sample_percent != nc.sample_percent
+        || oc.include_models != nc.include_models
+        || oc.exclude_models != nc.exclude_models
+        || oc.skip_quota_checks != nc.skip_quota_checks
+        || oc.max_request_bytes != nc.max_request_bytes
+    {
+        changes.push("compare sampling updated".to_string());
+    }
+    if oc.candidates != nc.candidates {
+        changes.push(format!(
+            "compare.candidates updated ({} candidates)",
+            nc.candidates.len()
+        ));
+    }
+    if oc.queue_depth != nc.queue_depth || oc.queue_max_wait_secs != nc.queue_max_wait_secs {
+        changes.push("compare queue (queue_depth/queue_max_wait_secs) updated".to_string());
+    }
+    match (&oc.judge, &nc.judge) {
+        (None, Some(_)) => changes.push("compare.judge enabled".to_string()),
+        (Some(_), None) => changes.push("compare.judge disabled".to_string()),
+        (Some(a), Some(b)) if a != b => changes.push("compare.judge updated".to_string()),
+        _ => {}
+    }
+
+    if old.server.listen_address != new.server.listen_address {
+        restart_required.push("server.listen_address".to_string());
+    }
+    if old.server.config_watch_secs != new.server.config_watch_secs {
+        restart_required.push("server.config_watch_secs".to_string());
+    }
+    if checks_on(old) != checks_on(new) {
+        restart_required.push("health_check.interval_secs (enable/disable)".to_string());
+    }
+    if old.server.models_state_file != new.server.models_state_file {
+        restart_required.push("server.models_state_file".to_string());
+    }
+    if old.batches.dir != new.batches.dir
+        || old.batches.max_concurrent_per_target != new.batches.max_concurrent_per_target
+    {
+        restart_required.push("batches".to_string());
+    }
+    if old.compare.store_file != new.compare.store_file {
+        restart_required.push("compare.store_file".to_string());
+    }
+    if old.compare.stats_window != new.compare.stats_window {
+        restart_required.push("compare.stats_window".to_string());
+    }
+    if old.circuit_breaker != new.circuit_breaker {
+        restart_required.push("circuit_breaker".to_string());
+    }
+    if old.target.timeout_secs != new.target.timeout_secs {
+        restart_required.push("target.timeout_secs".to_string());
+    }
+    if old.target.max_concurrent != new.target.max_concurrent {
+        restart_required.push("target.max_concurrent".to_string());
+    }
+    if old.passthrough.timeout_secs != new.passthrough.timeout_secs {
+        restart_required.push("passthrough.timeout_secs".to_string());
+    }
+    if old.tracing.service_name != new.tracing.service_name
+        || old.tracing.otlp_endpoint != new.tracing.otlp_endpoint
+        || old.tracing.protocol != new.tracing.protocol
+        || old.tracing.log_level != new.tracing.log_level
+    {
+        restart_required.push("tracing".to_string());
+    }
+    if old.default_mode != new.default_mode {
+        // The runtime mode is owned by PUT /api/mode; default_mode only
+        // seeds it at startup.
+        restart_required.push("default_mode (use PUT /api/mode to switch now)".to_string());
+    }
+
+    ReloadReport {
+        changes,
+        restart_required,
+    }
+}
+
+/// Reload on SIGHUP until the process exits.
+#[cfg(unix)]
+pub async fn watch_sighup(live: LiveConfig) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(s) => s,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to install SIGHUP handler, signal reload disabled");
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        live.reload_and_log("sighup");
+    }
+}
+
+/// Poll the config file's modification time and reload when it changes.
+pub async fn watch_file(live: LiveConfig, interval: Duration) {
+    let mtime = |path: &str| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut last: Option<SystemTime> = mtime(live.config_path());
+
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let current = mtime(live.config_path());
+        if current.is_some() && current != last {
+            last = current;
+            live.reload_and_log("file_watch");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(path: &std::path::Path, models: &str) {
+        std::fs::write(
+            path,
+            format!(
+                r#"
+[server]
+[passthrough]
+[target]
+[tracing]
+{models}
+"#
+            ),
+        )
+        .unwrap();
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("cc-proxy-{name}-{}.toml", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn reload_swaps_registry_and_reports_changes() {
+        let path = temp_path("reload");
+        write_config(
+            &path,
+            r#"
+[[models]]
+id = "a"
+target_url = "http://a:8000"
+"#,
+        );
+        let live = LiveConfig::load(path.to_str().unwrap(), CliOverrides::default()).unwrap();
+        let before = live.current();
+        assert_eq!(before.model_registry.len(), 1);
+
+        write_config(
+            &path,
+            r#"
+[[models]]
+id = "a"
+target_url = "http://a:9000"
+
+[[models]]
+id = "b"
+target_url = "http://b:8000"
+"#,
+        );
+        let report = live.reload().unwrap();
+        assert_eq!(
+            report.changes,
+            vec![
+                "model 'a' updated".to_string(),
+                "model 'b' added".to_string()
+            ]
+        );
+
+        // The snapshot held by an in-flight request is unchanged.
+        assert_eq!(before.model_registry.len(), 1);
+        assert_eq!(live.current().model_registry.len(), 2);
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn in_flight_leases_survive_reload() {
+        use crate::models::RouteTarget;
+        use crate::targets::TargetHealth;
+
+        let path = temp_path("in-flight");
+        write_config(
+            &path,
+            r#"
+[[models]]
+id = "a"
+target_url = "http://a:8000"
+"#,
+        );
+        let live = LiveConfig::load(path.to_str().unwrap(), CliOverrides::default()).unwrap();
+        let targets = TargetHealth::new();
+        let held = live.current().model_registry.resolve("a", &targets);
+
+        write_config(
+            &path,
+            r#"
+[[models]]
+id = "a"
+target_url = "http://a:8000"
+context_window = 1000
+"#,
+        );
+        live.reload().unwrap();
+        let in_flight =
+            |live: &LiveConfig| match live.current().model_registry.resolve("a", &targets) {
+                RouteTarget::Local { lease, .. } => lease.in_flight_at_pick,
+                RouteTarget::Anthropic => panic!("expected Local"),
+            };
+        assert_eq!(in_flight(&live), 1);
+
+        live.remove_model("a").unwrap();
+        live.add_model(ModelDef {
+            id: "a".into(),
+            target_url: Some("http://a:8000".into()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(in_flight(&live), 1);
+
+        drop(held);
+        assert_eq!(in_flight(&live), 0);
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn reload_reports_hot_settings() {
+        let path = temp_path("settings");
+        let write = |extra: &str| {
+            std::fs::write(
+                &path,
+                format!(
+                    r#"
+[server]
+[passthrough]
+[target]
+[tracing]
+{extra}
+"#
+                ),
+            )
+            .unwrap();
+        };
+        write("");
+        let live = LiveConfig::load(path.to_str().unwrap(), CliOverrides::default()).unwrap();
+
+        write(
+            r#"
+[retry]
+max_retries = 5
+
+[health_check]
+unhealthy_threshold = 7
+
+[compare]
+sample_percent = 50.0
+queue_depth = 10
+
+[compare.judge]
+model = "claude-sonnet-4-5"
+"#,
+        );
+        let report = live.reload().unwrap();
+        assert_eq!(
+            report.changes,
+            [
+                "retry updated",
+                "health_check updated",
+                "compare sampling updated",
+                "compare queue (queue_depth/queue_max_wait_secs) updated",
+                "compare.judge enabled",
+            ]
+        );
+        assert!(report.restart_required.is_empty());
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn invalid_reload_keeps_previous_snapshot() {
+        let path = temp_path("invalid");
+        write_config(
+            &path,
+            r#"
+[[models]]
+id = "a"
+target_url = "http://a:8000"
+"#,
+        );
+        let live = LiveConfig::load(path.to_str().unwrap(), CliOverrides::default()).unwrap();
+
+        write_config(
+            &path,
+            r#"
+[[models]]
+id = "a"
+target_url = "not a url"
+fallback = ["missing"]
+overflow_to = "anthropic"
+"#,
+        );
+        let err = live.reload().unwrap_err();
+        assert_eq!(err.errors.len(), 3, "{:?}", err.errors);
+        assert_eq!(
+            live.current().config.models[0].target_url.as_deref(),
+            Some("http://a:8000")
+        );
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn invalid_compare_glob_fails_to_load() {
+        let path = temp_path("compare-glob");
+        let too_big = "?".repeat(100_000);
+        write_config(
+            &path,
+            &format!("[compare]\ninclude_models = [\"claude-*\", \"{too_big}\"]\n"),
+        );
+        let err = LiveConfig::load(path.to_str().unwrap(), CliOverrides::default())
+            .err()
+            .expect("config with an invalid glob loaded");
+        assert!(err.to_string().contains("invalid model glob"), "{err}");
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn cli_overrides_survive_reload() {
+        let path = temp_path("overrides");
+        write_config(&path, "");
+        let overrides = CliOverrides {
+            target_url: Some("http://cli:8000".into()),
+            model_override: Some("cli-model".into()),
+            allow_anthropic_only: true,
+        };
+        let live = LiveConfig::load(path.to_str().unwrap(), overrides).unwrap();
+        live.reload().unwrap();
+
+        let snap = live.current();
+        assert_eq!(snap.config.target.url.as_deref(), Some("http://cli:8000"));
+        assert_eq!(snap.config.model_override.as_deref(), Some("cli-model"));
+        assert!(snap.config.anthropic_only_allowed);
+        // --model with no [[models]] synthesizes a registry entry
+        assert_eq!(snap.model_registry.len(), 1);
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn model_changes_apply_immediately_and_persist() {
+        let path = temp_path("models-api");
+        let state = temp_path("models-state");
+        std::fs::write(
+            &path,
+            format!(
+                r#"
+[server]
+models_state_file = "{}"
+[passthrough]
+[target]
+[tracing]
+
+[[models]]
+id = "a"
+target_url = "http://a:8000"
+"#,
+                state.display()
+            ),
+        )
+        .unwrap();
+        let live = LiveConfig::load(path.to_str().unwrap(), CliOverrides::default()).unwrap();
+
+        live.add_model(ModelDef {
+            id: "b".into(),
+            target_url: Some("http://b:8000".into()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(live.current().model_registry.is_local("b"));
+        assert!(matches!(
+            live.add_model(ModelDef {
+                id: "b".into(),
+                ..Default::default()
+            }),
+            Err(ModelChangeError::AlreadyExists(_))
+        ));
+
+        let updated = live
+            .update_model("b", &serde_json::json!({"target_url": "http://b:9000"}))
+            .unwrap();
+        assert_eq!(updated.target_url.as_deref(), Some("http://b:9000"));
+        assert!(matches!(
+            live.update_model("b", &serde_json::json!({"target_url": "nope"})),
+            Err(ModelChangeError::Invalid(_))
+        ));
+
+        live.remove_model("a").unwrap();
+        assert!(!live.current().model_registry.is_local("a"));
+
+        // Survives a reload of the unchanged TOML and a restart.
+        live.reload().unwrap();
+        let restarted = LiveConfig::load(path.to_str().unwrap(), CliOverrides::default()).unwrap();
+        for snap in [live.current(), restarted.current()] {
+            assert!(!snap.model_registry.is_local("a"));
+            assert_eq!(
+                snap.config.models[0].target_url.as_deref(),
+                Some("http://b:9000")
+            );
+        }
+
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(&state).ok();
+    }
+
+    #[test]
+    fn removing_a_fallback_target_is_rejected() {
+        let path = temp_path("models-fallback");
+        write_config(
+            &path,
+            r#"
+[[models]]
+id = "a"
+target_url = "http://a:8000"
+fallback = ["b"]
+
+[[models]]
+id = "b"
+target_url = "http://b:8000"
+"#,
+        );
+        let live = LiveConfig::load(path.to_str().unwrap(), CliOverrides::default()).unwrap();
+        assert!(matches!(
+            live.remove_model("b"),
+            Err(ModelChangeError::Invalid(_))
+        ));
+        assert!(matches!(
+            live.remove_model("missing"),
+            Err(ModelChangeError::NotFound(_))
+        ));
+        assert_eq!(live.current().model_registry.len(), 2);
+
+        std::fs::remove_file(&path).ok();
+    }
+}
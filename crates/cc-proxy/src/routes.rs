@@ -0,0 +1,320 @@
+//! Content-aware routing rules from `[[routes]]`.
+//!
+//! Each rule lists conditions on request features and a destination (a local
+//! model ID or `"anthropic"`). All conditions set on a rule must hold; rules
+//! are checked in order and the first match wins. Requests that match no rule
+//! fall through to model-based routing (exact ID, then aliases).
+
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Destination that keeps a request on the Anthropic passthrough.
+pub const ROUTE_ANTHROPIC: &str = "anthropic";
+
+/// One `[[routes]]` rule. Unset conditions are ignored.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RouteRule {
+    /// Name shown in traces and logs. Defaults to `routes[<index>]`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Local model ID, or `"anthropic"` for the passthrough.
+    pub to: String,
+
+    /// Whether the request declares any tools.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_tools: Option<bool>,
+    /// Matches when any of these tools is declared.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_names: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens_lte: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens_gte: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub messages_lte: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub messages_gte: Option<usize>,
+    /// Bounds on the estimated input size (see `crate::tokens`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_tokens_lte: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_tokens_gte: Option<u64>,
+    /// Matches when any of these flags is present in `anthropic-beta`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub anthropic_beta: Vec<String>,
+    /// Matches when the system prompt contains any of these substrings.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub system_contains: Vec<String>,
+    /// Matches when `metadata.user_id` equals any of these.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub user_ids: Vec<String>,
+}
+
+impl RouteRule {
+    /// Name for traces: the configured name or `routes[<index>]`.
+    pub fn label(&self, index: usize) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("routes[{index}]"))
+    }
+
+    /// Whether every condition set on this rule holds for `req`.
+    pub fn matches(&self, req: &RequestFeatures) -> bool {
+        if let Some(want) = self.has_tools {
+            let has_tools = !req.tool_names.is_empty();
+            if want != has_tools {
+                return false;
+            }
+        }
+        if !self.tool_names.is_empty()
+            && !self.tool_names.iter().any(|t| req.tool_names.contains(t))
+        {
+            return false;
+        }
+        if !within(req.max_tokens, self.max_tokens_gte, self.max_tokens_lte) {
+            return false;
+        }
+        if !within(
+            Some(req.message_count as u64),
+            self.messages_gte.map(|n| n as u64),
+            self.messages_lte.map(|n| n as u64),
+        ) {
+            return false;
+        }
+        if !within(
+            Some(req.input_tokens),
+            self.input_tokens_gte,
+            self.input_tokens_lte,
+        ) {
+            return false;
+        }
+        if !self.anthropic_beta.is_empty()
+            && !self.anthropic_beta.iter().any(|b| req.betas.contains(b))
+        {
+            return false;
+        }
+        if !self.system_contains.is_empty()
+            && !self
+                .system_contains
+                .iter()
+                .any(|s| req.system.contains(s.as_str()))
+        {
+            return false;
+        }
+        if !self.user_ids.is_empty()
+            && !req
+                .user_id
+                .as_ref()
+                .is_some_and(|u| self.user_ids.contains(u))
+        {
+            return false;
+        }
+        true
+    }
+}
+
+/// `value` lies within the optional inclusive bounds. A missing value only
+/// passes when no bound is set.
+fn within(value: Option<u64>, gte: Option<u64>, lte: Option<u64>) -> bool {
+    if gte.is_none() && lte.is_none() {
+        return true;
+    }
+    value.is_some_and(|v| gte.is_none_or(|min| v >= min) && lte.is_none_or(|max| v <= max))
+}
+
+/// The request features rules can match on, extracted once per request.
+#[derive(Debug, Default)]
+pub struct RequestFeatures {
+    pub tool_names: Vec<String>,
+    pub max_tokens: Option<u64>,
+    pub message_count: usize,
+    pub input_tokens: u64,
+    pub betas: Vec<String>,
+    pub system: String,
+    pub user_id: Option<String>,
+}
+
+impl RequestFeatures {
+    pub fn from_request(req: &Value, headers: &HeaderMap) -> Self {
+        let tool_names = req
+            .get("tools")
+            .and_then(|t| t.as_array())
+            .map(|tools| {
+                tools
+                    .iter()
+                    .filter_map(|t| t.get("name").and_then(|n| n.as_str()))
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let system = match req.get("system") {
+            Some(Value::String(s)) => s.clone(),
+            Some(Value::Array(blocks)) => blocks
+                .iter()
+                .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            _ => String::new(),
+        };
+
+        let betas = headers
+            .get_all("anthropic-beta")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|b| b.trim().to_string())
+            .filter(|b| !b.is_empty())
+            .collect();
+
+        Self {
+            tool_names,
+            max_tokens: req.get("max_tokens").and_then(|m| m.as_u64()),
+            message_count: req
+                .get("messages")
+                .and_then(|m| m.as_array())
+                .map_or(0, |m| m.len()),
+            input_tokens: crate::tokens::estimate_input_tokens(req),
+            betas,
+            system,
+            user_id: req
+                .get("metadata")
+                .and_then(|m| m.get("user_id"))
+                .and_then(|u| u.as_str())
+                .map(String::from),
+        }
+    }
+}
+
+/// First rule matching `req`, with its index.
+pub fn first_match<'a>(
+    rules: &'a [RouteRule],
+    req: &RequestFeatures,
+) -> Option<(usize, &'a RouteRule)> {
+    rules.iter().enumerate().find(|(_, rule)| rule.matches(req))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn features(req: Value) -> RequestFeatures {
+        RequestFeatures::from_request(&req, &HeaderMap::new())
+    }
+
+    fn rule(to: &str) -> RouteRule {
+        RouteRule {
+            to: to.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = vec![
+            RouteRule {
+                name: Some("titles".into()),
+                has_tools: Some(false),
+                max_tokens_lte: Some(512),
+                ..rule("small")
+            },
+            RouteRule {
+                has_tools: Some(true),
+                messages_gte: Some(3),
+                ..rule("big")
+            },
+        ];
+
+        let title = features(json!({
+            "max_tokens": 64,
+            "messages": [{"role": "user", "content": "Summarize in 5 words"}]
+        }));
+        let (i, r) = first_match(&rules, &title).unwrap();
+        assert_eq!((i, r.label(i).as_str()), (0, "titles"));
+
+        let agentic = features(json!({
+            "max_tokens": 32000,
+            "tools": [{"name": "Bash", "input_schema": {}}],
+            "messages": [{"role": "user", "content": "a"}, {"role": "assistant", "content": "b"}, {"role": "user", "content": "c"}]
+        }));
+        let (i, r) = first_match(&rules, &agentic).unwrap();
+        assert_eq!((i, r.label(i).as_str()), (1, "routes[1]"));
+
+        let neither = features(json!({"max_tokens": 32000, "messages": []}));
+        assert!(first_match(&rules, &neither).is_none());
+    }
+
+    #[test]
+    fn tool_names_match_any() {
+        let r = RouteRule {
+            tool_names: vec!["WebSearch".into(), "WebFetch".into()],
+            ..rule("anthropic")
+        };
+        assert!(r.matches(&features(
+            json!({"tools": [{"name": "Bash"}, {"name": "WebFetch"}]})
+        )));
+        assert!(!r.matches(&features(json!({"tools": [{"name": "Bash"}]}))));
+    }
+
+    #[test]
+    fn bounds_require_the_value() {
+        let r = RouteRule {
+            max_tokens_gte: Some(100),
+            ..rule("big")
+        };
+        assert!(!r.matches(&features(json!({}))));
+        assert!(r.matches(&features(json!({"max_tokens": 100}))));
+        assert!(!r.matches(&features(json!({"max_tokens": 99}))));
+    }
+
+    #[test]
+    fn input_size_bound() {
+        let r = RouteRule {
+            input_tokens_gte: Some(1000),
+            ..rule("big")
+        };
+        let long = "x".repeat(8000);
+        assert!(r.matches(&features(
+            json!({"messages": [{"role": "user", "content": long}]})
+        )));
+        assert!(!r.matches(&features(
+            json!({"messages": [{"role": "user", "content": "short"}]})
+        )));
+    }
+
+    #[test]
+    fn beta_header_system_and_user_id() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "anthropic-beta",
+            "interleaved-thinking-2025-05-14, context-1m-2025-08-07"
+                .parse()
+                .unwrap(),
+        );
+        let req = json!({
+            "system": [{"type": "text", "text": "You are Claude Code."}, {"type": "text", "text": "Generate a title"}],
+            "metadata": {"user_id": "alice"}
+        });
+        let f = RequestFeatures::from_request(&req, &headers);
+
+        let beta = RouteRule {
+            anthropic_beta: vec!["context-1m-2025-08-07".into()],
+            ..rule("anthropic")
+        };
+        assert!(beta.matches(&f));
+
+        let system = RouteRule {
+            system_contains: vec!["Generate a title".into()],
+            ..rule("small")
+        };
+        assert!(system.matches(&f));
+
+        let users = RouteRule {
+            user_ids: vec!["bob".into()],
+            ..rule("small")
+        };
+        assert!(!users.matches(&f));
+    }
+}
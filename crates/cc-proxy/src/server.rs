@@ -1,71 +1,145 @@
 //! Axum HTTP server: router, listener, graceful shutdown.
 
+use std::pin::Pin;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::task::{Context, Poll};
 
-use axum::extract::{Path, Request, State};
-use axum::http::{HeaderMap, StatusCode};
+use axum::body::Body;
+use axum::extract::{Path, Query, Request, State};
+use axum::http::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
-use axum::routing::{get, post};
+use axum::routing::{get, patch, post};
 use axum::Router;
 use bytes::Bytes;
+use futures_core::Stream;
+use futures_util::stream::{self, StreamExt};
+use serde::Deserialize;
+use tokio_util::io::ReaderStream;
 use tracing::Instrument;
 
-use crate::config::{ProxyConfig, TargetConfig};
+use crate::batches::{self, BatchRequest, BatchResult, BatchStore, ProcessingStatus};
+use crate::config::TargetConfig;
+use crate::context::{self, ContextOverflow};
+use crate::convert::anthropic_to_openai::{self, ChunkTranslator};
+use crate::convert::capabilities;
+use crate::convert::openai_to_anthropic;
+use crate::convert::validation::ValidationFinding;
 use crate::mode::{ProxyMode, RuntimeMode};
-use crate::models::{ModelRegistry, RouteTarget};
+use crate::models::{EndpointLease, FallbackHop, ModelDef, RouteTarget, TargetProtocol};
 use crate::openinference;
-use crate::proxy::compare::CompareDispatcher;
+use crate::proxy::compare::{CompareDispatcher, CompareEndpoint, CompareTarget};
+use crate::proxy::compare_policy::{self, CompareDecision};
+use crate::proxy::compare_stats::CompareStats;
+use crate::proxy::compare_store::{CompareFilter, CompareStore, RequestSummary};
 use crate::proxy::correlation;
-use crate::proxy::primary;
+use crate::proxy::judge::{self, JudgeRequest};
+use crate::proxy::primary::{self, FailedForward};
+use crate::reload::{LiveConfig, ModelChangeError, Snapshot};
+use crate::routes::{self, RequestFeatures, ROUTE_ANTHROPIC};
 use crate::stats::ProxyStats;
+use crate::targets::{CircuitBreakers, TargetHealth};
+use crate::tokens::{self, TokenizerCache};
 
 /// Shared application state.
 #[derive(Clone)]
 pub struct AppState {
-    pub config: ProxyConfig,
+    /// Hot-reloadable config + model registry. Load once per request.
+    pub live: LiveConfig,
     pub primary_client: reqwest::Client,
     pub compare_dispatcher: CompareDispatcher,
     pub stats: ProxyStats,
     pub mode: RuntimeMode,
-    pub model_registry: ModelRegistry,
     pub tracing_enabled: Arc<AtomicBool>,
+    /// Per-target health, updated by the background checker.
+    pub targets: Arc<TargetHealth>,
+    /// Per-target circuit breakers, shared with the compare dispatcher.
+    pub breakers: Arc<CircuitBreakers>,
+    /// Model tokenizers for `/v1/messages/count_tokens`, loaded on first use.
+    pub tokenizers: Arc<TokenizerCache>,
+    /// Message Batches emulated for local models.
+    pub batches: Arc<BatchStore>,
+    /// Completed compare pairs, shared with the compare dispatcher.
+    pub compares: Arc<CompareStore>,
+    /// Rolling per-model compare aggregates, fed by the compare dispatcher.
+    pub compare_stats: Arc<CompareStats>,
 }
 
 /// Build and run the HTTP server.
 pub async fn run(state: AppState) -> anyhow::Result<()> {
-    let listen_addr = state.config.server.listen_address.clone();
+    let listen_addr = state.live.current().config.server.listen_address.clone();
+    let state = Arc::new(state);
 
-    let app = Router::new()
+    for batch_id in state.batches.unfinished() {
+        tracing::info!(batch_id = %batch_id, "Resuming message batch");
+        tokio::spawn(run_batch(state.clone(), batch_id));
+    }
+
+    let app = router(state);
+
+    let listener = tokio::net::TcpListener::bind(&listen_addr).await?;
+    tracing::info!(address = %listen_addr, "cc-proxy listening");
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    tracing::info!("cc-proxy shut down gracefully");
+    Ok(())
+}
+
+/// All proxy and admin routes.
+fn router(state: Arc<AppState>) -> Router {
+    Router::new()
         .route("/v1/messages", post(handle_messages))
+        .route("/v1/messages/count_tokens", post(handle_count_tokens))
+        .route(
+            "/v1/messages/batches",
+            get(handle_list_batches).post(handle_create_batch),
+        )
+        .route(
+            "/v1/messages/batches/{batch_id}",
+            get(handle_get_batch).delete(handle_delete_batch),
+        )
+        .route(
+            "/v1/messages/batches/{batch_id}/results",
+            get(handle_batch_results),
+        )
+        .route(
+            "/v1/messages/batches/{batch_id}/cancel",
+            post(handle_cancel_batch),
+        )
+        .route("/v1/chat/completions", post(handle_chat_completions))
         .route("/v1/models", get(handle_list_models))
         .route("/v1/models/{model_id}", get(handle_get_model))
         .route("/health", get(handle_health))
         .route("/api/stats", get(handle_get_stats))
+        .route("/api/compare", get(handle_list_compares))
+        .route("/api/compare/summary", get(handle_compare_summary))
+        .route("/api/compare/stats", get(handle_compare_stats))
         .route("/api/mode", get(handle_get_mode).put(handle_set_mode))
+        .route("/api/config/reload", post(handle_reload_config))
+        .route("/api/models", post(handle_create_model))
+        .route(
+            "/api/models/{model_id}",
+            patch(handle_update_model).delete(handle_delete_model),
+        )
+        .route("/api/targets", get(handle_get_targets))
         .route(
             "/api/tracing",
             get(handle_get_tracing).put(handle_set_tracing),
         )
         .fallback(handle_fallback)
-        .with_state(Arc::new(state));
-
-    let listener = tokio::net::TcpListener::bind(&listen_addr).await?;
-    tracing::info!(address = %listen_addr, "cc-proxy listening");
-
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
-
-    tracing::info!("cc-proxy shut down gracefully");
-    Ok(())
+        .with_state(state)
 }
 
 /// Main handler for POST /v1/messages.
 ///
 /// Routing is model-based:
 /// 1. Extract model name from request body
-/// 2. Resolve via ModelRegistry → Local or Anthropic
+/// 2. The first matching `[[routes]]` rule picks a local model or Anthropic;
+///    otherwise resolve via ModelRegistry (exact ID, then `[[aliases]]`)
 /// 3. Local models: apply target defaults, forward to target URL
 /// 4. Anthropic models: forward body as-is to Anthropic passthrough
 ///
@@ -110,12 +184,16 @@ async fn handle_messages(
     // Increment request counter
     state.stats.inc_requests();
 
+    // Pin the config snapshot for the lifetime of this request so a
+    // concurrent reload can't change routing mid-flight.
+    let snapshot = state.live.current();
+
     async {
         let current_mode = state.mode.get();
 
         // In anthropic-only mode, reject requests for local models
         if current_mode == ProxyMode::AnthropicOnly {
-            if let RouteTarget::Local { .. } = state.model_registry.resolve(&model) {
+            if snapshot.model_registry.is_local(&model) {
                 return (
                     StatusCode::BAD_REQUEST,
                     axum::Json(serde_json::json!({
@@ -129,111 +207,1149 @@ async fn handle_messages(
                     .into_response();
             }
 
-            // Check if anthropic-only mode is allowed
-            if !state.config.anthropic_only_allowed {
-                return (
-                    StatusCode::FORBIDDEN,
-                    axum::Json(serde_json::json!({
-                        "error": "anthropic-only mode is not enabled; restart with --allow-anthropic-only"
-                    })),
-                )
-                    .into_response();
+            // Check if anthropic-only mode is allowed
+            if !snapshot.config.anthropic_only_allowed {
+                return (
+                    StatusCode::FORBIDDEN,
+                    axum::Json(serde_json::json!({
+                        "error": "anthropic-only mode is not enabled; restart with --allow-anthropic-only"
+                    })),
+                )
+                    .into_response();
+            }
+        }
+
+        let route = resolve_route(
+            &state,
+            &snapshot,
+            current_mode,
+            parsed.as_ref(),
+            &headers,
+            &model,
+        );
+
+        let (route, overflow_model) =
+            match enforce_context_window(&state, &snapshot, route, parsed.as_ref()) {
+                Ok(routed) => routed,
+                Err(overflow) => return overflow.into_response(),
+            };
+
+        match route {
+            RouteTarget::Local {
+                model_def,
+                target_url,
+                lease,
+            } => {
+                forward_local_with_fallback(
+                    &state,
+                    &snapshot,
+                    &headers,
+                    &body,
+                    &model,
+                    model_def,
+                    target_url,
+                    lease,
+                    &correlation_id,
+                    is_streaming,
+                )
+                .await
+            }
+            RouteTarget::Anthropic => {
+                // In compare mode, also fire-and-forget to the default target
+                // when the compare policy picks this request. Requests that
+                // only got here by overflowing a local model are not compared.
+                let compare = current_mode == ProxyMode::Compare
+                    && overflow_model.is_none()
+                    && {
+                    let decision = compare_policy::decide(
+                        &snapshot.config.compare,
+                        parsed.as_ref(),
+                        &model,
+                        body.len(),
+                    );
+                    tracing::Span::current().record("compare_decision", decision.as_str());
+                    decision == CompareDecision::Sampled
+                };
+                if compare {
+                    let judge = judge_request(&state, &snapshot, parsed.as_ref());
+                    for target in compare_targets(&state, &snapshot, &body, &model) {
+                        let request =
+                            RequestSummary::new(parsed.as_ref(), &model, &target.model);
+                        state
+                            .compare_dispatcher
+                            .dispatch(
+                                target,
+                                correlation_id.clone(),
+                                request,
+                                &snapshot.config.compare,
+                                judge.clone(),
+                            );
+                    }
+                }
+                let observer = compare.then(|| {
+                    state
+                        .compare_dispatcher
+                        .primary_observer(correlation_id.clone())
+                });
+
+                // Forward the original body to Anthropic, renamed to the
+                // `overflow_to` model when the request overflowed a local one
+                let body = match overflow_model {
+                    Some(ref to) => match rewrite_model_field(&body, to) {
+                        Ok(rewritten) => rewritten,
+                        Err(e) => {
+                            tracing::warn!(error = %e, "Failed to rewrite model for overflow");
+                            body
+                        }
+                    },
+                    None => body,
+                };
+                let url = format!("{}/v1/messages", snapshot.config.passthrough.url);
+
+                tracing::info!(
+                    model = %model,
+                    "Routing to Anthropic"
+                );
+
+                let root_span = tracing::Span::current();
+                root_span.record("served_by", "anthropic");
+                primary::forward_to_anthropic(
+                    &state.primary_client,
+                    &url,
+                    &snapshot.config.retry,
+                    &headers,
+                    body,
+                    &correlation_id,
+                    is_streaming,
+                    root_span,
+                    state.stats.clone(),
+                    observer,
+                )
+                .await
+                .unwrap_or_else(FailedForward::into_response)
+            }
+        }
+    }
+    .instrument(span)
+    .await
+}
+
+/// Handler for POST /v1/messages/count_tokens.
+///
+/// Routed like `/v1/messages`. Anthropic models pass through unchanged. Local
+/// models are counted with their `tokenizer` when configured, else by the
+/// target's own endpoint (`anthropic` protocol targets with the
+/// `count_tokens` capability), else with the character estimate.
+async fn handle_count_tokens(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let correlation_id = correlation::generate_id();
+    let snapshot = state.live.current();
+    let parsed: Option<serde_json::Value> = serde_json::from_slice(&body).ok();
+    let model = parsed
+        .as_ref()
+        .and_then(|v| v.get("model").and_then(|m| m.as_str()))
+        .unwrap_or("unknown")
+        .to_string();
+
+    let route = resolve_route(
+        &state,
+        &snapshot,
+        state.mode.get(),
+        parsed.as_ref(),
+        &headers,
+        &model,
+    );
+    let RouteTarget::Local {
+        model_def,
+        target_url,
+        lease: _lease,
+    } = route
+    else {
+        let url = format!(
+            "{}/v1/messages/count_tokens",
+            snapshot.config.passthrough.url
+        );
+        return primary::forward_raw(
+            &state.primary_client,
+            axum::http::Method::POST,
+            &url,
+            &headers,
+            body,
+            &correlation_id,
+        )
+        .await;
+    };
+    let mut req = parsed.unwrap_or_default();
+
+    if let Some(ref path) = model_def.tokenizer {
+        match state.tokenizers.count(path, &req).await {
+            Ok(input_tokens) => {
+                return count_tokens_response(&model_def.id, "tokenizer", input_tokens)
+            }
+            Err(e) => tracing::warn!(
+                model = %model_def.id,
+                error = %e,
+                "Tokenizer failed, falling back"
+            ),
+        }
+    }
+    if model_def.protocol == TargetProtocol::Anthropic && model_def.capabilities.count_tokens {
+        // The target must see its own model ID, as on /v1/messages.
+        let new_model = (model_def.id != model)
+            .then_some(model_def.id.as_str())
+            .or(snapshot.config.model_override.as_deref());
+        if let (Some(new_model), Some(obj)) = (new_model, req.as_object_mut()) {
+            obj.insert(
+                "model".to_string(),
+                serde_json::Value::String(new_model.to_string()),
+            );
+        }
+        let mut findings = capabilities::strip_unsupported(&mut req, &model_def.capabilities);
+        let target_headers = capabilities::strip_unsupported_headers(
+            &headers,
+            &model_def.capabilities,
+            &mut findings,
+        );
+        let target_body = Bytes::from(serde_json::to_vec(&req).unwrap_or_default());
+        match primary::count_tokens_on_target(
+            &state.primary_client,
+            &target_url,
+            &target_headers,
+            &model_auth_headers(&snapshot, &model_def),
+            target_body,
+            &correlation_id,
+        )
+        .await
+        {
+            Ok(input_tokens) => {
+                return count_tokens_response(&model_def.id, "target", input_tokens)
+            }
+            Err(e) => tracing::debug!(
+                model = %model_def.id,
+                target_url = %target_url,
+                error = %e,
+                "Target count_tokens failed, estimating"
+            ),
+        }
+    }
+    count_tokens_response(
+        &model_def.id,
+        "estimate",
+        tokens::estimate_input_tokens(&req),
+    )
+}
+
+fn count_tokens_response(model: &str, counted_by: &str, input_tokens: u64) -> Response {
+    tracing::debug!(model, counted_by, input_tokens, "Counted input tokens");
+    axum::Json(serde_json::json!({ "input_tokens": input_tokens })).into_response()
+}
+
+/// POST /v1/messages/batches — create a Message Batch.
+///
+/// Batches whose requests all route to local models are stored and run by
+/// the proxy (see `batches`); batches for Anthropic models go to the
+/// passthrough like any other unmatched path. Mixing the two is rejected.
+async fn handle_create_batch(State(state): State<Arc<AppState>>, request: Request) -> Response {
+    let (parts, body) = request.into_parts();
+    let body = match axum::body::to_bytes(body, batches::MAX_BATCH_BYTES).await {
+        Ok(b) => b,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to read request body");
+            return (StatusCode::BAD_REQUEST, "failed to read request body").into_response();
+        }
+    };
+    let invalid = |message: String| {
+        batches::error_response(StatusCode::BAD_REQUEST, "invalid_request_error", message)
+    };
+
+    #[derive(Deserialize)]
+    struct CreateBatch {
+        requests: Vec<BatchRequest>,
+    }
+    let requests = match serde_json::from_slice::<CreateBatch>(&body) {
+        Ok(create) => create.requests,
+        Err(e) => return invalid(format!("invalid batch: {e}")),
+    };
+    if requests.is_empty() || requests.len() > batches::MAX_REQUESTS {
+        return invalid(format!(
+            "requests: expected 1 to {} requests, got {}",
+            batches::MAX_REQUESTS,
+            requests.len()
+        ));
+    }
+    let mut seen = std::collections::HashSet::new();
+    if let Some(dup) = requests.iter().find(|r| !seen.insert(r.custom_id.as_str())) {
+        return invalid(format!("requests: duplicate custom_id '{}'", dup.custom_id));
+    }
+
+    let snapshot = state.live.current();
+    let mode = state.mode.get();
+    let local = requests
+        .iter()
+        .filter(|r| routes_locally(&snapshot, mode, &r.params, &parts.headers))
+        .count();
+    if local == 0 {
+        let request = Request::from_parts(parts, Body::from(body));
+        return handle_fallback(State(state), request).await;
+    }
+    if local < requests.len() {
+        return invalid("batches must use only local models or only Anthropic models".to_string());
+    }
+
+    let count = requests.len();
+    let headers = parts.headers;
+    let created = blocking_batches(&state, move |store| store.create(&requests, &headers)).await;
+    let batch = match created {
+        Ok(batch) => batch,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to store message batch");
+            return batches::error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "api_error",
+                "failed to store batch",
+            );
+        }
+    };
+    tracing::info!(batch_id = %batch.id, requests = count, "Created message batch");
+    tokio::spawn(run_batch(state.clone(), batch.id.clone()));
+    axum::Json(batch).into_response()
+}
+
+/// Whether a request would be served by a local model: the route rule's
+/// target when one matches, else the model name (aliases included).
+fn routes_locally(
+    snapshot: &Snapshot,
+    mode: ProxyMode,
+    req: &serde_json::Value,
+    headers: &HeaderMap,
+) -> bool {
+    if mode == ProxyMode::AnthropicOnly {
+        return false;
+    }
+    let features = RequestFeatures::from_request(req, headers);
+    let model = match routes::first_match(&snapshot.config.routes, &features) {
+        Some((_, rule)) if rule.to == ROUTE_ANTHROPIC => return false,
+        Some((_, rule)) => rule.to.as_str(),
+        None => {
+            let model = req.get("model").and_then(|m| m.as_str()).unwrap_or("");
+            snapshot.model_registry.canonical_model(model).0
+        }
+    };
+    snapshot.model_registry.is_local(model)
+}
+
+/// Run every pending request of a batch, then mark it ended. Requests run
+/// concurrently, bounded per local model by `batches.max_concurrent_per_target`
+/// for each of its endpoints.
+async fn run_batch(state: Arc<AppState>, batch_id: String) {
+    let id = batch_id.clone();
+    let pending = match blocking_batches(&state, move |store| store.pending(&id)).await {
+        Ok(pending) => pending,
+        Err(e) => {
+            tracing::error!(batch_id = %batch_id, error = %e, "Failed to read batch requests");
+            return;
+        }
+    };
+    let headers = state.batches.headers(&batch_id);
+    // Only as many requests as could run against one endpoint are started;
+    // the rest wait here rather than as tasks
+    stream::iter(pending)
+        .map(|request| {
+            let (state, batch_id, headers) = (&state, &batch_id, &headers);
+            async move {
+                let result = run_batch_request(state, batch_id, headers, request.params).await;
+                let (id, custom_id) = (batch_id.clone(), request.custom_id.clone());
+                let recorded =
+                    blocking_batches(state, move |store| store.record(&id, &custom_id, result));
+                if let Err(e) = recorded.await {
+                    tracing::error!(batch_id = %batch_id, custom_id = %request.custom_id, error = %e, "Failed to record batch result");
+                }
+            }
+        })
+        .buffer_unordered(state.batches.max_concurrent_per_target())
+        .for_each(|()| std::future::ready(()))
+        .await;
+
+    let id = batch_id.clone();
+    match blocking_batches(&state, move |store| store.finish(&id)).await {
+        Ok(()) => tracing::info!(batch_id = %batch_id, "Message batch ended"),
+        Err(e) => tracing::error!(batch_id = %batch_id, error = %e, "Failed to store ended batch"),
+    }
+}
+
+/// Send one batch request through the `/v1/messages` local path (routing,
+/// context window, defaults, fallbacks) without streaming.
+async fn run_batch_request(
+    state: &AppState,
+    batch_id: &str,
+    headers: &HeaderMap,
+    mut params: serde_json::Value,
+) -> BatchResult {
+    if state.batches.is_canceling(batch_id) {
+        return BatchResult::Canceled;
+    }
+    if let Some(obj) = params.as_object_mut() {
+        obj.remove("stream");
+    }
+    let model = params
+        .get("model")
+        .and_then(|m| m.as_str())
+        .unwrap_or("unknown")
+        .to_string();
+    let correlation_id = correlation::generate_id();
+    let span = cc_tracing::proxy_request_span!(&correlation_id, &model);
+    span.record("batch_id", batch_id);
+    openinference::set_request_attributes(&span, &params);
+    let snapshot = state.live.current();
+
+    async {
+        let route = resolve_route(
+            state,
+            &snapshot,
+            state.mode.get(),
+            Some(&params),
+            headers,
+            &model,
+        );
+        let route = match enforce_context_window(state, &snapshot, route, Some(&params)) {
+            Ok((route, _)) => route,
+            Err(overflow) => return batch_result(overflow.into_response()).await,
+        };
+        let (model_id, endpoints) = match &route {
+            RouteTarget::Local { model_def, .. } => {
+                (model_def.id.clone(), model_def.endpoints.len().max(1))
+            }
+            RouteTarget::Anthropic => {
+                return BatchResult::error(
+                    "invalid_request_error",
+                    format!("model '{model}' is not served by a local target"),
+                );
+            }
+        };
+        // Pick the endpoint only once a slot is free, so waiting batch
+        // requests don't count as in flight on it
+        drop(route);
+        let limit = state.batches.limit(&model_id, endpoints);
+        let Ok(_permit) = limit.acquire_owned().await else {
+            return BatchResult::Canceled;
+        };
+        if state.batches.is_canceling(batch_id) {
+            return BatchResult::Canceled;
+        }
+        let RouteTarget::Local {
+            model_def,
+            target_url,
+            lease,
+        } = snapshot.model_registry.resolve(&model_id, &state.targets)
+        else {
+            return BatchResult::error(
+                "invalid_request_error",
+                format!("model '{model_id}' is not served by a local target"),
+            );
+        };
+        state.stats.inc_requests();
+        let body = Bytes::from(serde_json::to_vec(&params).unwrap_or_default());
+        let response = forward_local_with_fallback(
+            state,
+            &snapshot,
+            headers,
+            &body,
+            &model,
+            model_def,
+            target_url,
+            lease,
+            &correlation_id,
+            false,
+        )
+        .await;
+        batch_result(response).await
+    }
+    .instrument(span)
+    .await
+}
+
+/// A batch result from a `/v1/messages` response: the message on success,
+/// the Anthropic error body otherwise.
+async fn batch_result(response: Response) -> BatchResult {
+    let status = response.status();
+    let body = match axum::body::to_bytes(response.into_body(), batches::MAX_RESULT_BYTES).await {
+        Ok(body) => body,
+        Err(e) => return BatchResult::error("api_error", format!("reading response: {e}")),
+    };
+    match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(message) if status.is_success() => BatchResult::Succeeded { message },
+        Ok(error) if error.get("type").and_then(|t| t.as_str()) == Some("error") => {
+            BatchResult::Errored { error }
+        }
+        _ => BatchResult::error(
+            "api_error",
+            format!(
+                "upstream returned {}: {}",
+                status.as_u16(),
+                String::from_utf8_lossy(&body)
+            ),
+        ),
+    }
+}
+
+/// Query parameters of GET /v1/messages/batches.
+#[derive(Deserialize)]
+struct ListBatchesQuery {
+    #[serde(default = "default_batch_list_limit")]
+    limit: usize,
+    before_id: Option<String>,
+    after_id: Option<String>,
+}
+
+fn default_batch_list_limit() -> usize {
+    20
+}
+
+/// GET /v1/messages/batches — list local batches, newest first.
+async fn handle_list_batches(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ListBatchesQuery>,
+) -> Response {
+    let limit = query.limit.clamp(1, 1000);
+    let (data, has_more) =
+        state
+            .batches
+            .list(limit, query.before_id.as_deref(), query.after_id.as_deref());
+    axum::Json(serde_json::json!({
+        "first_id": data.first().map(|b| &b.id),
+        "last_id": data.last().map(|b| &b.id),
+        "has_more": has_more,
+        "data": data,
+    }))
+    .into_response()
+}
+
+/// GET /v1/messages/batches/{batch_id}. Unknown IDs go to the passthrough.
+async fn handle_get_batch(
+    State(state): State<Arc<AppState>>,
+    Path(batch_id): Path<String>,
+    request: Request,
+) -> Response {
+    match state.batches.get(&batch_id) {
+        Some(batch) => axum::Json(batch).into_response(),
+        None => handle_fallback(State(state), request).await,
+    }
+}
+
+/// GET /v1/messages/batches/{batch_id}/results — JSONL, once the batch ended.
+async fn handle_batch_results(
+    State(state): State<Arc<AppState>>,
+    Path(batch_id): Path<String>,
+    request: Request,
+) -> Response {
+    let Some(batch) = state.batches.get(&batch_id) else {
+        return handle_fallback(State(state), request).await;
+    };
+    if batch.processing_status != ProcessingStatus::Ended {
+        return batches::error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            format!("batch {batch_id} is still processing"),
+        );
+    }
+    let body = match tokio::fs::File::open(state.batches.results_path(&batch_id)).await {
+        Ok(file) => Body::from_stream(ReaderStream::new(file)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Body::empty(),
+        Err(e) => {
+            tracing::error!(batch_id = %batch_id, error = %e, "Failed to read batch results");
+            return batches::error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "api_error",
+                "failed to read batch results",
+            );
+        }
+    };
+    ([(CONTENT_TYPE, "application/x-jsonl")], body).into_response()
+}
+
+/// POST /v1/messages/batches/{batch_id}/cancel. Requests not yet sent finish
+/// as `canceled`; the batch ends once in-flight ones return.
+async fn handle_cancel_batch(
+    State(state): State<Arc<AppState>>,
+    Path(batch_id): Path<String>,
+    request: Request,
+) -> Response {
+    let id = batch_id.clone();
+    match blocking_batches(&state, move |store| store.cancel(&id)).await {
+        Ok(Some(batch)) => {
+            tracing::info!(batch_id = %batch_id, "Canceling message batch");
+            axum::Json(batch).into_response()
+        }
+        Ok(None) => handle_fallback(State(state), request).await,
+        Err(e) => {
+            tracing::error!(batch_id = %batch_id, error = %e, "Failed to store batch cancellation");
+            batches::error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "api_error",
+                "failed to cancel batch",
+            )
+        }
+    }
+}
+
+/// DELETE /v1/messages/batches/{batch_id} — only ended batches.
+async fn handle_delete_batch(
+    State(state): State<Arc<AppState>>,
+    Path(batch_id): Path<String>,
+    request: Request,
+) -> Response {
+    match state.batches.delete(&batch_id) {
+        Ok(true) => axum::Json(serde_json::json!({
+            "id": batch_id,
+            "type": "message_batch_deleted",
+        }))
+        .into_response(),
+        Ok(false) => handle_fallback(State(state), request).await,
+        Err(_) => batches::error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            format!("batch {batch_id} is still processing; cancel it first"),
+        ),
+    }
+}
+
+/// Run a `BatchStore` call that touches disk off the async workers.
+async fn blocking_batches<T, E>(
+    state: &AppState,
+    op: impl FnOnce(&BatchStore) -> Result<T, E> + Send + 'static,
+) -> Result<T, E>
+where
+    T: Send + 'static,
+    E: From<std::io::Error> + Send + 'static,
+{
+    let store = state.batches.clone();
+    tokio::task::spawn_blocking(move || op(&store))
+        .await
+        .unwrap_or_else(|e| Err(std::io::Error::other(e).into()))
+}
+
+/// Handler for POST /v1/chat/completions (OpenAI-compatible ingress).
+///
+/// Converts the request to Anthropic format and runs it through
+/// `handle_messages`, so routing, stats and spans are exactly those of
+/// `/v1/messages`; the Anthropic response (JSON, SSE or error) is converted
+/// back to OpenAI format as it streams.
+async fn handle_chat_completions(
+    State(state): State<Arc<AppState>>,
+    mut headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let req: serde_json::Value = match serde_json::from_slice(&body) {
+        Ok(v) => v,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                axum::Json(serde_json::json!({
+                    "error": {
+                        "message": format!("invalid JSON body: {e}"),
+                        "type": "invalid_request_error",
+                        "param": null,
+                        "code": null,
+                    }
+                })),
+            )
+                .into_response();
+        }
+    };
+    let include_usage = req
+        .pointer("/stream_options/include_usage")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let anthropic_req = openai_to_anthropic::openai_request_to_anthropic(&req);
+    let anthropic_body = Bytes::from(serde_json::to_vec(&anthropic_req).unwrap_or_default());
+    adapt_openai_headers(&mut headers);
+
+    let response = handle_messages(State(state), headers, anthropic_body).await;
+
+    let (mut parts, body) = response.into_parts();
+    let is_sse = parts
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("text/event-stream"));
+    let translation = match (parts.status.is_success(), is_sse) {
+        (true, true) => IngressTranslation::Sse(ChunkTranslator::new(include_usage)),
+        (true, false) => IngressTranslation::Json(Vec::new()),
+        (false, _) => IngressTranslation::Error(Vec::new()),
+    };
+    parts.headers.remove(CONTENT_LENGTH);
+    if !is_sse {
+        parts
+            .headers
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    }
+    let body = ChatCompletionsBody {
+        inner: body.into_data_stream(),
+        translation,
+        done: false,
+    };
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+/// OpenAI SDKs authenticate with `authorization: Bearer <key>` and never send
+/// `anthropic-version`. Move Anthropic API keys to `x-api-key` and add the
+/// version header so passthrough requests are accepted.
+fn adapt_openai_headers(headers: &mut HeaderMap) {
+    if !headers.contains_key("x-api-key") {
+        let api_key = headers
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .filter(|key| key.starts_with("sk-ant-api"))
+            .and_then(|key| HeaderValue::from_str(key).ok());
+        if let Some(api_key) = api_key {
+            headers.insert("x-api-key", api_key);
+            headers.remove(AUTHORIZATION);
+        }
+    }
+    if !headers.contains_key("anthropic-version") {
+        headers.insert(
+            "anthropic-version",
+            HeaderValue::from_static(primary::ANTHROPIC_VERSION),
+        );
+    }
+}
+
+/// How a `/v1/chat/completions` response body is converted.
+enum IngressTranslation {
+    Sse(ChunkTranslator),
+    /// Buffered successful JSON response.
+    Json(Vec<u8>),
+    /// Buffered error body (Anthropic error JSON or plain text).
+    Error(Vec<u8>),
+}
+
+/// Response body converting the Anthropic response of `handle_messages` to
+/// OpenAI format.
+struct ChatCompletionsBody {
+    inner: axum::body::BodyDataStream,
+    translation: IngressTranslation,
+    done: bool,
+}
+
+impl Stream for ChatCompletionsBody {
+    type Item = Result<Bytes, axum::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match Pin::new(&mut self.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => match self.translation {
+                    IngressTranslation::Sse(ref mut translator) => {
+                        let out = translator.push(&chunk);
+                        if !out.is_empty() {
+                            return Poll::Ready(Some(Ok(Bytes::from(out))));
+                        }
+                    }
+                    IngressTranslation::Json(ref mut buffer)
+                    | IngressTranslation::Error(ref mut buffer) => buffer.extend_from_slice(&chunk),
+                },
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => {
+                    if self.done {
+                        return Poll::Ready(None);
+                    }
+                    self.done = true;
+                    let out = match self.translation {
+                        IngressTranslation::Sse(ref mut translator) => translator.finish(),
+                        IngressTranslation::Json(ref buffer) => {
+                            let converted = match serde_json::from_slice(buffer) {
+                                Ok(resp) => {
+                                    anthropic_to_openai::anthropic_response_to_openai(&resp)
+                                }
+                                Err(_) => anthropic_to_openai::anthropic_error_to_openai(buffer),
+                            };
+                            serde_json::to_vec(&converted).unwrap_or_default()
+                        }
+                        IngressTranslation::Error(ref buffer) => serde_json::to_vec(
+                            &anthropic_to_openai::anthropic_error_to_openai(buffer),
+                        )
+                        .unwrap_or_default(),
+                    };
+                    return Poll::Ready(Some(Ok(Bytes::from(out))));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Resolve where a request goes: `[[routes]]` rules on request content
+/// first, then the model name (exact ID, then `[[aliases]]`). Neither applies
+/// in anthropic-only mode — everything stays on the passthrough. Records the
+/// match on the current span.
+fn resolve_route(
+    state: &AppState,
+    snapshot: &Snapshot,
+    current_mode: ProxyMode,
+    parsed: Option<&serde_json::Value>,
+    headers: &HeaderMap,
+    model: &str,
+) -> RouteTarget {
+    if current_mode == ProxyMode::AnthropicOnly {
+        return RouteTarget::Anthropic;
+    }
+    // Extracting request features walks the whole body; skip it when there
+    // are no rules to match against.
+    let matched_route = if snapshot.config.routes.is_empty() {
+        None
+    } else {
+        parsed.and_then(|req| {
+            let features = RequestFeatures::from_request(req, headers);
+            routes::first_match(&snapshot.config.routes, &features)
+        })
+    };
+    if let Some((index, rule)) = matched_route {
+        let label = rule.label(index);
+        let root_span = tracing::Span::current();
+        root_span.record("matched_route", label.as_str());
+        tracing::debug!(
+            original_model = %model,
+            route = %label,
+            to = %rule.to,
+            "Request matched route rule"
+        );
+        if rule.to == ROUTE_ANTHROPIC {
+            root_span.record("resolved_model", model);
+            RouteTarget::Anthropic
+        } else {
+            root_span.record("resolved_model", rule.to.as_str());
+            snapshot.model_registry.resolve(&rule.to, &state.targets)
+        }
+    } else {
+        let (resolved, alias) = snapshot.model_registry.canonical_model(model);
+        let root_span = tracing::Span::current();
+        root_span.record("resolved_model", resolved);
+        if let Some(rule) = alias {
+            root_span.record("matched_alias", rule.describe());
+            tracing::debug!(
+                original_model = %model,
+                resolved_model = %resolved,
+                alias = rule.describe(),
+                "Model matched alias rule"
+            );
+        }
+        snapshot.model_registry.resolve(model, &state.targets)
+    }
+}
+
+/// Check a local route against its model's `context_window`. Overflowing
+/// requests go to the model's `overflow_to` target when that has room;
+/// otherwise they are rejected with "prompt is too long". A request
+/// rerouted to Anthropic comes back with the model to send it as.
+fn enforce_context_window(
+    state: &AppState,
+    snapshot: &Snapshot,
+    route: RouteTarget,
+    req: Option<&serde_json::Value>,
+) -> Result<(RouteTarget, Option<String>), ContextOverflow> {
+    let Some(req) = req else {
+        return Ok((route, None));
+    };
+    let RouteTarget::Local { model_def, .. } = &route else {
+        return Ok((route, None));
+    };
+    let default_max_tokens = snapshot.config.target.max_tokens;
+    let Err(overflow) = context::check(req, default_max_tokens, model_def) else {
+        return Ok((route, None));
+    };
+
+    let root_span = tracing::Span::current();
+    let rerouted = match model_def.overflow_hop() {
+        Some(FallbackHop::Anthropic { model: Some(model) }) => {
+            Some((RouteTarget::Anthropic, Some(model)))
+        }
+        Some(FallbackHop::Local(to)) => {
+            match snapshot.model_registry.resolve(&to, &state.targets) {
+                RouteTarget::Local {
+                    model_def: larger, ..
+                } if context::check(req, default_max_tokens, &larger).is_err() => None,
+                local @ RouteTarget::Local { .. } => Some((local, None)),
+                RouteTarget::Anthropic => None,
             }
         }
+        // Rejected by config validation
+        Some(FallbackHop::Anthropic { model: None }) | None => None,
+    };
+
+    match rerouted {
+        Some(target) => {
+            let to = model_def.overflow_to.as_deref().unwrap_or_default();
+            tracing::info!(
+                model = %model_def.id,
+                overflow_to = to,
+                input_tokens = overflow.input_tokens,
+                max_tokens = overflow.max_tokens,
+                context_window = overflow.context_window,
+                "Request overflows context window, rerouting"
+            );
+            root_span.record("context_overflow", format!("rerouted:{to}").as_str());
+            root_span.record("resolved_model", to);
+            Ok(target)
+        }
+        None => {
+            tracing::warn!(
+                model = %model_def.id,
+                input_tokens = overflow.input_tokens,
+                max_tokens = overflow.max_tokens,
+                context_window = overflow.context_window,
+                "Request overflows context window, rejecting"
+            );
+            root_span.record("context_overflow", "rejected");
+            Err(overflow)
+        }
+    }
+}
 
-        // Resolve routing target from model name
-        let route = state.model_registry.resolve(&model);
+/// One upstream in a local model's fallback chain.
+enum Hop {
+    Local {
+        model_def: Arc<ModelDef>,
+        target_url: String,
+        lease: EndpointLease,
+        /// Model field to write into the body when the request reached this
+        /// model via an alias or fallback. Otherwise the `--model` override
+        /// (if any) applies.
+        rewrite_model: Option<String>,
+    },
+    Anthropic {
+        rewrite_model: Option<String>,
+    },
+}
 
-        match route {
-            RouteTarget::Local { target_url, .. } => {
-                // Build rewritten body for local target (apply model override + target defaults)
-                let target_body = match apply_local_defaults(
-                    &body,
-                    state.config.model_override.as_deref(),
-                    &state.config.target,
+/// Forward a local-model request, walking the model's `fallback` chain when a
+/// hop fails before any response bytes were streamed (connect error, timeout
+/// or 5xx). Records the hop that finally served it on the root span.
+#[allow(clippy::too_many_arguments)]
+async fn forward_local_with_fallback(
+    state: &AppState,
+    snapshot: &Snapshot,
+    headers: &HeaderMap,
+    body: &Bytes,
+    model: &str,
+    model_def: Arc<ModelDef>,
+    target_url: String,
+    lease: EndpointLease,
+    correlation_id: &str,
+    is_streaming: bool,
+) -> Response {
+    let root_span = tracing::Span::current();
+    let mut fallbacks = model_def.fallback_hops().into_iter();
+    // Requests that reached this model through an alias carry a different
+    // model ID; the target must see its own.
+    let rewrite_model = (model_def.id != model).then(|| model_def.id.clone());
+    let mut hop = Hop::Local {
+        model_def,
+        target_url,
+        lease,
+        rewrite_model,
+    };
+    let mut depth: u64 = 0;
+
+    loop {
+        // A target marked down by health checks is skipped without a request
+        // when a routable hop follows; otherwise it is tried anyway.
+        if let Hop::Local {
+            model_def,
+            target_url,
+            ..
+        } = &hop
+        {
+            if state.targets.is_down(target_url) {
+                if let Some(next_hop) = next_fallback(state, snapshot, &mut fallbacks) {
+                    tracing::warn!(
+                        model = %model_def.id,
+                        target_url = %target_url,
+                        fallback_depth = depth + 1,
+                        "Target is marked down, skipping to fallback"
+                    );
+                    hop = next_hop;
+                    depth += 1;
+                    continue;
+                }
+            }
+        }
+
+        let (served_by, result) = match hop {
+            Hop::Local {
+                model_def,
+                target_url,
+                lease,
+                rewrite_model,
+            } => {
+                let new_model = rewrite_model
+                    .as_deref()
+                    .or(snapshot.config.model_override.as_deref());
+                // Build rewritten body for local target (apply model override,
+                // target defaults and the model's capability profile)
+                let (target_body, mut findings) = match apply_local_defaults(
+                    body,
+                    new_model,
+                    &snapshot.config.target,
+                    Some(&model_def),
                 ) {
                     Ok(rewritten) => rewritten,
                     Err(e) => {
                         tracing::warn!(error = %e, "Failed to rewrite target body, forwarding unchanged");
-                        body.clone()
+                        (body.clone(), Vec::new())
                     }
                 };
+                let target_headers = capabilities::strip_unsupported_headers(
+                    headers,
+                    &model_def.capabilities,
+                    &mut findings,
+                );
+                capabilities::emit(&root_span, &model_def.id, &findings);
+                let auth = model_auth_headers(snapshot, &model_def);
+                let target_body = match model_def.protocol {
+                    TargetProtocol::Anthropic => target_body,
+                    TargetProtocol::Openai => to_openai_body(&target_body).unwrap_or_else(|e| {
+                        tracing::warn!(error = %e, "Failed to convert request to OpenAI format, forwarding unchanged");
+                        target_body
+                    }),
+                };
 
                 tracing::info!(
-                    model = %model,
+                    model = %model_def.id,
                     target_url = %target_url,
+                    fallback_depth = depth,
                     "Routing to local model"
                 );
 
-                primary::forward_to_target(
+                let result = primary::forward_to_target(
                     &state.primary_client,
                     &target_url,
-                    &headers,
+                    lease,
+                    &state.breakers,
+                    &snapshot.config.retry,
+                    &target_headers,
+                    &auth,
+                    model_def.protocol,
                     target_body,
-                    &correlation_id,
+                    correlation_id,
                     is_streaming,
-                    tracing::Span::current(),
+                    root_span.clone(),
                     state.stats.clone(),
                 )
-                .await
+                .await;
+                (format!("local:{}", model_def.id), result)
             }
-            RouteTarget::Anthropic => {
-                // In compare mode, also fire-and-forget to the default target
-                if current_mode == ProxyMode::Compare {
-                    let target_body = match apply_local_defaults(
-                        &body,
-                        state.config.model_override.as_deref(),
-                        &state.config.target,
-                    ) {
-                        Ok(rewritten) => rewritten,
-                        Err(e) => {
-                            tracing::warn!(error = %e, "Failed to rewrite target body for compare");
-                            body.clone()
-                        }
-                    };
-                    state
-                        .compare_dispatcher
-                        .dispatch(target_body, correlation_id.clone());
-                }
-
-                // Forward original unmodified body to Anthropic
-                let url = format!("{}/v1/messages", state.config.passthrough.url);
+            Hop::Anthropic { rewrite_model } => {
+                let anthropic_body = match rewrite_model {
+                    Some(ref m) => rewrite_model_field(body, m).unwrap_or_else(|e| {
+                        tracing::warn!(error = %e, "Failed to rewrite model for Anthropic fallback");
+                        body.clone()
+                    }),
+                    None => body.clone(),
+                };
+                let url = format!("{}/v1/messages", snapshot.config.passthrough.url);
 
                 tracing::info!(
-                    model = %model,
+                    model = rewrite_model.as_deref().unwrap_or(model),
+                    fallback_depth = depth,
                     "Routing to Anthropic"
                 );
 
-                let root_span = tracing::Span::current();
-                primary::forward_to_anthropic(
+                let result = primary::forward_to_anthropic(
                     &state.primary_client,
                     &url,
-                    &headers,
-                    body,
-                    &correlation_id,
+                    &snapshot.config.retry,
+                    headers,
+                    anthropic_body,
+                    correlation_id,
                     is_streaming,
-                    root_span,
+                    root_span.clone(),
                     state.stats.clone(),
+                    None,
                 )
-                .await
+                .await;
+                ("anthropic".to_string(), result)
+            }
+        };
+
+        let failed = match result {
+            Ok(response) => {
+                root_span.record("served_by", served_by.as_str());
+                root_span.record("fallback_depth", depth);
+                return response;
+            }
+            Err(failed) => failed,
+        };
+
+        // Give up with the last failure if no usable hop remains.
+        match next_fallback(state, snapshot, &mut fallbacks) {
+            Some(next_hop) => {
+                tracing::warn!(
+                    failed = %served_by,
+                    reason = %failed.reason,
+                    fallback_depth = depth + 1,
+                    "Upstream failed before streaming, trying fallback"
+                );
+                hop = next_hop;
+                depth += 1;
+            }
+            None => {
+                root_span.record("served_by", served_by.as_str());
+                root_span.record("fallback_depth", depth);
+                return failed.into_response();
             }
         }
     }
-    .instrument(span)
-    .await
+}
+
+/// Resolve the next usable hop of a fallback chain, skipping local models
+/// that are no longer routable.
+fn next_fallback(
+    state: &AppState,
+    snapshot: &Snapshot,
+    fallbacks: &mut impl Iterator<Item = FallbackHop>,
+) -> Option<Hop> {
+    fallbacks.find_map(|candidate| match candidate {
+        FallbackHop::Local(id) => match snapshot.model_registry.resolve(&id, &state.targets) {
+            RouteTarget::Local {
+                model_def,
+                target_url,
+                lease,
+            } => Some(Hop::Local {
+                model_def,
+                target_url,
+                lease,
+                rewrite_model: Some(id),
+            }),
+            RouteTarget::Anthropic => {
+                tracing::warn!(fallback = %id, "Fallback model is not a routable local model, skipping");
+                None
+            }
+        },
+        FallbackHop::Anthropic { model } => Some(Hop::Anthropic {
+            rewrite_model: model,
+        }),
+    })
 }
 
 /// Apply model override and target config defaults to a request body.
 ///
 /// - Replaces `model` with `new_model` (if Some)
 /// - Sets `max_tokens`, `temperature`, `top_p` from target config defaults (if absent in request)
+/// - With a `model_def`: applies its `[models.params]` defaults (ahead of the
+///   target defaults) and overrides, clamps `max_tokens` to its
+///   `max_output_tokens` and strips features its capability profile marks
+///   unsupported, returning what changed
 ///
 /// Only used for local-model-bound traffic. Anthropic passthrough uses the original body.
 fn apply_local_defaults(
     body: &Bytes,
     new_model: Option<&str>,
     target: &TargetConfig,
-) -> Result<Bytes, serde_json::Error> {
+    model_def: Option<&ModelDef>,
+) -> Result<(Bytes, Vec<ValidationFinding>), serde_json::Error> {
     let mut value: serde_json::Value = serde_json::from_slice(body)?;
     if let Some(obj) = value.as_object_mut() {
         if let Some(model) = new_model {
@@ -242,6 +1358,9 @@ fn apply_local_defaults(
                 serde_json::Value::String(model.to_string()),
             );
         }
+        if let Some(model_def) = model_def {
+            model_def.params.apply_defaults(obj);
+        }
         if let Some(max_tokens) = target.max_tokens {
             if !obj.contains_key("max_tokens") || obj.get("max_tokens").is_some_and(|v| v.is_null())
             {
@@ -261,9 +1380,195 @@ fn apply_local_defaults(
                 obj.insert("top_p".to_string(), serde_json::json!(top_p));
             }
         }
+        if let Some(model_def) = model_def {
+            model_def.params.apply_overrides(obj);
+        }
+    }
+    let mut findings = Vec::new();
+    if let Some(model_def) = model_def {
+        if let Some(requested) = context::clamp_max_tokens(&mut value, model_def) {
+            tracing::debug!(
+                model = %model_def.id,
+                requested,
+                max_output_tokens = model_def.max_output_tokens,
+                "Clamped max_tokens to model limit"
+            );
+        }
+        findings = capabilities::strip_unsupported(&mut value, &model_def.capabilities);
     }
     let rewritten = serde_json::to_vec(&value)?;
-    Ok(Bytes::from(rewritten))
+    Ok((Bytes::from(rewritten), findings))
+}
+
+/// Convert an (already rewritten) Anthropic request body to OpenAI chat
+/// completions format for `protocol = "openai"` targets.
+fn to_openai_body(body: &Bytes) -> anyhow::Result<Bytes> {
+    let value: serde_json::Value = serde_json::from_slice(body)?;
+    let converted = crate::convert::anthropic_to_openai::anthropic_to_openai(&value)?;
+    Ok(Bytes::from(serde_json::to_vec(&converted)?))
+}
+
+/// A model's upstream credentials, as read when the snapshot was built. A
+/// secret that could not be read is logged and the request goes out without
+/// credentials.
+fn model_auth_headers(snapshot: &Snapshot, model_def: &ModelDef) -> HeaderMap {
+    snapshot.model_registry.auth(&model_def.id).unwrap_or_else(|e| {
+        tracing::warn!(model = %model_def.id, error = %e, "Failed to resolve model credentials");
+        HeaderMap::new()
+    })
+}
+
+/// Where compare mode mirrors a request for `model`: each configured
+/// candidate with its own body, credentials and limits, or the default target
+/// when there are none. Candidates that no longer resolve to a local model
+/// are skipped.
+fn compare_targets(
+    state: &AppState,
+    snapshot: &Snapshot,
+    body: &Bytes,
+    model: &str,
+) -> Vec<CompareTarget> {
+    let config = &snapshot.config;
+    if config.compare.candidates.is_empty() {
+        let target_body = match apply_local_defaults(
+            body,
+            config.model_override.as_deref(),
+            &config.target,
+            None,
+        ) {
+            Ok((rewritten, _)) => rewritten,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to rewrite target body for compare");
+                body.clone()
+            }
+        };
+        let auth = config
+            .model_override
+            .as_deref()
+            .and_then(|m| snapshot.model_registry.get(m))
+            .map(|m| model_auth_headers(snapshot, m))
+            .unwrap_or_default();
+        let target_model = config.model_override.as_deref().unwrap_or(model);
+        return vec![state.compare_dispatcher.default_target(
+            config.target.url.clone().unwrap_or_default(),
+            target_model.to_string(),
+            target_body,
+            auth,
+        )];
+    }
+
+    let mut targets = Vec::new();
+    for candidate in &config.compare.candidates {
+        // The endpoint is picked once the request leaves its compare queue
+        let (model_id, _) = snapshot.model_registry.canonical_model(&candidate.model);
+        let Some(model_def) = snapshot
+            .model_registry
+            .get(model_id)
+            .filter(|_| snapshot.model_registry.is_local(model_id))
+        else {
+            tracing::warn!(model = %candidate.model, "Compare candidate is not a local model, skipping");
+            continue;
+        };
+        let target_body = match apply_local_defaults(
+            body,
+            Some(&model_def.id),
+            &config.target,
+            Some(model_def),
+        ) {
+            Ok((rewritten, _)) => rewritten,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to rewrite target body for compare");
+                continue;
+            }
+        };
+        targets.push(CompareTarget {
+            model: model_def.id.clone(),
+            endpoint: CompareEndpoint::Local {
+                registry: snapshot.model_registry.clone(),
+                targets: state.targets.clone(),
+            },
+            protocol: model_def.protocol,
+            body: target_body,
+            auth: model_auth_headers(snapshot, model_def),
+            timeout: std::time::Duration::from_secs(
+                candidate.timeout_secs.unwrap_or(config.target.timeout_secs),
+            ),
+            max_concurrent: candidate
+                .max_concurrent
+                .unwrap_or(config.target.max_concurrent),
+        });
+    }
+    targets
+}
+
+/// How to judge a mirrored request's pairs, when `[compare.judge]` is set
+/// and samples the request. A local judge model uses its own endpoint and
+/// credentials; any other model is sent to Anthropic with the judge's `auth`.
+fn judge_request(
+    state: &AppState,
+    snapshot: &Snapshot,
+    request: Option<&serde_json::Value>,
+) -> Option<Arc<JudgeRequest>> {
+    let compare = &snapshot.config.compare;
+    let judge = compare.judge.as_ref()?;
+    let request = request?;
+    if !compare_policy::sampled(judge.sample_percent) {
+        return None;
+    }
+    let (model, target_url, protocol, auth) = match snapshot
+        .model_registry
+        .resolve(&judge.model, &state.targets)
+    {
+        // The judge runs once both responses are in, so the endpoint pick is
+        // not held as a lease until then
+        RouteTarget::Local {
+            model_def,
+            target_url,
+            lease: _,
+        } => (
+            model_def.id.clone(),
+            target_url,
+            model_def.protocol,
+            model_auth_headers(snapshot, &model_def),
+        ),
+        RouteTarget::Anthropic => {
+            let auth = snapshot.judge_auth.clone().unwrap_or_else(|e| {
+                tracing::warn!(error = %e, "Failed to resolve judge credentials");
+                HeaderMap::new()
+            });
+            (
+                judge.model.clone(),
+                snapshot.config.passthrough.url.clone(),
+                TargetProtocol::Anthropic,
+                auth,
+            )
+        }
+    };
+    Some(Arc::new(JudgeRequest {
+        model,
+        target_url,
+        protocol,
+        auth,
+        timeout: std::time::Duration::from_secs(judge.timeout_secs),
+        max_concurrent: judge.max_concurrent,
+        queue_depth: compare.queue_depth,
+        queue_max_wait: std::time::Duration::from_secs(compare.queue_max_wait_secs),
+        max_tokens: judge.max_tokens,
+        rubric: judge.rubric.clone(),
+        context: judge::render_context(request, judge.max_context_chars),
+    }))
+}
+
+/// Replace only the `model` field of a request body.
+fn rewrite_model_field(body: &Bytes, model: &str) -> Result<Bytes, serde_json::Error> {
+    let mut value: serde_json::Value = serde_json::from_slice(body)?;
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert(
+            "model".to_string(),
+            serde_json::Value::String(model.to_string()),
+        );
+    }
+    Ok(Bytes::from(serde_json::to_vec(&value)?))
 }
 
 /// GET /v1/models — list available models.
@@ -271,7 +1576,8 @@ fn apply_local_defaults(
 /// Returns locally-registered models from the ModelRegistry. Merges with
 /// Anthropic's model list when passthrough is available (best-effort, 5s timeout).
 async fn handle_list_models(State(state): State<Arc<AppState>>) -> Response {
-    let local_models = state.model_registry.list_models();
+    let snapshot = state.live.current();
+    let local_models = snapshot.model_registry.list_models();
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap_or_default()
@@ -297,7 +1603,7 @@ async fn handle_list_models(State(state): State<Arc<AppState>>) -> Response {
         .collect();
 
     // Best-effort: merge Anthropic's model list (5s timeout)
-    if let Ok(anthropic_models) = fetch_anthropic_models(&state).await {
+    if let Ok(anthropic_models) = fetch_anthropic_models(&snapshot.config.passthrough.url).await {
         data.extend(anthropic_models);
     }
 
@@ -318,8 +1624,10 @@ async fn handle_get_model(
     State(state): State<Arc<AppState>>,
     Path(model_id): Path<String>,
 ) -> Response {
+    let snapshot = state.live.current();
+
     // Check local registry first
-    let local_models = state.model_registry.list_models();
+    let local_models = snapshot.model_registry.list_models();
     if let Some(m) = local_models.iter().find(|m| m.id == model_id) {
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -341,7 +1649,7 @@ async fn handle_get_model(
     }
 
     // Try Anthropic
-    let url = format!("{}/v1/models/{}", state.config.passthrough.url, model_id);
+    let url = format!("{}/v1/models/{}", snapshot.config.passthrough.url, model_id);
     let client = reqwest::Client::builder()
         .timeout(std::time::Duration::from_secs(5))
         .build()
@@ -366,8 +1674,8 @@ async fn handle_get_model(
 }
 
 /// Fetch Anthropic's model list (best-effort, 5s timeout).
-async fn fetch_anthropic_models(state: &AppState) -> Result<Vec<serde_json::Value>, ()> {
-    let url = format!("{}/v1/models", state.config.passthrough.url);
+async fn fetch_anthropic_models(passthrough_url: &str) -> Result<Vec<serde_json::Value>, ()> {
+    let url = format!("{}/v1/models", passthrough_url);
     let client = reqwest::Client::builder()
         .timeout(std::time::Duration::from_secs(5))
         .build()
@@ -397,6 +1705,7 @@ async fn handle_fallback(State(state): State<Arc<AppState>>, request: Request) -
     }
 
     let correlation_id = correlation::generate_id();
+    let snapshot = state.live.current();
 
     let method = request.method().clone();
     let path = request.uri().path().to_string();
@@ -405,7 +1714,7 @@ async fn handle_fallback(State(state): State<Arc<AppState>>, request: Request) -
         .query()
         .map(|q| format!("?{q}"))
         .unwrap_or_default();
-    let url = format!("{}{path}{query}", state.config.passthrough.url);
+    let url = format!("{}{path}{query}", snapshot.config.passthrough.url);
 
     let headers = request.headers().clone();
     let body = match axum::body::to_bytes(request.into_body(), 10 * 1024 * 1024).await {
@@ -427,9 +1736,83 @@ async fn handle_fallback(State(state): State<Arc<AppState>>, request: Request) -
     .await
 }
 
-/// GET /api/stats — return current proxy statistics.
+/// GET /api/stats — return current proxy statistics, including circuit
+/// breaker state per target URL.
 async fn handle_get_stats(State(state): State<Arc<AppState>>) -> Response {
-    axum::Json(state.stats.snapshot()).into_response()
+    let mut body = serde_json::to_value(state.stats.snapshot()).unwrap_or_default();
+    body["circuit_breakers"] = serde_json::json!(state.breakers.snapshot());
+    axum::Json(body).into_response()
+}
+
+/// Paging of GET /api/compare.
+#[derive(Deserialize)]
+struct ListComparesQuery {
+    #[serde(default = "default_compare_list_limit")]
+    limit: usize,
+}
+
+fn default_compare_list_limit() -> usize {
+    100
+}
+
+/// GET /api/compare — stored compare records, newest first, filtered by
+/// `model`, `since` / `until` (unix seconds), `mismatch` and `matched`.
+async fn handle_list_compares(
+    State(state): State<Arc<AppState>>,
+    Query(filter): Query<CompareFilter>,
+    Query(page): Query<ListComparesQuery>,
+) -> Response {
+    // The store is read from disk; keep that off the async workers
+    let store = state.compares.clone();
+    let limit = page.limit.clamp(1, 1000);
+    let queried = tokio::task::spawn_blocking(move || store.query(&filter, limit))
+        .await
+        .unwrap_or_else(|e| Err(std::io::Error::other(e)));
+    match queried {
+        Ok((data, has_more)) => axum::Json(serde_json::json!({
+            "has_more": has_more,
+            "data": data,
+        }))
+        .into_response(),
+        Err(e) => compare_store_error(e),
+    }
+}
+
+/// GET /api/compare/summary — match rates of the stored compare records,
+/// overall and per compare model, with the same filters as /api/compare.
+async fn handle_compare_summary(
+    State(state): State<Arc<AppState>>,
+    Query(filter): Query<CompareFilter>,
+) -> Response {
+    let store = state.compares.clone();
+    let summarized = tokio::task::spawn_blocking(move || store.summary(&filter))
+        .await
+        .unwrap_or_else(|e| Err(std::io::Error::other(e)));
+    match summarized {
+        Ok(summary) => axum::Json(summary).into_response(),
+        Err(e) => compare_store_error(e),
+    }
+}
+
+/// GET /api/compare/stats — rolling per-model aggregates of recent compare
+/// pairs: latency percentiles, throughput and error rate of each side, and
+/// tool-call agreement.
+async fn handle_compare_stats(State(state): State<Arc<AppState>>) -> Response {
+    axum::Json(serde_json::json!({
+        "window": state.compare_stats.window(),
+        "models": state.compare_stats.snapshot(),
+        "queues": state.compare_dispatcher.queue_stats(),
+        "judge_queues": state.compare_dispatcher.judge_queue_stats(),
+    }))
+    .into_response()
+}
+
+fn compare_store_error(e: std::io::Error) -> Response {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        axum::Json(serde_json::json!({ "error": format!("failed to read compare store: {e}") })),
+    )
+        .into_response()
 }
 
 /// GET /api/mode — return the current proxy operating mode.
@@ -468,7 +1851,7 @@ async fn handle_set_mode(
         };
 
     // Block anthropic-only mode unless explicitly allowed at launch
-    if mode == ProxyMode::AnthropicOnly && !state.config.anthropic_only_allowed {
+    if mode == ProxyMode::AnthropicOnly && !state.live.current().config.anthropic_only_allowed {
         return (
             StatusCode::FORBIDDEN,
             axum::Json(serde_json::json!({
@@ -483,6 +1866,145 @@ async fn handle_set_mode(
     axum::Json(serde_json::json!({ "mode": mode })).into_response()
 }
 
+/// POST /api/config/reload — re-read the config file and env, validate, and
+/// swap in the new config and model registry.
+///
+/// Returns what changed (and what needs a restart), or 422 with the
+/// validation errors if the new config was rejected.
+async fn handle_reload_config(State(state): State<Arc<AppState>>) -> Response {
+    match state.live.reload() {
+        Ok(report) => {
+            tracing::info!(
+                changes = ?report.changes,
+                restart_required = ?report.restart_required,
+                "Configuration reloaded via API"
+            );
+            axum::Json(serde_json::json!({
+                "status": "reloaded",
+                "changes": report.changes,
+                "restart_required": report.restart_required,
+            }))
+            .into_response()
+        }
+        Err(e) => {
+            tracing::warn!(errors = ?e.errors, "Configuration reload via API rejected");
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                axum::Json(serde_json::json!({
+                    "status": "invalid",
+                    "errors": e.errors,
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// POST /api/models — register a new local model.
+///
+/// The body is a `[[models]]` entry as JSON. Returns 201 with the stored
+/// definition; routing and `/v1/models` see it immediately.
+async fn handle_create_model(
+    State(state): State<Arc<AppState>>,
+    axum::Json(payload): axum::Json<serde_json::Value>,
+) -> Response {
+    let def: ModelDef = match serde_json::from_value(payload) {
+        Ok(def) => def,
+        Err(e) => return model_change_error(ModelChangeError::Invalid(vec![e.to_string()])),
+    };
+    match state.live.add_model(def) {
+        Ok(def) => {
+            tracing::info!(model = %def.id, "Model added via API");
+            (StatusCode::CREATED, axum::Json(def)).into_response()
+        }
+        Err(e) => model_change_error(e),
+    }
+}
+
+/// PATCH /api/models/:model_id — change fields of a local model.
+///
+/// Fields in the body replace the stored ones; `null` clears a field.
+async fn handle_update_model(
+    State(state): State<Arc<AppState>>,
+    Path(model_id): Path<String>,
+    axum::Json(payload): axum::Json<serde_json::Value>,
+) -> Response {
+    match state.live.update_model(&model_id, &payload) {
+        Ok(def) => {
+            tracing::info!(model = %def.id, "Model updated via API");
+            axum::Json(def).into_response()
+        }
+        Err(e) => model_change_error(e),
+    }
+}
+
+/// DELETE /api/models/:model_id — remove a local model.
+async fn handle_delete_model(
+    State(state): State<Arc<AppState>>,
+    Path(model_id): Path<String>,
+) -> Response {
+    match state.live.remove_model(&model_id) {
+        Ok(()) => {
+            tracing::info!(model = %model_id, "Model removed via API");
+            axum::Json(serde_json::json!({ "id": model_id, "deleted": true })).into_response()
+        }
+        Err(e) => model_change_error(e),
+    }
+}
+
+fn model_change_error(e: ModelChangeError) -> Response {
+    let (status, body) = match e {
+        ModelChangeError::NotFound(id) => (
+            StatusCode::NOT_FOUND,
+            serde_json::json!({ "error": format!("Model '{id}' not found") }),
+        ),
+        ModelChangeError::AlreadyExists(id) => (
+            StatusCode::CONFLICT,
+            serde_json::json!({ "error": format!("Model '{id}' already exists") }),
+        ),
+        ModelChangeError::Invalid(errors) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            serde_json::json!({ "status": "invalid", "errors": errors }),
+        ),
+        ModelChangeError::Persist(e) => {
+            tracing::error!(error = %e, "Failed to persist model change");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                serde_json::json!({ "error": format!("failed to write models state file: {e}") }),
+            )
+        }
+    };
+    (status, axum::Json(body)).into_response()
+}
+
+/// GET /api/targets — health of every distinct target URL in the registry.
+async fn handle_get_targets(State(state): State<Arc<AppState>>) -> Response {
+    let snapshot = state.live.current();
+    let checks_enabled = snapshot
+        .config
+        .health_check
+        .interval_secs
+        .is_some_and(|s| s > 0);
+    let targets: Vec<serde_json::Value> = snapshot
+        .model_registry
+        .targets()
+        .into_iter()
+        .map(|(url, models)| {
+            let mut obj = serde_json::to_value(state.targets.state(&url)).unwrap_or_default();
+            obj["url"] = serde_json::json!(url);
+            obj["models"] = serde_json::json!(models);
+            obj["circuit"] = serde_json::json!(state.breakers.state(&url));
+            obj
+        })
+        .collect();
+
+    axum::Json(serde_json::json!({
+        "health_checks_enabled": checks_enabled,
+        "targets": targets,
+    }))
+    .into_response()
+}
+
 /// GET /api/tracing — return whether trace logging is enabled.
 async fn handle_get_tracing(State(state): State<Arc<AppState>>) -> Response {
     let enabled = state.tracing_enabled.load(Ordering::Relaxed);
@@ -522,3 +2044,490 @@ async fn shutdown_signal() {
         .expect("failed to install CTRL+C signal handler");
     tracing::info!("Shutdown signal received, draining connections...");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::primary::tests::spawn_app;
+    use crate::reload::CliOverrides;
+    use std::sync::Mutex;
+
+    /// Serve the proxy on a free local port, with Anthropic at
+    /// `passthrough_url` and `config` appended to the config file. Returns
+    /// its base URL and state.
+    async fn spawn_proxy(passthrough_url: &str, config: &str) -> (String, Arc<AppState>) {
+        let dir = std::env::temp_dir().join(format!("cc-proxy-server-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cc-proxy.toml");
+        std::fs::write(
+            &path,
+            format!(
+                r#"
+[server]
+[passthrough]
+url = "{passthrough_url}"
+[target]
+[tracing]
+[compare]
+store_file = "{store}"
+[batches]
+dir = "{batches}"
+{config}
+"#,
+                store = dir.join("compares.jsonl").display(),
+                batches = dir.join("batches").display(),
+            ),
+        )
+        .unwrap();
+        let live = LiveConfig::load(path.to_str().unwrap(), CliOverrides::default()).unwrap();
+        let snapshot = live.current();
+        let config = &snapshot.config;
+        let breakers = Arc::new(CircuitBreakers::new(config.circuit_breaker.clone()));
+        let compares = Arc::new(CompareStore::open(&config.compare.store_file).unwrap());
+        let compare_stats = Arc::new(CompareStats::new(config.compare.stats_window));
+        let state = Arc::new(AppState {
+            live: live.clone(),
+            primary_client: reqwest::Client::new(),
+            compare_dispatcher: CompareDispatcher::new(
+                config.target.timeout_secs,
+                config.target.max_concurrent,
+                reqwest::Client::new(),
+                breakers.clone(),
+                Some(compares.clone()),
+                compare_stats.clone(),
+            ),
+            stats: ProxyStats::new(),
+            mode: RuntimeMode::new(ProxyMode::TargetOnly),
+            tracing_enabled: Arc::new(AtomicBool::new(true)),
+            targets: Arc::new(TargetHealth::new()),
+            breakers,
+            tokenizers: Arc::new(TokenizerCache::default()),
+            batches: Arc::new(
+                BatchStore::open(
+                    &config.batches.dir,
+                    config.batches.max_concurrent_per_target,
+                )
+                .unwrap(),
+            ),
+            compares,
+            compare_stats,
+        });
+        (spawn_app(router(state.clone())).await, state)
+    }
+
+    /// Start a mock upstream whose `/v1/messages` answers with a minimal
+    /// message and records every request body it receives.
+    async fn recording_upstream() -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = received.clone();
+        let app = Router::new().route(
+            "/v1/messages",
+            post(move |axum::Json(body): axum::Json<serde_json::Value>| {
+                let log = log.clone();
+                async move {
+                    log.lock().unwrap().push(body);
+                    axum::Json(serde_json::json!({
+                        "id": "msg_1",
+                        "type": "message",
+                        "role": "assistant",
+                        "model": "upstream",
+                        "content": [{"type": "text", "text": "hi"}],
+                        "stop_reason": "end_turn",
+                        "usage": {"input_tokens": 1, "output_tokens": 1}
+                    }))
+                }
+            }),
+        );
+        (spawn_app(app).await, received)
+    }
+
+    /// Start a mock upstream whose `/v1/messages` always fails with a 500
+    /// that still reports token usage.
+    async fn failing_upstream() -> String {
+        let app = Router::new().route(
+            "/v1/messages",
+            post(|| async {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    axum::Json(serde_json::json!({
+                        "type": "error",
+                        "error": {"type": "api_error", "message": "boom"},
+                        "usage": {"input_tokens": 100, "output_tokens": 100}
+                    })),
+                )
+            }),
+        );
+        spawn_app(app).await
+    }
+
+    #[tokio::test]
+    async fn failed_hops_fall_back_to_the_next_model() {
+        let (backup, received) = recording_upstream().await;
+        let failing = failing_upstream().await;
+        let (proxy, state) = spawn_proxy(
+            "http://127.0.0.1:1",
+            &format!(
+                r#"
+[[models]]
+id = "errors"
+target_url = "{failing}"
+fallback = ["refused", "backup"]
+
+[[models]]
+id = "refused"
+target_url = "http://127.0.0.1:1"
+
+[[models]]
+id = "backup"
+target_url = "{backup}"
+"#
+            ),
+        )
+        .await;
+
+        let response = reqwest::Client::new()
+            .post(format!("{proxy}/v1/messages"))
+            .json(&serde_json::json!({
+                "model": "errors",
+                "max_tokens": 10,
+                "messages": [{"role": "user", "content": "hi"}]
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["content"][0]["text"], "hi");
+        {
+            let received = received.lock().unwrap();
+            assert_eq!(received.len(), 1);
+            assert_eq!(received[0]["model"], "backup");
+        }
+
+        // Only the served response is counted
+        for _ in 0..50 {
+            if state.stats.snapshot().input_tokens > 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(state.stats.snapshot().input_tokens, 1);
+    }
+
+    #[tokio::test]
+    async fn overflow_to_anthropic_forwards_the_named_model() {
+        let (anthropic, received) = recording_upstream().await;
+        let (proxy, state) = spawn_proxy(
+            &anthropic,
+            r#"
+[[models]]
+id = "small"
+target_url = "http://127.0.0.1:1"
+context_window = 100
+overflow_to = "anthropic:claude-sonnet-4-5"
+"#,
+        )
+        .await;
+        state.mode.set(ProxyMode::Compare);
+
+        let response = reqwest::Client::new()
+            .post(format!("{proxy}/v1/messages"))
+            .json(&serde_json::json!({
+                "model": "small",
+                "max_tokens": 50,
+                "messages": [{"role": "user", "content": "x".repeat(1000)}]
+            }))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0]["model"], "claude-sonnet-4-5");
+        assert_eq!(received[0]["max_tokens"], 50);
+    }
+
+    #[tokio::test]
+    async fn marked_down_target_is_tried_without_a_routable_fallback() {
+        let (target, received) = recording_upstream().await;
+        let (proxy, state) = spawn_proxy(
+            "http://127.0.0.1:1",
+            &format!(
+                r#"
+[[models]]
+id = "local"
+target_url = "{target}"
+fallback = ["no-url"]
+
+[[models]]
+id = "no-url"
+"#
+            ),
+        )
+        .await;
+        let health = crate::config::HealthCheckConfig {
+            unhealthy_threshold: 1,
+            ..Default::default()
+        };
+        state
+            .targets
+            .record_probe(&target, Err("refused".to_string()), &health);
+        assert!(state.targets.is_down(&target));
+
+        let response = reqwest::Client::new()
+            .post(format!("{proxy}/v1/messages"))
+            .json(&serde_json::json!({
+                "model": "local",
+                "max_tokens": 10,
+                "messages": [{"role": "user", "content": "hi"}]
+            }))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    /// Start a mock upstream whose `/v1/messages` always answers `status`
+    /// with `body` as `content_type`.
+    async fn canned_upstream(
+        status: StatusCode,
+        content_type: &'static str,
+        body: &'static str,
+    ) -> String {
+        let app = Router::new().route(
+            "/v1/messages",
+            post(move || async move { (status, [(CONTENT_TYPE, content_type)], body) }),
+        );
+        spawn_app(app).await
+    }
+
+    async fn chat_completion(proxy: &str, request: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{proxy}/v1/chat/completions"))
+            .bearer_auth("sk-ant-api03-test")
+            .json(&request)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn chat_completions_round_trip() {
+        let (anthropic, received) = recording_upstream().await;
+        let (proxy, _state) = spawn_proxy(&anthropic, "").await;
+
+        let response = chat_completion(
+            &proxy,
+            serde_json::json!({
+                "model": "claude-sonnet-4-5",
+                "max_tokens": 10,
+                "messages": [
+                    {"role": "system", "content": "be brief"},
+                    {"role": "user", "content": "hello"}
+                ]
+            }),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["object"], "chat.completion");
+        assert_eq!(body["choices"][0]["message"]["role"], "assistant");
+        assert_eq!(body["choices"][0]["message"]["content"], "hi");
+        assert_eq!(body["choices"][0]["finish_reason"], "stop");
+        assert_eq!(body["usage"]["prompt_tokens"], 1);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received[0]["model"], "claude-sonnet-4-5");
+        assert_eq!(received[0]["system"], "be brief");
+        assert_eq!(received[0]["messages"][0]["role"], "user");
+    }
+
+    #[tokio::test]
+    async fn chat_completions_streams_chunks() {
+        let anthropic = canned_upstream(
+            StatusCode::OK,
+            "text/event-stream",
+            concat!(
+                "event: message_start\n",
+                r#"data: {"type":"message_start","message":{"id":"msg_1","type":"message","role":"assistant","model":"claude-sonnet-4-5","content":[],"stop_reason":null,"usage":{"input_tokens":3,"output_tokens":0}}}"#,
+                "\n\n",
+                "event: content_block_start\n",
+                r#"data: {"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+                "\n\n",
+                "event: content_block_delta\n",
+                r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"hel"}}"#,
+                "\n\n",
+                "event: content_block_delta\n",
+                r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"lo"}}"#,
+                "\n\n",
+                "event: content_block_stop\n",
+                r#"data: {"type":"content_block_stop","index":0}"#,
+                "\n\n",
+                "event: message_delta\n",
+                r#"data: {"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":2}}"#,
+                "\n\n",
+                "event: message_stop\n",
+                r#"data: {"type":"message_stop"}"#,
+                "\n\n",
+            ),
+        )
+        .await;
+        let (proxy, _state) = spawn_proxy(&anthropic, "").await;
+
+        let response = chat_completion(
+            &proxy,
+            serde_json::json!({
+                "model": "claude-sonnet-4-5",
+                "stream": true,
+                "stream_options": {"include_usage": true},
+                "messages": [{"role": "user", "content": "hello"}]
+            }),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/event-stream"));
+        let body = response.text().await.unwrap();
+        let events: Vec<&str> = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .collect();
+        assert_eq!(events.last(), Some(&"[DONE]"));
+        let chunks: Vec<serde_json::Value> = events[..events.len() - 1]
+            .iter()
+            .map(|e| serde_json::from_str(e).unwrap())
+            .collect();
+        assert!(chunks
+            .iter()
+            .all(|c| c["object"] == "chat.completion.chunk"));
+        let text: String = chunks
+            .iter()
+            .filter_map(|c| c["choices"][0]["delta"]["content"].as_str())
+            .collect();
+        assert_eq!(text, "hello");
+        assert!(chunks
+            .iter()
+            .any(|c| c["choices"][0]["finish_reason"] == "stop"));
+        let usage = &chunks.last().unwrap()["usage"];
+        assert_eq!(usage["prompt_tokens"], 3);
+        assert_eq!(usage["completion_tokens"], 2);
+    }
+
+    #[tokio::test]
+    async fn chat_completions_maps_upstream_errors() {
+        let anthropic = canned_upstream(
+            StatusCode::TOO_MANY_REQUESTS,
+            "application/json",
+            r#"{"type":"error","error":{"type":"rate_limit_error","message":"slow down"}}"#,
+        )
+        .await;
+        let (proxy, _state) = spawn_proxy(&anthropic, "").await;
+
+        let response = chat_completion(
+            &proxy,
+            serde_json::json!({
+                "model": "claude-sonnet-4-5",
+                "messages": [{"role": "user", "content": "hello"}]
+            }),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": {
+                    "message": "slow down",
+                    "type": "rate_limit_error",
+                    "param": null,
+                    "code": null
+                }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn local_batch_runs_and_serves_results() {
+        let (target, received) = recording_upstream().await;
+        let (proxy, _state) = spawn_proxy(
+            "http://127.0.0.1:1",
+            &format!(
+                r#"
+[[models]]
+id = "local"
+target_url = "{target}"
+"#
+            ),
+        )
+        .await;
+        let client = reqwest::Client::new();
+        let request = |custom_id: &str| {
+            serde_json::json!({
+                "custom_id": custom_id,
+                "params": {
+                    "model": "local",
+                    "max_tokens": 10,
+                    "messages": [{"role": "user", "content": "hi"}]
+                }
+            })
+        };
+
+        let batch: serde_json::Value = client
+            .post(format!("{proxy}/v1/messages/batches"))
+            .json(&serde_json::json!({"requests": [request("a"), request("b")]}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let id = batch["id"].as_str().unwrap().to_string();
+        assert_eq!(batch["processing_status"], "in_progress");
+
+        let mut batch = batch;
+        for _ in 0..100 {
+            if batch["processing_status"] == "ended" {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            batch = client
+                .get(format!("{proxy}/v1/messages/batches/{id}"))
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+        }
+        assert_eq!(batch["processing_status"], "ended");
+        assert_eq!(batch["request_counts"]["succeeded"], 2);
+
+        let results = client
+            .get(format!("{proxy}{}", batch["results_url"].as_str().unwrap()))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let mut custom_ids: Vec<String> = results
+            .lines()
+            .map(|line| {
+                let line: serde_json::Value = serde_json::from_str(line).unwrap();
+                assert_eq!(line["result"]["type"], "succeeded");
+                line["custom_id"].as_str().unwrap().to_string()
+            })
+            .collect();
+        custom_ids.sort();
+        assert_eq!(custom_ids, ["a", "b"]);
+        assert_eq!(received.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,663 @@
+//! Per-target health tracking and circuit breaking.
+//!
+//! A background task probes every distinct target URL in the current
+//! `ModelRegistry` on `health_check.interval_secs` and records the outcome
+//! here. Routing consults `is_down` to skip targets that keep failing when
+//! another endpoint or fallback hop is available. State is keyed by URL, so it
+//! survives config reloads; URLs that leave the registry are pruned.
+//!
+//! `CircuitBreakers` watches real traffic instead: primary and compare
+//! requests to a target share one breaker per URL, which fails requests fast
+//! while the target is known to be failing.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{CircuitBreakerConfig, HealthCheckConfig};
+use crate::models::TargetProtocol;
+use crate::reload::LiveConfig;
+
+/// How a target is probed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthProbe {
+    /// `GET {url}/health`, any 2xx is up.
+    #[default]
+    Health,
+    /// A one-token `POST {url}/v1/messages` (`/v1/chat/completions` for
+    /// `openai` targets) for a model the target serves.
+    Messages,
+    /// `GET {url}/v1/models`, any 2xx is up. Exercises the target's auth.
+    Models,
+}
+
+/// Health classification of a target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetStatus {
+    /// Not probed yet (or health checks disabled). Treated as up.
+    #[default]
+    Unknown,
+    Healthy,
+    /// Answering, but slowly or after recent failures.
+    Degraded,
+    /// `unhealthy_threshold` consecutive probes failed.
+    Down,
+}
+
+/// What is known about one target URL.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TargetState {
+    pub status: TargetStatus,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    pub last_latency_ms: Option<u64>,
+    /// Unix seconds of the last completed probe.
+    pub last_checked: Option<u64>,
+}
+
+/// Shared health state for all targets, keyed by base URL.
+#[derive(Debug, Default)]
+pub struct TargetHealth {
+    states: RwLock<HashMap<String, TargetState>>,
+}
+
+impl TargetHealth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether routing should avoid `url`.
+    pub fn is_down(&self, url: &str) -> bool {
+        self.states
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(url)
+            .is_some_and(|s| s.status == TargetStatus::Down)
+    }
+
+    /// Current state of `url` (default when never probed).
+    pub fn state(&self, url: &str) -> TargetState {
+        self.states
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(url)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Record a probe outcome and reclassify the target.
+    pub fn record_probe(
+        &self,
+        url: &str,
+        outcome: Result<Duration, String>,
+        settings: &HealthCheckConfig,
+    ) {
+        let mut states = self.states.write().unwrap_or_else(|e| e.into_inner());
+        let state = states.entry(url.to_string()).or_default();
+        state.last_checked = Some(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        );
+        let previous = state.status;
+        match outcome {
+            Ok(latency) => {
+                let latency_ms = latency.as_millis() as u64;
+                state.consecutive_failures = 0;
+                state.last_latency_ms = Some(latency_ms);
+                state.status = if latency_ms > settings.degraded_latency_ms {
+                    TargetStatus::Degraded
+                } else {
+                    TargetStatus::Healthy
+                };
+            }
+            Err(error) => {
+                state.consecutive_failures += 1;
+                state.last_error = Some(error);
+                state.status = if state.consecutive_failures >= settings.unhealthy_threshold {
+                    TargetStatus::Down
+                } else {
+                    TargetStatus::Degraded
+                };
+            }
+        }
+        if state.status != previous {
+            tracing::info!(
+                target_url = %url,
+                from = ?previous,
+                to = ?state.status,
+                last_error = ?state.last_error,
+                "Target health changed"
+            );
+        }
+    }
+
+    /// Drop state for URLs no longer in the registry.
+    pub fn retain(&self, keep: impl Fn(&str) -> bool) {
+        self.states
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|url, _| keep(url));
+    }
+}
+
+/// Probe every registered target on `health_check.interval_secs` until the
+/// process exits. Settings are re-read from the live config on every round.
+pub async fn run_health_checks(
+    live: LiveConfig,
+    health: std::sync::Arc<TargetHealth>,
+    client: reqwest::Client,
+) {
+    loop {
+        let snapshot = live.current();
+        let settings = snapshot.config.health_check.clone();
+        let Some(interval) = settings.interval_secs.filter(|s| *s > 0) else {
+            return;
+        };
+
+        let targets = snapshot.model_registry.targets();
+        health.retain(|url| targets.iter().any(|(u, _)| u == url));
+
+        let mut probes = tokio::task::JoinSet::new();
+        for (url, models) in targets {
+            let client = client.clone();
+            let settings = settings.clone();
+            let health = health.clone();
+            // Probes carry the credentials of the first model on this URL
+            // that has any.
+            let auth = models
+                .iter()
+                .filter_map(|id| snapshot.model_registry.get(id))
+                .find(|m| !m.auth.is_empty())
+                .map(|m| snapshot.model_registry.auth(&m.id))
+                .unwrap_or_else(|| Ok(Default::default()));
+            let protocol = snapshot
+                .model_registry
+                .get(&models[0])
+                .map(|m| m.protocol)
+                .unwrap_or_default();
+            probes.spawn(async move {
+                let outcome = match auth {
+                    Ok(auth) => probe(&client, &url, &models[0], protocol, auth, &settings).await,
+                    Err(e) => Err(format!("credentials: {e}")),
+                };
+                health.record_probe(&url, outcome, &settings);
+            });
+        }
+        while probes.join_next().await.is_some() {}
+
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+/// Run one probe against `url`. Returns the latency on success.
+async fn probe(
+    client: &reqwest::Client,
+    url: &str,
+    model: &str,
+    protocol: TargetProtocol,
+    auth: reqwest::header::HeaderMap,
+    settings: &HealthCheckConfig,
+) -> Result<Duration, String> {
+    let request = match settings.probe {
+        HealthProbe::Health => client.get(format!("{url}/health")),
+        HealthProbe::Models => client.get(format!("{url}/v1/models")),
+        HealthProbe::Messages => client
+            .post(format!("{url}{}", protocol.messages_path()))
+            .json(&serde_json::json!({
+                "model": model,
+                "max_tokens": 1,
+                "messages": [{"role": "user", "content": "ping"}],
+            })),
+    };
+
+    let start = Instant::now();
+    let resp = request
+        .headers(auth)
+        .timeout(Duration::from_secs(settings.timeout_secs))
+        .send()
+        .await
+        .map_err(|e| {
+            if e.is_timeout() {
+                "timeout".to_string()
+            } else {
+                format!("connect error: {e}")
+            }
+        })?;
+    let latency = start.elapsed();
+
+    if resp.status().is_success() {
+        Ok(latency)
+    } else {
+        Err(format!("status {}", resp.status().as_u16()))
+    }
+}
+
+/// State of a circuit breaker.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests flow normally.
+    #[default]
+    Closed,
+    /// Requests fail fast until the cooldown elapses.
+    Open,
+    /// Cooldown elapsed; one trial request decides whether to close again.
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    state: CircuitState,
+    consecutive_failures: u32,
+    /// Outcomes of the most recent requests (`true` = failure).
+    window: VecDeque<bool>,
+    opened_at: Option<Instant>,
+    /// When the half-open trial request was let through.
+    trial_started: Option<Instant>,
+    times_opened: u64,
+    rejected: u64,
+}
+
+/// Point-in-time view of one breaker, for the stats and targets APIs.
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakerSnapshot {
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    /// Failure rate over the recent-requests window.
+    pub error_rate: f64,
+    pub times_opened: u64,
+    /// Requests failed fast while open.
+    pub rejected: u64,
+}
+
+/// Per-target-URL circuit breakers. Settings are fixed at startup.
+#[derive(Debug)]
+pub struct CircuitBreakers {
+    settings: CircuitBreakerConfig,
+    breakers: Mutex<HashMap<String, Breaker>>,
+}
+
+impl CircuitBreakers {
+    pub fn new(settings: CircuitBreakerConfig) -> Self {
+        Self {
+            settings,
+            breakers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Ask to send a request to `url`. Returns the breaker state the request
+    /// goes out under, or `Err` with the remaining cooldown if it must fail
+    /// fast.
+    pub fn try_acquire(&self, url: &str) -> Result<CircuitState, Duration> {
+        if !self.settings.enabled {
+            return Ok(CircuitState::Closed);
+        }
+        let cooldown = Duration::from_secs(self.settings.cooldown_secs);
+        let mut breakers = self.breakers.lock().unwrap_or_else(|e| e.into_inner());
+        let breaker = breakers.entry(url.to_string()).or_default();
+        match breaker.state {
+            CircuitState::Closed => Ok(CircuitState::Closed),
+            CircuitState::Open => {
+                let elapsed = breaker.opened_at.map_or(cooldown, |t| t.elapsed());
+                if elapsed >= cooldown {
+                    transition(url, breaker, CircuitState::HalfOpen);
+                    breaker.trial_started = Some(Instant::now());
+                    Ok(CircuitState::HalfOpen)
+                } else {
+                    breaker.rejected += 1;
+                    Err(cooldown - elapsed)
+                }
+            }
+            CircuitState::HalfOpen => {
+                // One trial at a time. A trial that never reported back (e.g.
+                // the client went away) is given up on after another cooldown.
+                let stale = breaker
+                    .trial_started
+                    .is_none_or(|t| t.elapsed() >= cooldown);
+                if stale {
+                    breaker.trial_started = Some(Instant::now());
+                    Ok(CircuitState::HalfOpen)
+                } else {
+                    breaker.rejected += 1;
+                    Err(cooldown)
+                }
+            }
+        }
+    }
+
+    /// Report the outcome of a request that `try_acquire` let through.
+    /// Failures are connect errors, timeouts and 5xx responses.
+    pub fn record(&self, url: &str, success: bool) {
+        if !self.settings.enabled {
+            return;
+        }
+        let mut breakers = self.breakers.lock().unwrap_or_else(|e| e.into_inner());
+        let breaker = breakers.entry(url.to_string()).or_default();
+
+        breaker.window.push_back(!success);
+        while breaker.window.len() > self.settings.window_size.max(1) {
+            breaker.window.pop_front();
+        }
+        if success {
+            breaker.consecutive_failures = 0;
+        } else {
+            breaker.consecutive_failures += 1;
+        }
+
+        match breaker.state {
+            CircuitState::HalfOpen if success => {
+                breaker.window.clear();
+                transition(url, breaker, CircuitState::Closed);
+            }
+            CircuitState::HalfOpen => transition(url, breaker, CircuitState::Open),
+            CircuitState::Closed if !success && self.should_open(breaker) => {
+                transition(url, breaker, CircuitState::Open);
+            }
+            _ => {}
+        }
+    }
+
+    fn should_open(&self, breaker: &Breaker) -> bool {
+        if breaker.consecutive_failures >= self.settings.failure_threshold {
+            return true;
+        }
+        match self.settings.error_rate_threshold {
+            Some(threshold) if breaker.window.len() >= self.settings.min_requests => {
+                error_rate(breaker) >= threshold
+            }
+            _ => false,
+        }
+    }
+
+    /// Current state of the breaker for `url`.
+    pub fn state(&self, url: &str) -> CircuitState {
+        self.breakers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(url)
+            .map(|b| b.state)
+            .unwrap_or_default()
+    }
+
+    /// Every breaker that has seen traffic, by URL.
+    pub fn snapshot(&self) -> BTreeMap<String, BreakerSnapshot> {
+        self.breakers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(url, b)| {
+                (
+                    url.clone(),
+                    BreakerSnapshot {
+                        state: b.state,
+                        consecutive_failures: b.consecutive_failures,
+                        error_rate: error_rate(b),
+                        times_opened: b.times_opened,
+                        rejected: b.rejected,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+fn error_rate(breaker: &Breaker) -> f64 {
+    if breaker.window.is_empty() {
+        return 0.0;
+    }
+    let failures = breaker.window.iter().filter(|f| **f).count();
+    failures as f64 / breaker.window.len() as f64
+}
+
+/// Every transition into `Open`, including a failed half-open trial, restarts
+/// the cooldown and counts towards `times_opened`.
+fn transition(url: &str, breaker: &mut Breaker, to: CircuitState) {
+    let from = breaker.state;
+    breaker.state = to;
+    if to == CircuitState::Open {
+        breaker.opened_at = Some(Instant::now());
+        breaker.times_opened += 1;
+    }
+    tracing::warn!(
+        target_url = %url,
+        from = from.as_str(),
+        to = to.as_str(),
+        consecutive_failures = breaker.consecutive_failures,
+        "Circuit breaker state changed"
+    );
+}
+
+/// Anthropic-format `overloaded_error` returned while a target's circuit is
+/// open.
+pub fn overloaded_response(retry_after: Duration) -> axum::response::Response {
+    use axum::response::IntoResponse;
+
+    let body = serde_json::json!({
+        "type": "error",
+        "error": {
+            "type": "overloaded_error",
+            "message": "Target is failing and temporarily unavailable (circuit open)",
+        },
+    });
+    let status = axum::http::StatusCode::from_u16(529)
+        .unwrap_or(axum::http::StatusCode::SERVICE_UNAVAILABLE);
+    (
+        status,
+        [("retry-after", retry_after.as_secs().max(1).to_string())],
+        axum::Json(body),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> HealthCheckConfig {
+        HealthCheckConfig {
+            unhealthy_threshold: 2,
+            degraded_latency_ms: 100,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn failures_degrade_then_mark_down() {
+        let health = TargetHealth::new();
+        let url = "http://t:8000";
+        assert_eq!(health.state(url).status, TargetStatus::Unknown);
+
+        health.record_probe(url, Err("status 503".into()), &settings());
+        assert_eq!(health.state(url).status, TargetStatus::Degraded);
+        assert!(!health.is_down(url));
+
+        health.record_probe(url, Err("timeout".into()), &settings());
+        assert!(health.is_down(url));
+        assert_eq!(health.state(url).last_error.as_deref(), Some("timeout"));
+
+        health.record_probe(url, Ok(Duration::from_millis(5)), &settings());
+        let state = health.state(url);
+        assert_eq!(state.status, TargetStatus::Healthy);
+        assert_eq!(state.consecutive_failures, 0);
+    }
+
+    #[test]
+    fn slow_probe_is_degraded() {
+        let health = TargetHealth::new();
+        health.record_probe("http://t", Ok(Duration::from_millis(500)), &settings());
+        assert_eq!(health.state("http://t").status, TargetStatus::Degraded);
+    }
+
+    #[tokio::test]
+    async fn probes_health_endpoint() {
+        let app = axum::Router::new().route("/health", axum::routing::get(|| async { "ok" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        assert!(probe(
+            &client,
+            &url,
+            "m",
+            TargetProtocol::Anthropic,
+            Default::default(),
+            &settings()
+        )
+        .await
+        .is_ok());
+
+        let messages = HealthCheckConfig {
+            probe: HealthProbe::Messages,
+            ..settings()
+        };
+        // No /v1/messages route on the mock → 404
+        assert_eq!(
+            probe(
+                &client,
+                &url,
+                "m",
+                TargetProtocol::Anthropic,
+                Default::default(),
+                &messages
+            )
+            .await
+            .unwrap_err(),
+            "status 404"
+        );
+    }
+
+    #[tokio::test]
+    async fn models_probe_sends_credentials() {
+        let app = axum::Router::new().route(
+            "/v1/models",
+            axum::routing::get(|headers: axum::http::HeaderMap| async move {
+                if headers.get("x-api-key").is_some_and(|v| v == "secret") {
+                    axum::http::StatusCode::OK
+                } else {
+                    axum::http::StatusCode::UNAUTHORIZED
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let models = HealthCheckConfig {
+            probe: HealthProbe::Models,
+            ..settings()
+        };
+        assert_eq!(
+            probe(
+                &client,
+                &url,
+                "m",
+                TargetProtocol::Anthropic,
+                Default::default(),
+                &models
+            )
+            .await
+            .unwrap_err(),
+            "status 401"
+        );
+        let mut auth = reqwest::header::HeaderMap::new();
+        auth.insert("x-api-key", "secret".parse().unwrap());
+        assert!(
+            probe(&client, &url, "m", TargetProtocol::Anthropic, auth, &models)
+                .await
+                .is_ok()
+        );
+    }
+
+    fn breakers(failure_threshold: u32) -> CircuitBreakers {
+        CircuitBreakers::new(CircuitBreakerConfig {
+            failure_threshold,
+            cooldown_secs: 0,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn breaker_opens_after_consecutive_failures() {
+        let cb = CircuitBreakers::new(CircuitBreakerConfig {
+            failure_threshold: 2,
+            cooldown_secs: 60,
+            ..Default::default()
+        });
+        let url = "http://t:8000";
+        cb.record(url, false);
+        assert_eq!(cb.state(url), CircuitState::Closed);
+        cb.record(url, false);
+        assert_eq!(cb.state(url), CircuitState::Open);
+        assert!(cb.try_acquire(url).is_err());
+
+        let snap = &cb.snapshot()[url];
+        assert_eq!((snap.times_opened, snap.rejected), (1, 1));
+    }
+
+    #[test]
+    fn breaker_half_opens_after_cooldown() {
+        let cb = breakers(1);
+        let url = "http://t:8000";
+        cb.record(url, false);
+        assert_eq!(cb.state(url), CircuitState::Open);
+
+        // Zero cooldown: the next request is the half-open trial, and only one
+        // trial runs at a time.
+        assert_eq!(cb.try_acquire(url), Ok(CircuitState::HalfOpen));
+        cb.record(url, false);
+        assert_eq!(cb.state(url), CircuitState::Open);
+        assert_eq!(cb.snapshot()[url].times_opened, 2);
+
+        assert_eq!(cb.try_acquire(url), Ok(CircuitState::HalfOpen));
+        cb.record(url, true);
+        assert_eq!(cb.state(url), CircuitState::Closed);
+    }
+
+    #[test]
+    fn breaker_opens_on_error_rate() {
+        let cb = CircuitBreakers::new(CircuitBreakerConfig {
+            failure_threshold: 100,
+            error_rate_threshold: Some(0.5),
+            min_requests: 4,
+            window_size: 4,
+            cooldown_secs: 60,
+            ..Default::default()
+        });
+        let url = "http://t:8000";
+        for ok in [true, false, true] {
+            cb.record(url, ok);
+        }
+        assert_eq!(cb.state(url), CircuitState::Closed);
+        cb.record(url, false);
+        assert_eq!(cb.state(url), CircuitState::Open);
+    }
+
+    #[test]
+    fn disabled_breaker_never_opens() {
+        let cb = CircuitBreakers::new(CircuitBreakerConfig {
+            enabled: false,
+            failure_threshold: 1,
+            ..Default::default()
+        });
+        cb.record("http://t", false);
+        assert_eq!(cb.try_acquire("http://t"), Ok(CircuitState::Closed));
+    }
+}
@@ -0,0 +1,184 @@
+//! Input-size estimation and counting.
+//!
+//! A character-count heuristic (about four characters per token) over the
+//! parts of a Messages request that reach the model: system prompt, message
+//! content and tool definitions. Image and document payloads are skipped.
+//! Good enough for routing decisions; not a substitute for a real tokenizer.
+//!
+//! `/v1/messages/count_tokens` uses a model's HuggingFace tokenizer over the
+//! same text when one is configured (see [`TokenizerCache`]).
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+use tokenizers::Tokenizer;
+use tokio::sync::OnceCell;
+
+/// Average characters per token assumed by the estimator.
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// Estimate the input tokens of an Anthropic Messages request body.
+pub fn estimate_input_tokens(req: &Value) -> u64 {
+    let chars: usize = ["system", "messages", "tools"]
+        .iter()
+        .filter_map(|key| req.get(*key))
+        .map(count_chars)
+        .sum();
+    chars.div_ceil(CHARS_PER_TOKEN) as u64
+}
+
+/// Sum the characters of every string in `value`, skipping binary sources.
+fn count_chars(value: &Value) -> usize {
+    match value {
+        Value::String(s) => s.chars().count(),
+        Value::Array(items) => items.iter().map(count_chars).sum(),
+        Value::Object(map) => map
+            .iter()
+            .filter(|(key, _)| key.as_str() != "source")
+            .map(|(_, v)| count_chars(v))
+            .sum(),
+        _ => 0,
+    }
+}
+
+/// The text `estimate_input_tokens` measures, one string per line.
+pub fn input_text(req: &Value) -> String {
+    let mut parts = Vec::new();
+    for key in ["system", "messages", "tools"] {
+        if let Some(value) = req.get(key) {
+            collect_strings(value, &mut parts);
+        }
+    }
+    parts.join("\n")
+}
+
+fn collect_strings<'a>(value: &'a Value, out: &mut Vec<&'a str>) {
+    match value {
+        Value::String(s) => out.push(s),
+        Value::Array(items) => items.iter().for_each(|v| collect_strings(v, out)),
+        Value::Object(map) => map
+            .iter()
+            .filter(|(key, _)| key.as_str() != "source")
+            .for_each(|(_, v)| collect_strings(v, out)),
+        _ => {}
+    }
+}
+
+type Loaded = Arc<OnceCell<Arc<Tokenizer>>>;
+
+/// HuggingFace tokenizers loaded from `tokenizer.json` files, keyed by path.
+/// Each file is read once, by the first request that needs it. A failed
+/// load is not kept, so a file that appears or is fixed later gets used.
+#[derive(Default)]
+pub struct TokenizerCache {
+    loaded: Mutex<HashMap<String, Loaded>>,
+}
+
+impl TokenizerCache {
+    /// Count the input tokens of a Messages request with the tokenizer at
+    /// `path`. Loading and encoding run on the blocking pool: large
+    /// vocabularies take a while to parse and long prompts to encode.
+    pub async fn count(&self, path: &str, req: &Value) -> Result<u64, String> {
+        let tokenizer = self.get(path).await?;
+        let text = input_text(req);
+        let path = path.to_string();
+        tokio::task::spawn_blocking(move || {
+            tokenizer
+                .encode(text, false)
+                .map(|encoding| encoding.len() as u64)
+                .map_err(|e| format!("tokenizing with {path}: {e}"))
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    }
+
+    async fn get(&self, path: &str) -> Result<Arc<Tokenizer>, String> {
+        let cell = self
+            .loaded
+            .lock()
+            .unwrap()
+            .entry(path.to_string())
+            .or_default()
+            .clone();
+        cell.get_or_try_init(|| {
+            let path = path.to_string();
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    Tokenizer::from_file(&path)
+                        .map(Arc::new)
+                        .map_err(|e| format!("loading {path}: {e}"))
+                })
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()))
+            }
+        })
+        .await
+        .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn counts_system_messages_and_tools() {
+        let req = json!({
+            "model": "ignored-model-name",
+            "system": "abcd",
+            "messages": [
+                {"role": "user", "content": "abcdefgh"},
+                {"role": "assistant", "content": [{"type": "text", "text": "abcd"}]}
+            ]
+        });
+        // "abcd" + "user" + "abcdefgh" + "assistant" + "text" + "abcd" = 33 chars
+        assert_eq!(estimate_input_tokens(&req), 9);
+    }
+
+    #[test]
+    fn skips_image_sources() {
+        let req = json!({
+            "messages": [{"role": "user", "content": [{
+                "type": "image",
+                "source": {"type": "base64", "media_type": "image/png", "data": "A".repeat(10_000)}
+            }]}]
+        });
+        assert!(estimate_input_tokens(&req) < 10);
+    }
+
+    #[tokio::test]
+    async fn tokenizer_counts_input_text() {
+        let path =
+            std::env::temp_dir().join(format!("cc-proxy-tokenizer-{}.json", uuid::Uuid::new_v4()));
+        let tokenizer = json!({
+            "version": "1.0",
+            "truncation": null,
+            "padding": null,
+            "added_tokens": [],
+            "normalizer": null,
+            "pre_tokenizer": {"type": "Whitespace"},
+            "post_processor": null,
+            "decoder": null,
+            "model": {"type": "WordLevel", "vocab": {"[UNK]": 0, "hello": 1}, "unk_token": "[UNK]"}
+        });
+        std::fs::write(&path, tokenizer.to_string()).unwrap();
+        let path = path.to_str().unwrap();
+
+        let req = json!({
+            "system": "hello world",
+            "messages": [{"role": "user", "content": "hello there again"}]
+        });
+        let cache = TokenizerCache::default();
+        let missing = format!("{path}.missing");
+        assert!(cache.count(&missing, &req).await.is_err());
+        // "hello world" + "user" + "hello there again", one token per word
+        assert_eq!(cache.count(path, &req).await, Ok(6));
+        std::fs::rename(path, &missing).unwrap();
+        // Served from the cache once loaded; a failed load is retried
+        assert_eq!(cache.count(path, &req).await, Ok(6));
+        assert_eq!(cache.count(&missing, &req).await, Ok(6));
+        std::fs::remove_file(missing).unwrap();
+    }
+}
@@ -10,6 +10,16 @@
 ///
 /// Upstream identity:
 /// - `anthropic_request_id`: `x-request-id` from the upstream response headers
+/// - `resolved_model`: model ID after exact/alias resolution (`original_model` is as sent)
+/// - `matched_alias`: pattern of the `[[aliases]]` rule that matched, if any
+/// - `matched_route`: name of the `[[routes]]` rule that matched, if any
+/// - `served_by`: upstream that produced the response (`local:<model>` or `anthropic`)
+/// - `fallback_depth`: 0 when the first choice served it, N for the Nth fallback hop
+/// - `context_overflow`: `rejected` or `rerouted:<target>` when the request
+///   overflowed the local model's context window
+/// - `batch_id`: the Message Batch a request belongs to, for batch requests
+/// - `compare_decision`: in compare mode, whether the request was mirrored
+///   (`sampled`) or why not (`skipped:<reason>`)
 #[macro_export]
 macro_rules! proxy_request_span {
     ($correlation_id:expr, $model:expr) => {
@@ -20,11 +30,25 @@ macro_rules! proxy_request_span {
             ttft_ms = tracing::field::Empty,
             total_duration_ms = tracing::field::Empty,
             anthropic_request_id = tracing::field::Empty,
+            resolved_model = tracing::field::Empty,
+            matched_alias = tracing::field::Empty,
+            matched_route = tracing::field::Empty,
+            served_by = tracing::field::Empty,
+            fallback_depth = tracing::field::Empty,
+            context_overflow = tracing::field::Empty,
+            batch_id = tracing::field::Empty,
+            compare_decision = tracing::field::Empty,
         )
     };
 }
 
 /// Create a tracing span for the primary Anthropic forward.
+///
+/// Load-balancing fields recorded by `forward_to_target` for local models:
+/// - `endpoint`: index of the chosen replica endpoint (`i/n`)
+/// - `lb_strategy`: strategy used to pick it
+/// - `endpoint_in_flight`: in-flight requests on that endpoint at pick time
+/// - `circuit_state`: the target's circuit breaker state (`closed`, `half_open`, `open`)
 #[macro_export]
 macro_rules! primary_forward_span {
     ($correlation_id:expr, $target:expr) => {
@@ -34,6 +58,29 @@ macro_rules! primary_forward_span {
             target = %$target,
             status = tracing::field::Empty,
             latency_ms = tracing::field::Empty,
+            endpoint = tracing::field::Empty,
+            lb_strategy = tracing::field::Empty,
+            endpoint_in_flight = tracing::field::Empty,
+            circuit_state = tracing::field::Empty,
+        )
+    };
+}
+
+/// Create a tracing span for one attempt at an upstream request. Child of
+/// `primary_forward`; there is more than one when 429/529 responses are
+/// retried.
+///
+/// - `status`: upstream status (0 for connect error / timeout)
+/// - `backoff_ms`: delay before the next attempt, when one follows
+#[macro_export]
+macro_rules! upstream_attempt_span {
+    ($attempt:expr) => {
+        tracing::info_span!(
+            "upstream_attempt",
+            attempt = $attempt,
+            status = tracing::field::Empty,
+            latency_ms = tracing::field::Empty,
+            backoff_ms = tracing::field::Empty,
         )
     };
 }